@@ -7,3 +7,7 @@ cargo build
 cargo fmt
 cargo clippy $fix $allow_dirty --all-targets --all-features -- 	-Dclippy::perf 	-Dclippy::style 	-Wclippy::pedantic 	-Aclippy::unnested_or_patterns 	-Aclippy::wildcard_imports 	-Aclippy::enum_glob_use 	-Aclippy::too_many_lines 	-Aclippy::match_same_arms 	-Aclippy::unnecessary_wraps 	-Aclippy::missing_errors_doc
 cargo test
+# The bytecode engine has to agree with the tree walking interpreter, output, errors and all
+for script in hello.ls fib.ls ; do
+	diff <(./target/debug/lyss run --engine tree $script 2>&1 ; echo "exit $?") <(./target/debug/lyss run --engine bytecode $script 2>&1 ; echo "exit $?")
+done
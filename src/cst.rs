@@ -0,0 +1,298 @@
+//! A lossless syntax tree, keeping the comments and whitespace the parser throws away, for tools
+//! that rewrite source like formatters
+//!
+//! Every byte of the source is either in a token or in the [`Trivia`] around one, so printing a
+//! [`Cst`] gives back exactly the source it was read from.
+
+use std::path::Path;
+
+use crate::LyssCompError;
+use crate::span::{FileId, Span};
+use crate::tokenizer::{self, Token, TokenCont};
+
+/// Source between tokens
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Trivia {
+    Whitespace(String),
+    /// A `#` comment, without the newline ending it
+    Comment(String),
+}
+
+impl Trivia {
+    #[must_use]
+    pub fn text(&self) -> &str {
+        match self {
+            Trivia::Whitespace(text) | Trivia::Comment(text) => text,
+        }
+    }
+    #[must_use]
+    pub fn newlines(&self) -> usize {
+        self.text().matches('\n').count()
+    }
+}
+
+/// A token with its source text and the trivia around it. The trivia after a token up to the
+/// end of its line is its `trailing`, the rest is the `leading` of the next token
+#[derive(Debug, Clone)]
+pub struct CstToken {
+    pub leading: Vec<Trivia>,
+    pub token: Token,
+    pub text: String,
+    pub trailing: Vec<Trivia>,
+}
+
+impl CstToken {
+    /// The comment at the end of the token's line, if any
+    #[must_use]
+    pub fn trailing_comment(&self) -> Option<&str> {
+        self.trailing.iter().find_map(|trivia| match trivia {
+            Trivia::Comment(text) => Some(text.as_str()),
+            Trivia::Whitespace(_) => None,
+        })
+    }
+}
+
+/// `(` followed by items and `)`, or a `[ .. ]` list literal
+#[derive(Debug, Clone)]
+pub struct CstList {
+    pub open: Box<CstToken>,
+    pub items: Vec<CstNode>,
+    /// `None` when the source ended before the list was closed
+    pub close: Option<Box<CstToken>>,
+}
+
+#[derive(Debug, Clone)]
+pub enum CstNode {
+    /// A token that isn't part of a list or block, or a stray one
+    Token(CstToken),
+    List(CstList),
+    /// A `'( ... )'` code block or a `` `( ... )` `` template
+    Quoted {
+        open: Box<CstToken>,
+        list: CstList,
+        /// `None` when the closing quote is missing
+        close: Option<Box<CstToken>>,
+    },
+}
+
+impl CstNode {
+    /// The first token of the node, holding the trivia before it
+    #[must_use]
+    pub fn first_token(&self) -> &CstToken {
+        match self {
+            CstNode::Token(token) => token,
+            CstNode::Quoted { open, .. } => open,
+            CstNode::List(list) => &list.open,
+        }
+    }
+    #[must_use]
+    pub fn span(&self) -> Span {
+        let start = self.first_token().token.span;
+        let last = match self {
+            CstNode::Token(token) => Some(token),
+            CstNode::List(list) => list.close.as_deref(),
+            CstNode::Quoted { close, list, .. } => close.as_deref().or(list.close.as_deref()),
+        };
+        last.map_or(start, |token| start.to(token.token.span))
+    }
+}
+
+/// A whole file, printing it gives back the source
+#[derive(Debug, Clone)]
+pub struct Cst {
+    pub nodes: Vec<CstNode>,
+    /// Trivia after the last token
+    pub trailing: Vec<Trivia>,
+}
+
+/// The tokens of a file with their trivia, the trivia after the last token and the errors of
+/// the tokenizer
+type Tokenized = (Vec<CstToken>, Vec<Trivia>, Vec<LyssCompError>);
+
+/// Tokenizes `content` keeping the trivia, source that can't be tokenized is kept in error
+/// tokens
+#[must_use]
+pub fn tokenize(content: &str, file_name: &Path, file_id: FileId) -> Tokenized {
+    let (tokens, errors) = tokenizer::tokenize(content, file_name, file_id);
+    let mut cst_tokens: Vec<CstToken> = Vec::with_capacity(tokens.len());
+    let mut gap_start = 0;
+    for token in tokens {
+        let gap = &content[gap_start..token.span.start_byte];
+        let leading = match cst_tokens.last_mut() {
+            Some(previous) => {
+                let (trailing, leading) = split_line(gap);
+                previous.trailing = trailing;
+                leading
+            }
+            None => split_trivia(gap),
+        };
+        gap_start = token.span.end_byte;
+        cst_tokens.push(CstToken {
+            leading,
+            text: content[token.span.start_byte..token.span.end_byte].to_owned(),
+            token,
+            trailing: vec![],
+        });
+    }
+    let gap = &content[gap_start..];
+    let trailing = match cst_tokens.last_mut() {
+        Some(last) => {
+            let (trailing, rest) = split_line(gap);
+            last.trailing = trailing;
+            rest
+        }
+        None => split_trivia(gap),
+    };
+    (cst_tokens, trailing, errors)
+}
+
+/// Reads `content` into a lossless tree, unbalanced parens and quotes are kept as they are and
+/// so is source that can't be tokenized, along with the errors of the tokenizer
+#[must_use]
+pub fn parse(content: &str, file_name: &Path, file_id: FileId) -> (Cst, Vec<LyssCompError>) {
+    let (tokens, trailing, errors) = tokenize(content, file_name, file_id);
+    let mut tokens = tokens.into_iter().peekable();
+    let mut nodes = vec![];
+    while let Some(token) = tokens.next() {
+        nodes.push(parse_node(token, &mut tokens));
+    }
+    (Cst { nodes, trailing }, errors)
+}
+
+type CstTokens = std::iter::Peekable<std::vec::IntoIter<CstToken>>;
+
+fn parse_node(token: CstToken, tokens: &mut CstTokens) -> CstNode {
+    let single = match token.token.content {
+        TokenCont::OParam | TokenCont::OBracket => return CstNode::List(parse_list(token, tokens)),
+        TokenCont::SingleQuote => true,
+        TokenCont::MacroQuote => false,
+        _ => return CstNode::Token(token),
+    };
+    let Some(open) = tokens.next_if(|next| matches!(next.token.content, TokenCont::OParam)) else {
+        return CstNode::Token(token);
+    };
+    let list = parse_list(open, tokens);
+    let close = tokens.next_if(|next| match next.token.content {
+        TokenCont::SingleQuote => single,
+        TokenCont::MacroQuote => !single,
+        _ => false,
+    });
+    CstNode::Quoted {
+        open: Box::new(token),
+        list,
+        close: close.map(Box::new),
+    }
+}
+
+fn parse_list(open: CstToken, tokens: &mut CstTokens) -> CstList {
+    let bracket = matches!(open.token.content, TokenCont::OBracket);
+    let mut items = vec![];
+    let close = loop {
+        match tokens.next() {
+            Some(token)
+                if matches!(
+                    (&token.token.content, bracket),
+                    (TokenCont::CParam, false) | (TokenCont::CBracket, true)
+                ) =>
+            {
+                break Some(Box::new(token));
+            }
+            Some(token) => items.push(parse_node(token, tokens)),
+            None => break None,
+        }
+    };
+    CstList {
+        open: Box::new(open),
+        items,
+        close,
+    }
+}
+
+/// Splits a gap between tokens at the end of the first line, comments never hold a newline
+fn split_line(gap: &str) -> (Vec<Trivia>, Vec<Trivia>) {
+    let line_end = gap.find('\n').unwrap_or(gap.len());
+    (
+        split_trivia(&gap[..line_end]),
+        split_trivia(&gap[line_end..]),
+    )
+}
+
+fn split_trivia(mut gap: &str) -> Vec<Trivia> {
+    let mut trivia = vec![];
+    while !gap.is_empty() {
+        if gap.starts_with('#') {
+            let end = gap.find('\n').unwrap_or(gap.len());
+            trivia.push(Trivia::Comment(gap[..end].to_owned()));
+            gap = &gap[end..];
+        } else {
+            let end = gap.find('#').unwrap_or(gap.len());
+            trivia.push(Trivia::Whitespace(gap[..end].to_owned()));
+            gap = &gap[end..];
+        }
+    }
+    trivia
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(source: &str) {
+        let (cst, _) = parse(source, Path::new("test.ls"), 0);
+        assert_eq!(cst.to_string(), source);
+    }
+
+    #[test]
+    fn examples_round_trip() {
+        round_trip(include_str!("../hello.ls"));
+        round_trip(include_str!("../fib.ls"));
+        round_trip(include_str!("../macro.ls"));
+    }
+
+    #[test]
+    fn edge_cases_round_trip() {
+        for source in [
+            "",
+            "\n\n",
+            "# only a comment",
+            "(print 1 ) # trailing\n# leading\n(print 2 )",
+            "(a\t(b  [1 2 ]  ) )\r\n",
+            "!(twice (print \"x\" ) )   !(empty)",
+            "(set s \"\"\"\n    multi\n      line\n    \"\"\" ) # after",
+            "'( (a ) )' `( ($code ) )`",
+            "(unclosed (a ",
+            "'( missing quote )",
+            ") stray ]",
+            "(print \"bad \\q escape\" ) (print 1 )",
+            "(a ~@ b ) (c )",
+            "(größe \"名前\" )",
+        ] {
+            round_trip(source);
+        }
+    }
+
+    #[test]
+    fn comments_trail_the_token_on_their_line() {
+        let (tokens, trailing, _) =
+            tokenize("(a ) # one\n# two\n(b ) # three", Path::new("test.ls"), 0);
+        assert_eq!(tokens[2].trailing_comment(), Some("# one"));
+        assert_eq!(
+            tokens[3].leading,
+            [
+                Trivia::Whitespace("\n".to_owned()),
+                Trivia::Comment("# two".to_owned()),
+                Trivia::Whitespace("\n".to_owned()),
+            ]
+        );
+        assert_eq!(tokens[5].trailing_comment(), Some("# three"));
+        assert!(trailing.is_empty());
+    }
+
+    #[test]
+    fn untokenizable_source_is_kept_with_the_errors() {
+        let source = "(print \"bad \\q escape\" ) (print 1 )";
+        let (cst, errors) = parse(source, Path::new("test.ls"), 0);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(cst.nodes.len(), 2);
+    }
+}
@@ -0,0 +1,391 @@
+//! Turns errors into reports for humans, with the source they point at underlined:
+//!
+//! ```text
+//! error[E0100]: nothing is named `Builtin.prnt`
+//!  --> hello.ls:3:2
+//!   |
+//! 3 | (Builtin.prnt "hi" )
+//!   |  ^^^^^^^^^^^^
+//!   |
+//!   = help: did you mean `Builtin.print`?
+//! ```
+
+use std::fmt::Write;
+
+use crate::parser::ArgumentCont;
+use crate::runtime::Context;
+use crate::span::{SourceMap, Span};
+use crate::{LyssCompError, LyssRuntimeError};
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub code: &'static str,
+    pub message: String,
+    pub span: Option<Span>,
+    pub notes: Vec<String>,
+    pub help: Option<String>,
+}
+
+/// Spans of nodes made by the host don't point anywhere
+fn known(span: Span) -> Option<Span> {
+    (span != Span::default()).then_some(span)
+}
+
+impl LyssCompError {
+    #[must_use]
+    pub fn code(&self) -> &'static str {
+        match self {
+            LyssCompError::CodeWithoutRootAtom { .. } => "E0001",
+            LyssCompError::ParseFloat { .. } => "E0002",
+            LyssCompError::CantStopToken { .. } => "E0003",
+            LyssCompError::UnexpectedChar { .. } => "E0004",
+            LyssCompError::UnterminatedString { .. } => "E0005",
+            LyssCompError::UnexpectedToken { .. } => "E0006",
+            LyssCompError::UnexpectedEof { .. } => "E0007",
+            LyssCompError::InvalidEscape { .. } => "E0008",
+            LyssCompError::ParseInt { .. } => "E0009",
+        }
+    }
+    #[must_use]
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            LyssCompError::CodeWithoutRootAtom { first_token } => {
+                first_token.as_ref().and_then(|token| known(token.span))
+            }
+            LyssCompError::ParseFloat { span, .. }
+            | LyssCompError::ParseInt { span, .. }
+            | LyssCompError::CantStopToken { span, .. }
+            | LyssCompError::UnexpectedChar { span, .. }
+            | LyssCompError::UnterminatedString { span }
+            | LyssCompError::UnexpectedEof { span, .. }
+            | LyssCompError::InvalidEscape { span, .. } => known(*span),
+            LyssCompError::UnexpectedToken { found, .. } => known(found.span),
+        }
+    }
+}
+
+impl LyssRuntimeError {
+    #[must_use]
+    pub fn code(&self) -> &'static str {
+        match self {
+            LyssRuntimeError::EntryNotFound { .. } => "E0100",
+            LyssRuntimeError::EntryWasLeaf { .. } => "E0101",
+            LyssRuntimeError::EntryWasBranch { .. } => "E0102",
+            LyssRuntimeError::VarNotFound { .. } => "E0103",
+            LyssRuntimeError::LiteralNotFound { .. } => "E0104",
+            LyssRuntimeError::UnexpectedArg { .. } => "E0105",
+            LyssRuntimeError::NeedsArg => "E0106",
+            LyssRuntimeError::TooManyArgs { .. } => "E0107",
+            LyssRuntimeError::TooFewArgs { .. } => "E0108",
+            LyssRuntimeError::UnmatchedArgCount { .. } => "E0109",
+            LyssRuntimeError::MacroNotFound { .. } => "E0110",
+            LyssRuntimeError::MacroContent { .. } => "E0111",
+            LyssRuntimeError::MacroDepthLimit { .. } => "E0112",
+            LyssRuntimeError::UnfilledHole { .. } => "E0113",
+            LyssRuntimeError::ReentrantHostFunc => "E0114",
+            LyssRuntimeError::WrongType { .. } => "E0115",
+            LyssRuntimeError::Return { .. } => "E0116",
+            LyssRuntimeError::MacroMismatch { .. } => "E0117",
+            LyssRuntimeError::SyntaxError => "E0118",
+            LyssRuntimeError::Exit { .. } => "E0119",
+            LyssRuntimeError::EmptyExpansion { .. } => "E0120",
+            LyssRuntimeError::CallDepthLimit => "E0121",
+            LyssRuntimeError::Located { error, .. } => error.code(),
+        }
+    }
+    /// The most precise location of the error, the argument it complains about if it has one,
+    /// otherwise where it was raised
+    #[must_use]
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            LyssRuntimeError::LiteralNotFound { got: arg, .. }
+            | LyssRuntimeError::UnexpectedArg { arg, .. } => known(arg.span),
+            LyssRuntimeError::MacroContent { error, .. } => error.span(),
+            LyssRuntimeError::MacroMismatch { got, .. } => {
+                got.as_ref().and_then(|token| known(token.span))
+            }
+            LyssRuntimeError::Located { span, error } => error.span().or(known(*span)),
+            _ => None,
+        }
+    }
+}
+
+impl From<&LyssCompError> for Diagnostic {
+    fn from(error: &LyssCompError) -> Self {
+        Diagnostic {
+            code: error.code(),
+            message: error.to_string(),
+            span: error.span(),
+            notes: vec![],
+            help: None,
+        }
+    }
+}
+
+impl Diagnostic {
+    /// Describes a runtime error, `ctx` is where it happened and is searched for names close to
+    /// the ones that couldn't be found
+    #[must_use]
+    pub fn from_runtime(error: &LyssRuntimeError, ctx: &Context) -> Diagnostic {
+        let help = match error.unlocated() {
+            LyssRuntimeError::EntryNotFound { path } => {
+                suggest(&path.join("."), visible_paths(ctx)).map(|name| did_you_mean(&name))
+            }
+            LyssRuntimeError::VarNotFound { name } => suggest(name, visible_vars(ctx))
+                .map(|name| did_you_mean(&format!("$.{name}")))
+                .or_else(|| Some(format!("define it first, `(Builtin.local $.{name} ...)`"))),
+            LyssRuntimeError::MacroNotFound { name } => suggest(name, visible_macros(ctx))
+                .map(|name| did_you_mean(&name))
+                .or_else(|| Some("macros are defined with `Builtin.Macro.def`".to_owned())),
+            LyssRuntimeError::UnexpectedArg { arg, .. } => match &arg.cont {
+                ArgumentCont::Ident(path) if path.0.len() == 1 => Some(format!(
+                    "to pass a variable write `$.{path}`, bare names are functions"
+                )),
+                _ => None,
+            },
+            LyssRuntimeError::MacroDepthLimit { .. } => {
+                Some("a macro is probably expanding into itself".to_owned())
+            }
+            LyssRuntimeError::CallDepthLimit => {
+                Some("a function is probably calling itself without ever returning".to_owned())
+            }
+            LyssRuntimeError::EmptyExpansion { .. } => Some(
+                "only macros expanding to at least one expression can be used as values".to_owned(),
+            ),
+            LyssRuntimeError::UnfilledHole { .. } => {
+                Some("holes like `_.name` and `$name` only work inside templates".to_owned())
+            }
+            LyssRuntimeError::Return { .. } => {
+                Some("`return` only works inside functions made with `Builtin.defn`".to_owned())
+            }
+            _ => None,
+        };
+        Diagnostic {
+            code: error.code(),
+            message: error.to_string(),
+            span: error.span(),
+            notes: vec![],
+            help,
+        }
+    }
+    /// Renders the diagnostic, with the source it points at if its file is in `sources`
+    #[must_use]
+    pub fn render(&self, sources: &SourceMap) -> String {
+        let mut out = format!("error[{}]: {}\n", self.code, self.message);
+        let source = self
+            .span
+            .and_then(|span| Some((span, sources.get(span.file_id)?)));
+        let gutter = match source {
+            Some((span, file)) => {
+                let gutter = " ".repeat(span.line.to_string().len());
+                let (text, start, len) = excerpt(&file.content, span);
+                let _ = writeln!(
+                    out,
+                    "{gutter}--> {}:{}:{}",
+                    file.path.display(),
+                    span.line,
+                    span.column
+                );
+                let _ = writeln!(out, "{gutter} |");
+                let _ = writeln!(out, "{} | {text}", span.line);
+                let _ = writeln!(
+                    out,
+                    "{gutter} | {}{}",
+                    " ".repeat(start),
+                    "^".repeat(len.max(1))
+                );
+                gutter
+            }
+            None => String::new(),
+        };
+        if !self.notes.is_empty() || self.help.is_some() {
+            let _ = writeln!(out, "{gutter} |");
+        }
+        for note in &self.notes {
+            let _ = writeln!(out, "{gutter} = note: {note}");
+        }
+        if let Some(help) = &self.help {
+            let _ = writeln!(out, "{gutter} = help: {help}");
+        }
+        out
+    }
+}
+
+const TAB: &str = "    ";
+
+/// The line `span` starts on with tabs expanded, and where the underline starts and how long
+/// it is, in chars. Spans going over many lines are underlined up to the end of the first
+fn excerpt(content: &str, span: Span) -> (String, usize, usize) {
+    let start = span.start_byte.min(content.len());
+    let end = span.end_byte.clamp(start, content.len());
+    let line_start = content[..start].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = content[start..]
+        .find('\n')
+        .map_or(content.len(), |i| start + i);
+    let width = |text: &str| {
+        text.chars()
+            .map(|c| if c == '\t' { TAB.len() } else { 1 })
+            .sum()
+    };
+    (
+        content[line_start..line_end].replace('\t', TAB),
+        width(&content[line_start..start]),
+        width(&content[start..end.min(line_end)]),
+    )
+}
+
+fn did_you_mean(name: &str) -> String {
+    format!("did you mean `{name}`?")
+}
+
+/// The candidate closest to `typed`, if any is close enough to be a typo
+fn suggest(typed: &str, candidates: Vec<String>) -> Option<String> {
+    let last = |path: &str| path.rsplit('.').next().unwrap_or(path).to_owned();
+    let typed_last = last(typed);
+    let limit = (typed.chars().count() / 3).max(1);
+    candidates
+        .into_iter()
+        .filter(|candidate| candidate != typed)
+        .map(|candidate| {
+            // A bare name matching the end of a path is only missing its namespace
+            let score = edit_distance(typed, &candidate)
+                .min(edit_distance(&typed_last, &last(&candidate)) + 1);
+            (score, candidate)
+        })
+        .filter(|(score, _)| *score <= limit)
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let next = (row[j + 1] + 1)
+                .min(row[j] + 1)
+                .min(diagonal + usize::from(ca != *cb));
+            diagonal = row[j + 1];
+            row[j + 1] = next;
+        }
+    }
+    row[b.len()]
+}
+
+/// Every function and object path that can be called from `ctx`
+fn visible_paths(ctx: &Context) -> Vec<String> {
+    let mut paths = vec![];
+    let mut ctx = Some(ctx);
+    while let Some(current) = ctx {
+        let objects = current
+            .scopes
+            .iter()
+            .map(AsRef::as_ref)
+            .chain([&*current.functions]);
+        for object in objects {
+            paths.extend(object.paths().iter().map(|path| path.join(".")));
+        }
+        ctx = current.paret;
+    }
+    paths
+}
+
+fn visible_vars(ctx: &Context) -> Vec<String> {
+    let mut names = vec![];
+    let mut ctx = Some(ctx);
+    while let Some(current) = ctx {
+        names.extend(
+            current
+                .variables
+                .borrow()
+                .keys()
+                .map(|slot| slot.name().to_string()),
+        );
+        ctx = current.paret;
+    }
+    names
+}
+
+fn visible_macros(ctx: &Context) -> Vec<String> {
+    let mut names = vec![];
+    let mut ctx = Some(ctx);
+    while let Some(current) = ctx {
+        names.extend(current.macros.keys().cloned());
+        ctx = current.paret;
+    }
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::testing::run;
+    use crate::tokenizer;
+
+    #[test]
+    fn runtime_errors_show_the_source_and_help() {
+        let outcome = run("(Builtin.list\n  $.missing )");
+        assert_eq!(
+            outcome.error(),
+            "error[E0103]: variable `$.missing` is not defined
+ --> test.ls:2:3
+  |
+2 |   $.missing )
+  |   ^^^^^^^^^
+  |
+  = help: define it first, `(Builtin.local $.missing ...)`
+"
+        );
+    }
+
+    #[test]
+    fn tabs_are_expanded_under_the_underline() {
+        let outcome = run("(Builtin.scope Builtin )\n\t(locl $.x 1 )");
+        assert_eq!(
+            outcome.error(),
+            "error[E0100]: nothing is named `locl`
+ --> test.ls:2:2
+  |
+2 |     (locl $.x 1 )
+  |     ^^^^^^^^^^^^^
+  |
+  = help: did you mean `local`?
+"
+        );
+    }
+
+    #[test]
+    fn compile_errors_render_the_same_way() {
+        let mut sources = SourceMap::new();
+        let source = "(print Builtin.)";
+        let file_id = sources.add(Path::new("bad.ls"), source.to_owned());
+        let (_, errors) = tokenizer::tokenize(source, Path::new("bad.ls"), file_id);
+        let rendered = Diagnostic::from(&errors[0]).render(&sources);
+        assert!(rendered.starts_with("error[E0"), "{rendered}");
+        assert!(rendered.contains(" --> bad.ls:1:8\n"), "{rendered}");
+        assert!(rendered.ends_with("  |        ^^^^^^^^\n"), "{rendered}");
+    }
+
+    #[test]
+    fn if_reports_the_count_it_checks() {
+        let outcome = run("(if '( (Builtin.list ) )' '( (Builtin.list ) )' )");
+        assert!(
+            outcome
+                .error()
+                .starts_with("error[E0108]: expected 4 arguments, got 2 arguments")
+        );
+    }
+
+    #[test]
+    fn suggestions_need_a_close_name() {
+        let names = || vec!["local".to_owned(), "Math.floor".to_owned()];
+        assert_eq!(suggest("locl", names()), Some("local".to_owned()));
+        assert_eq!(suggest("floor", names()), Some("Math.floor".to_owned()));
+        assert_eq!(suggest("print", names()), None);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+}
@@ -1,26 +1,64 @@
-use std::fmt::Display;
+use std::fmt::{Display, Write};
 
+/// The repr of a value, which [`crate::parser::parse_value`] reads back as an equal value.
+/// Functions, objects, macros, types and params have no literal, so they are only described
 impl Display for crate::Value {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            crate::Value::Str(cnt) => write!(f, "\"{}\"", cnt),
-            crate::Value::Num(cnt) => write!(f, "{}", cnt),
+            crate::Value::Str(cnt) => write_string(f, cnt),
+            crate::Value::Num(cnt) if cnt.is_nan() => write!(f, "nan"),
+            // Debug keeps the `.0` of whole floats, so they don't read back as integers, and
+            // writes infinities as `inf` and `-inf`
+            crate::Value::Num(cnt) => write!(f, "{cnt:?}"),
+            crate::Value::Int(n) => write!(f, "{n}"),
             crate::Value::Bool(b) => write!(f, "{b}"),
-            crate::Value::List(cnt) => {
-                write!(f, "[")?;
-                for v in cnt {
-                    write!(f, " {v} ")?;
-                }
-                write!(f, "[")?;
-                Ok(())
-            }
+            crate::Value::List(cnt) => write_list(f, cnt),
             crate::Value::Code(cnt) => {
                 write!(f, "{cnt}")
             }
+            crate::Value::Template(cnt) => write!(f, "{cnt}"),
+            crate::Value::Type(t) => write!(f, "{t}"),
+            crate::Value::Param(param) => write!(f, "{param}"),
+            crate::Value::Func(_) => write!(f, "<function>"),
+            crate::Value::Object(_) => write!(f, "<object>"),
+            crate::Value::MacroInput(input) => write!(f, "{input}"),
+            crate::Value::Macro(_) => write!(f, "<macro>"),
+        }
+    }
+}
+
+/// A string literal reading back as `text`
+fn write_string(f: &mut std::fmt::Formatter<'_>, text: &str) -> std::fmt::Result {
+    f.write_char('"')?;
+    for c in text.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\t' => f.write_str("\\t")?,
+            '\r' => f.write_str("\\r")?,
+            '\0' => f.write_str("\\0")?,
+            c if c.is_control() => write!(f, "\\u{{{:x}}}", u32::from(c))?,
+            c => f.write_char(c)?,
         }
     }
+    f.write_char('"')
 }
 
+/// A `[ .. ]` list literal
+fn write_list<T: Display>(f: &mut std::fmt::Formatter<'_>, items: &[T]) -> std::fmt::Result {
+    f.write_char('[')?;
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            f.write_char(' ')?;
+        }
+        write!(f, "{item}")?;
+    }
+    f.write_char(']')
+}
+
+/// A value as `print` shows it, strings without quotes. Values inside lists are shown as their
+/// repr
 pub struct DisplayValue(pub crate::Value);
 impl Display for DisplayValue {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -28,17 +66,18 @@ impl Display for DisplayValue {
             crate::Value::Str(cnt) => write!(f, "{}", cnt),
             crate::Value::Bool(b) => write!(f, "{b}"),
             crate::Value::Num(cnt) => write!(f, "{}", cnt),
-            crate::Value::List(cnt) => {
-                write!(f, "[")?;
-                for v in cnt {
-                    write!(f, " {v} ")?;
-                }
-                write!(f, "[")?;
-                Ok(())
-            }
+            crate::Value::Int(n) => write!(f, "{n}"),
+            crate::Value::List(cnt) => write_list(f, cnt),
             crate::Value::Code(cnt) => {
                 write!(f, "{cnt}")
             }
+            crate::Value::Template(cnt) => write!(f, "{cnt}"),
+            crate::Value::Type(t) => write!(f, "{t}"),
+            crate::Value::Param(param) => write!(f, "{param}"),
+            crate::Value::Func(_) => write!(f, "<function>"),
+            crate::Value::Object(_) => write!(f, "<object>"),
+            crate::Value::MacroInput(input) => write!(f, "{input}"),
+            crate::Value::Macro(_) => write!(f, "<macro>"),
         }
     }
 }
@@ -52,13 +91,32 @@ impl Display for crate::parser::Expr {
 impl Display for crate::parser::Code {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "'( ")?;
-        for expr in &self.exprs {
+        for expr in self.exprs.iter() {
             write!(f, "{expr} ")?;
         }
         write!(f, ")'")
     }
 }
 
+impl Display for crate::parser::Template {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "`( ")?;
+        for expr in &self.exprs {
+            write!(f, "{expr} ")?;
+        }
+        write!(f, ")`")
+    }
+}
+
+impl Display for crate::parser::Hole {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.kind {
+            crate::parser::HoleKind::Value => write!(f, "_.{}", self.name),
+            crate::parser::HoleKind::Splice => write!(f, "${}", self.name),
+        }
+    }
+}
+
 impl Display for crate::parser::FnName {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let mut items = self.0.iter().peekable();
@@ -85,13 +143,15 @@ impl Display for crate::ExprCont {
             crate::parser::ExprCont::Macro(m) => {
                 write!(f, "{m}")
             }
+            crate::parser::ExprCont::Hole(hole) => write!(f, "({hole} )"),
+            crate::parser::ExprCont::Error => write!(f, "<error>"),
         }
     }
 }
 
 impl Display for crate::parser::MacroUse {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "! ({} {})", self.name, self.content)
+        write!(f, "!({} {})", self.name, self.content)
     }
 }
 
@@ -107,12 +167,359 @@ impl Display for crate::parser::Atom {
 
 impl Display for crate::parser::Argument {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.cont {
+            crate::parser::ArgumentCont::Ident(cnt) => write!(f, "{}", cnt),
+            crate::parser::ArgumentCont::Atom(a) => write!(f, "{a}"),
+            crate::parser::ArgumentCont::Macro(m) => write!(f, "{m}"),
+            crate::parser::ArgumentCont::Value(v) => write!(f, "{v}"),
+            crate::parser::ArgumentCont::Var(v) => write!(f, "$.{v}"),
+            crate::parser::ArgumentCont::Hole(hole) => write!(f, "{hole}"),
+            crate::parser::ArgumentCont::List(items) => write_list(f, items),
+            crate::parser::ArgumentCont::Error => write!(f, "<error>"),
+        }
+    }
+}
+
+impl Display for crate::runtime::function::Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use crate::runtime::function::Type;
         match self {
-            crate::parser::Argument::Ident(cnt) => write!(f, "{}", cnt),
-            crate::parser::Argument::Atom(a) => write!(f, "{a}"),
-            crate::parser::Argument::Macro(m) => write!(f, "{m}"),
-            crate::parser::Argument::Value(v) => write!(f, "{v}"),
-            crate::parser::Argument::Var(v) => write!(f, "$.{v}"),
+            Type::Any => write!(f, "any"),
+            Type::Bool => write!(f, "bool"),
+            Type::Str => write!(f, "str"),
+            Type::Num => write!(f, "num"),
+            Type::Int => write!(f, "int"),
+            Type::Code => write!(f, "code"),
+            Type::Func => write!(f, "func"),
+            Type::List(item) => write!(f, "list({item})"),
+            Type::Maybe(item) => write!(f, "maybe({item})"),
         }
     }
 }
+
+impl Display for crate::runtime::function::Param {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}({})", self.name, self.kind)
+    }
+}
+
+impl Display for crate::runtime::macros::composer::MacroInput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use crate::runtime::macros::composer::MacroInput;
+        let list = |f: &mut std::fmt::Formatter<'_>, inputs: &[MacroInput]| {
+            write!(f, "(list ")?;
+            for input in inputs {
+                write!(f, "{input} ")?;
+            }
+            write!(f, ")")
+        };
+        match self {
+            MacroInput::Ident(name) => write!(f, "(ident \"{name}\" )"),
+            MacroInput::Literal(text) => write!(f, "(literal \"{text}\" )"),
+            MacroInput::TypeAtom(name) => write!(f, "(type_atom \"{name}\" )"),
+            MacroInput::MacroAtom(name) => write!(f, "(macro_atom \"{name}\" )"),
+            MacroInput::Many(name, inputs) => {
+                write!(f, "(many \"{name}\" ")?;
+                list(f, inputs)?;
+                write!(f, " )")
+            }
+            MacroInput::Maybe(inputs) => {
+                write!(f, "(maybe ")?;
+                list(f, inputs)?;
+                write!(f, " )")
+            }
+        }
+    }
+}
+
+impl Display for crate::tokenizer::TokenCont {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use crate::tokenizer::TokenCont;
+        match self {
+            TokenCont::OParam => write!(f, "("),
+            TokenCont::CParam => write!(f, ")"),
+            TokenCont::OBracket => write!(f, "["),
+            TokenCont::CBracket => write!(f, "]"),
+            TokenCont::SingleQuote => write!(f, "'"),
+            TokenCont::MacroQuote => write!(f, "`"),
+            TokenCont::Ident(name) => write!(f, "{name}"),
+            TokenCont::Path(secs) => write!(f, "{}", secs.join(".")),
+            TokenCont::String(cnt) => write!(f, "{cnt:?}"),
+            TokenCont::Digit(cnt) => write!(f, "{cnt}"),
+            TokenCont::Macro { name, content, .. } => write!(f, "!({name} {content})"),
+            TokenCont::Error => write!(f, "<error>"),
+        }
+    }
+}
+
+impl Display for crate::tokenizer::State {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use crate::tokenizer::State;
+        match self {
+            State::Nothing => write!(f, "nothing"),
+            State::Comment => write!(f, "comment"),
+            State::String(_) | State::StringContinue(_) => write!(f, "string"),
+            State::StringSlash(..) | State::StringHex(..) | State::StringUnicode(..) => {
+                write!(f, "string escape")
+            }
+            State::RawStringOpen(_) | State::RawString(..) | State::RawStringQuote(..) => {
+                write!(f, "raw string")
+            }
+            State::Ident(_) => write!(f, "identifier"),
+            State::PathDot(_) => write!(f, "path ending in `.`"),
+            State::Path(..) => write!(f, "path"),
+            State::Digit(_) => write!(f, "number"),
+            State::MacroWaitAtom => write!(f, "`!` without a `(`"),
+            State::MacroWaitContent(name) => write!(f, "name of macro `{name}`"),
+            State::Macro { name, .. } => write!(f, "invocation of macro `{name}`"),
+        }
+    }
+}
+
+impl Display for crate::LyssCompError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use crate::LyssCompError;
+        match self {
+            LyssCompError::CodeWithoutRootAtom {
+                first_token: Some(token),
+            } => write!(f, "expected `(` to start the code, got `{}`", token.content),
+            LyssCompError::CodeWithoutRootAtom { first_token: None } => {
+                write!(f, "expected `(` to start the code, got the end of the file")
+            }
+            LyssCompError::ParseFloat { error, .. } => write!(f, "invalid number, {error}"),
+            LyssCompError::ParseInt { error, .. } => write!(f, "invalid integer, {error}"),
+            LyssCompError::CantStopToken {
+                tokenizer_state, ..
+            } => write!(f, "unexpected end of {tokenizer_state}"),
+            LyssCompError::UnexpectedChar {
+                found,
+                tokenizer_state,
+                ..
+            } => match **tokenizer_state {
+                crate::tokenizer::State::Nothing => {
+                    write!(f, "unexpected character `{}`", found.escape_debug())
+                }
+                _ => write!(
+                    f,
+                    "unexpected character `{}` in {tokenizer_state}",
+                    found.escape_debug()
+                ),
+            },
+            LyssCompError::UnterminatedString { .. } => {
+                write!(f, "string is missing its closing `\"`")
+            }
+            LyssCompError::UnexpectedToken { found, expected } => {
+                write!(f, "expected {expected}, got `{}`", found.content)
+            }
+            LyssCompError::UnexpectedEof { expected, .. } => {
+                write!(f, "expected {expected}, got the end of the code")
+            }
+            LyssCompError::InvalidEscape { reason, .. } => write!(f, "invalid escape, {reason}"),
+        }
+    }
+}
+
+impl Display for crate::LyssRuntimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use crate::LyssRuntimeError;
+        let path = |path: &[String]| path.join(".");
+        let count = |got: &[crate::parser::Argument]| match got.len() {
+            1 => "1 argument".to_owned(),
+            n => format!("{n} arguments"),
+        };
+        match self {
+            LyssRuntimeError::EntryNotFound { path: p } => {
+                write!(f, "nothing is named `{}`", path(p))
+            }
+            LyssRuntimeError::EntryWasLeaf { path: p } => {
+                write!(f, "`{}` is a function, not an object", path(p))
+            }
+            LyssRuntimeError::EntryWasBranch { path: p } => {
+                write!(f, "`{}` is an object, not a function", path(p))
+            }
+            LyssRuntimeError::VarNotFound { name } => {
+                write!(f, "variable `$.{name}` is not defined")
+            }
+            LyssRuntimeError::LiteralNotFound { expected, got } => {
+                write!(f, "expected `{expected}`, got `{got}`")
+            }
+            LyssRuntimeError::UnexpectedArg { arg, expected } => {
+                write!(f, "expected {expected}, got `{arg}`")
+            }
+            LyssRuntimeError::NeedsArg => write!(f, "expected a value, got nothing"),
+            LyssRuntimeError::TooManyArgs { got, needs }
+            | LyssRuntimeError::TooFewArgs { got, needs } => {
+                write!(f, "expected {needs} arguments, got {}", count(got))
+            }
+            LyssRuntimeError::UnmatchedArgCount { got, could_usize } => {
+                let could: Vec<_> = could_usize.iter().map(usize::to_string).collect();
+                write!(
+                    f,
+                    "expected {} arguments, got {}",
+                    could.join(" or "),
+                    count(got)
+                )
+            }
+            LyssRuntimeError::MacroNotFound { name } => {
+                write!(f, "macro `{name}` is not defined")
+            }
+            LyssRuntimeError::MacroContent { name, error } => {
+                write!(f, "in the content of macro `{name}`, {error}")
+            }
+            LyssRuntimeError::MacroDepthLimit { name } => write!(
+                f,
+                "macro `{name}` expanded more than {} times inside itself",
+                crate::runtime::macros::MACRO_DEPTH_LIMIT
+            ),
+            LyssRuntimeError::CallDepthLimit => write!(
+                f,
+                "function calls nested more than {} deep",
+                crate::runtime::function::CALL_DEPTH_LIMIT
+            ),
+            LyssRuntimeError::EmptyExpansion { name } => {
+                write!(f, "macro `{name}` expanded to nothing, so it has no value")
+            }
+            LyssRuntimeError::UnfilledHole { name } => {
+                write!(f, "template hole `{name}` was never filled")
+            }
+            LyssRuntimeError::ReentrantHostFunc => {
+                write!(f, "host function was called again while it was running")
+            }
+            LyssRuntimeError::WrongType { expected, got } => {
+                write!(f, "expected a value of type `{expected}`, got `{got}`")
+            }
+            LyssRuntimeError::Return { .. } => write!(f, "`return` used outside of a function"),
+            LyssRuntimeError::Exit { code } => write!(f, "the script exited with code {code}"),
+            LyssRuntimeError::MacroMismatch {
+                name,
+                expected,
+                got: Some(token),
+            } => write!(
+                f,
+                "macro `{name}` expected {expected}, got `{}`",
+                token.content
+            ),
+            LyssRuntimeError::MacroMismatch {
+                name,
+                expected,
+                got: None,
+            } => write!(
+                f,
+                "macro `{name}` expected {expected}, got the end of its content"
+            ),
+            LyssRuntimeError::SyntaxError => write!(f, "tried to run code that failed to parse"),
+            LyssRuntimeError::Located { error, .. } => write!(f, "{error}"),
+        }
+    }
+}
+
+impl Display for crate::cst::Trivia {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.text())
+    }
+}
+
+impl Display for crate::cst::CstToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for trivia in &self.leading {
+            write!(f, "{trivia}")?;
+        }
+        write!(f, "{}", self.text)?;
+        for trivia in &self.trailing {
+            write!(f, "{trivia}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for crate::cst::CstList {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.open)?;
+        for item in &self.items {
+            write!(f, "{item}")?;
+        }
+        if let Some(close) = &self.close {
+            write!(f, "{close}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for crate::cst::CstNode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use crate::cst::CstNode;
+        match self {
+            CstNode::Token(token) => write!(f, "{token}"),
+            CstNode::List(list) => write!(f, "{list}"),
+            CstNode::Quoted { open, list, close } => {
+                write!(f, "{open}{list}")?;
+                if let Some(close) = close {
+                    write!(f, "{close}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Prints the exact source the tree was read from
+impl Display for crate::cst::Cst {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for node in &self.nodes {
+            write!(f, "{node}")?;
+        }
+        for trivia in &self.trailing {
+            write!(f, "{trivia}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Compact JSON, whole numbers are written without a fraction
+impl Display for crate::json::Json {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use crate::json::{Json, write_string};
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(b) => write!(f, "{b}"),
+            #[allow(clippy::cast_possible_truncation)]
+            Json::Num(num) if num.fract() == 0.0 && num.abs() < 1e15 => {
+                write!(f, "{}", *num as i64)
+            }
+            Json::Num(num) if num.is_finite() => write!(f, "{num}"),
+            Json::Num(_) => write!(f, "null"),
+            Json::Str(text) => write_string(f, text),
+            Json::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{item}")?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(fields) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{value}")?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+impl Display for crate::json::JsonError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "invalid JSON at byte {}, expected {}",
+            self.offset, self.expected
+        )
+    }
+}
@@ -0,0 +1,337 @@
+//! Prints tokens and ASTs for debugging, as an indented tree or as JSON
+//!
+//! Both formats show the same nodes, each with its kind, its text when it has one and where it
+//! starts. Nodes built by macros instead of read from a file have no location.
+
+use std::fmt::Write;
+
+use crate::Value;
+use crate::json;
+use crate::parser::{Argument, ArgumentCont, Expr, ExprCont};
+use crate::runtime::macros::Expansion;
+use crate::span::Span;
+use crate::tokenizer::{Token, TokenCont};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DumpFormat {
+    /// One node per line, children indented under their parent
+    #[default]
+    Human,
+    Json,
+}
+
+/// A token or AST node, ready to be printed in either format
+#[derive(Debug)]
+struct Node {
+    kind: &'static str,
+    text: Option<String>,
+    span: Span,
+    children: Vec<Node>,
+}
+
+impl Node {
+    fn new(kind: &'static str, text: Option<String>, span: Span) -> Node {
+        Node {
+            kind,
+            text,
+            span,
+            children: vec![],
+        }
+    }
+    fn with_children(mut self, children: Vec<Node>) -> Node {
+        self.children = children;
+        self
+    }
+}
+
+/// The token stream, one token per line
+#[must_use]
+pub fn tokens(tokens: &[Token], format: DumpFormat) -> String {
+    print(&tokens.iter().map(token).collect::<Vec<_>>(), format)
+}
+
+/// The AST as a tree, arguments and the expressions of code blocks under their parent
+#[must_use]
+pub fn exprs(exprs: &[Expr], format: DumpFormat) -> String {
+    print(&exprs.iter().map(expr).collect::<Vec<_>>(), format)
+}
+
+/// Every recorded expansion, with the expressions it expanded to under it
+#[must_use]
+pub fn expansions(expansions: &[Expansion], format: DumpFormat) -> String {
+    let nodes: Vec<Node> = expansions
+        .iter()
+        .map(|expansion| {
+            let macro_use = &expansion.macro_use;
+            Node::new("Expansion", Some(macro_use.to_string()), macro_use.span)
+                .with_children(expansion.exprs.iter().map(expr).collect())
+        })
+        .collect();
+    print(&nodes, format)
+}
+
+fn print(nodes: &[Node], format: DumpFormat) -> String {
+    let mut output = String::new();
+    match format {
+        DumpFormat::Human => {
+            for node in nodes {
+                write_human(&mut output, node, 0);
+            }
+        }
+        DumpFormat::Json => {
+            output.push('[');
+            write_json_items(&mut output, nodes, 0);
+            output.push_str("]\n");
+        }
+    }
+    output
+}
+
+fn token(token: &Token) -> Node {
+    let kind = match &token.content {
+        TokenCont::OParam => "OParam",
+        TokenCont::CParam => "CParam",
+        TokenCont::OBracket => "OBracket",
+        TokenCont::CBracket => "CBracket",
+        TokenCont::SingleQuote => "SingleQuote",
+        TokenCont::MacroQuote => "MacroQuote",
+        TokenCont::Ident(_) => "Ident",
+        TokenCont::Path(_) => "Path",
+        TokenCont::String(_) => "String",
+        TokenCont::Digit(_) => "Digit",
+        TokenCont::Macro { .. } => "Macro",
+        TokenCont::Error => return Node::new("Error", None, token.span),
+    };
+    Node::new(kind, Some(token.content.to_string()), token.span)
+}
+
+fn expr(expr: &Expr) -> Node {
+    match &expr.cont {
+        ExprCont::Atom(atom) => Node::new("Atom", Some(atom.fn_name.to_string()), expr.span)
+            .with_children(atom.arguments.iter().map(argument).collect()),
+        ExprCont::Macro(macro_use) => Node::new("Macro", Some(macro_use.to_string()), expr.span),
+        ExprCont::Hole(hole) => Node::new("Hole", Some(hole.to_string()), expr.span),
+        ExprCont::Error => Node::new("Error", None, expr.span),
+    }
+}
+
+fn argument(argument: &Argument) -> Node {
+    let span = argument.span;
+    match &argument.cont {
+        ArgumentCont::Var(name) => Node::new("Var", Some(format!("$.{name}")), span),
+        ArgumentCont::Ident(path) => Node::new("Ident", Some(path.to_string()), span),
+        ArgumentCont::Atom(atom) => Node::new("Atom", Some(atom.fn_name.to_string()), span)
+            .with_children(atom.arguments.iter().map(self::argument).collect()),
+        ArgumentCont::Value(value) => self::value(value, span),
+        ArgumentCont::Macro(macro_use) => Node::new("Macro", Some(macro_use.to_string()), span),
+        ArgumentCont::Hole(hole) => Node::new("Hole", Some(hole.to_string()), span),
+        ArgumentCont::List(items) => {
+            Node::new("List", None, span).with_children(items.iter().map(self::argument).collect())
+        }
+        ArgumentCont::Error => Node::new("Error", None, span),
+    }
+}
+
+/// Code and templates are shown as trees, other values by their repr
+fn value(value: &Value, span: Span) -> Node {
+    let kind = match value {
+        Value::Code(code) => {
+            return Node::new("Code", None, span)
+                .with_children(code.exprs.iter().map(expr).collect());
+        }
+        Value::Template(template) => {
+            return Node::new("Template", None, span)
+                .with_children(template.exprs.iter().map(expr).collect());
+        }
+        Value::Bool(_) => "Bool",
+        Value::Str(_) => "Str",
+        Value::Num(_) => "Num",
+        Value::Int(_) => "Int",
+        Value::List(_) => "List",
+        _ => "Value",
+    };
+    Node::new(kind, Some(value.to_string()), span)
+}
+
+/// Nodes start with their location, padded so the kinds line up
+fn write_human(output: &mut String, node: &Node, depth: usize) {
+    let location = if node.span == Span::default() {
+        String::new()
+    } else {
+        format!("{}:{}", node.span.line, node.span.column)
+    };
+    let _ = write!(
+        output,
+        "{location:<10}{:indent$}{}",
+        "",
+        node.kind,
+        indent = depth * 2
+    );
+    if let Some(text) = &node.text {
+        let _ = write!(output, " {text}");
+    }
+    output.push('\n');
+    for child in &node.children {
+        write_human(output, child, depth + 1);
+    }
+}
+
+/// A node as a JSON object, `text` and `children` are left out when there are none
+fn write_json(output: &mut String, node: &Node, depth: usize) {
+    let _ = write!(output, "{{\"kind\": \"{}\"", node.kind);
+    if let Some(text) = &node.text {
+        output.push_str(", \"text\": ");
+        let _ = json::write_string(output, text);
+    }
+    output.push_str(", \"span\": ");
+    let span = node.span;
+    if span == Span::default() {
+        output.push_str("null");
+    } else {
+        let _ = write!(
+            output,
+            "{{\"file\": {}, \"start\": {}, \"end\": {}, \"line\": {}, \"column\": {}}}",
+            span.file_id, span.start_byte, span.end_byte, span.line, span.column
+        );
+    }
+    if !node.children.is_empty() {
+        output.push_str(", \"children\": [");
+        write_json_items(output, &node.children, depth);
+        output.push(']');
+    }
+    output.push('}');
+}
+
+/// The items of an array, one per line
+fn write_json_items(output: &mut String, nodes: &[Node], depth: usize) {
+    for (i, node) in nodes.iter().enumerate() {
+        if i > 0 {
+            output.push(',');
+        }
+        let _ = write!(output, "\n{:indent$}", "", indent = (depth + 1) * 2);
+        write_json(output, node, depth + 1);
+    }
+    if !nodes.is_empty() {
+        let _ = write!(output, "\n{:indent$}", "", indent = depth * 2);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::json::Json;
+    use crate::testing::{library, parse};
+    use crate::tokenizer;
+
+    const SOURCE: &str = "(print $.x [1 \"a\"] '( (g ) )' )\n!(m a )";
+
+    #[test]
+    fn tokens_one_per_line() {
+        let (tokens, _) = tokenizer::tokenize("(f \"a\\n\" \\q )", Path::new("test.ls"), 0);
+        assert_eq!(
+            self::tokens(&tokens, DumpFormat::Human),
+            "1:1       OParam (\n\
+             1:2       Ident f\n\
+             1:4       String \"a\\n\"\n\
+             1:10      Error\n\
+             1:11      Ident q\n\
+             1:13      CParam )\n"
+        );
+    }
+
+    #[test]
+    fn exprs_as_a_tree() {
+        let exprs = parse(SOURCE);
+        assert_eq!(
+            self::exprs(&exprs, DumpFormat::Human),
+            "1:1       Atom print\n\
+             1:8         Var $.x\n\
+             1:12        List\n\
+             1:13          Int 1\n\
+             1:15          Str \"a\"\n\
+             1:20        Code\n\
+             1:23          Atom g\n\
+             2:1       Macro !(m a )\n"
+        );
+    }
+
+    #[test]
+    fn nodes_without_a_location() {
+        let exprs = [Expr {
+            span: Span::default(),
+            cont: ExprCont::Error,
+        }];
+        assert_eq!(self::exprs(&exprs, DumpFormat::Human), "          Error\n");
+        assert_eq!(
+            self::exprs(&exprs, DumpFormat::Json),
+            "[\n  {\"kind\": \"Error\", \"span\": null}\n]\n"
+        );
+    }
+
+    #[test]
+    fn json_has_the_same_nodes() {
+        let (tokens, _) = tokenizer::tokenize("(f \"\\\"\" )", Path::new("test.ls"), 0);
+        assert_eq!(
+            self::tokens(&tokens, DumpFormat::Json),
+            "[\n  \
+             {\"kind\": \"OParam\", \"text\": \"(\", \"span\": {\"file\": 0, \"start\": 0, \"end\": 1, \"line\": 1, \"column\": 1}},\n  \
+             {\"kind\": \"Ident\", \"text\": \"f\", \"span\": {\"file\": 0, \"start\": 1, \"end\": 2, \"line\": 1, \"column\": 2}},\n  \
+             {\"kind\": \"String\", \"text\": \"\\\"\\\\\\\"\\\"\", \"span\": {\"file\": 0, \"start\": 3, \"end\": 7, \"line\": 1, \"column\": 4}},\n  \
+             {\"kind\": \"CParam\", \"text\": \")\", \"span\": {\"file\": 0, \"start\": 8, \"end\": 9, \"line\": 1, \"column\": 9}}\n\
+             ]\n"
+        );
+        let exprs = parse(SOURCE);
+        let json = Json::parse(&self::exprs(&exprs, DumpFormat::Json)).expect("the dump is JSON");
+        let nodes = json.as_array().expect("the dump is an array");
+        assert_eq!(nodes.len(), 2);
+        assert_eq!(nodes[0].get("kind").and_then(Json::as_str), Some("Atom"));
+        let children = nodes[0].get("children").and_then(Json::as_array).unwrap();
+        assert_eq!(
+            children[2]
+                .at(&["children"])
+                .and_then(Json::as_array)
+                .unwrap()
+                .len(),
+            1
+        );
+        assert_eq!(
+            children[2].at(&["span", "column"]).and_then(Json::as_usize),
+            Some(20)
+        );
+    }
+
+    #[test]
+    fn expansions_are_dumped_as_they_ran() {
+        let mut ctx = library();
+        ctx.record_expansions();
+        let script = parse(
+            "(Builtin.Macro.def \"twice\" (Builtin.Macro.Composers.Default.make
+                (Builtin.Macro.Composers.Default.Inputs.macro_atom \"code\" )
+                `( ($code ) ($code ) )`
+            ) )
+            !(twice ((Builtin.Math.max 1 2 ) ) )
+            (Builtin.Math.max 1 !(twice ((Builtin.Math.max 3 4 ) ) ) )",
+        );
+        ctx.run(&script).unwrap();
+        assert_eq!(
+            expansions(&ctx.take_expansions(), DumpFormat::Human),
+            "5:13      Expansion !(twice ((Builtin.Math.max 1 2 ) ) )
+\
+             5:22        Atom Builtin.Math.max\n\
+             5:40          Int 1\n\
+             5:42          Int 2\n\
+             5:22        Atom Builtin.Math.max\n\
+             5:40          Int 1\n\
+             5:42          Int 2\n\
+             6:33      Expansion !(twice ((Builtin.Math.max 3 4 ) ) )\n\
+             6:42        Atom Builtin.Math.max\n\
+             6:60          Int 3\n\
+             6:62          Int 4\n\
+             6:42        Atom Builtin.Math.max\n\
+             6:60          Int 3\n\
+             6:62          Int 4\n"
+        );
+    }
+}
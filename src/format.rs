@@ -0,0 +1,609 @@
+//! Rewrites source in the canonical layout, for `lyss fmt`
+//!
+//! A list that fits on its line is written on it as `(head arg )` and code blocks as
+//! `'( (expr ) )'`. A longer list keeps its head and the tokens after it on the first line and
+//! puts every other item on its own line, indented by a tab, with the `)` on a line of its own.
+//! Lists made of tokens, code blocks and at most one short list only break their code blocks, so
+//! they keep the shape of an `if`:
+//!
+//! ```text
+//! (if '( (= $.var 1 ) )' '(
+//!     (print "true\n" )
+//! )' else '(
+//!     (print "false\n" )
+//! )' )
+//! ```
+//!
+//! A code block that starts a new line in the source stays broken even when it would fit. Comments
+//! stay next to the tokens they were written next to, blank lines between items are kept but
+//! collapsed to one. Tokens are written as they are in the source, macro invocations included,
+//! only one that doesn't fit on its line is broken between the items of its content like a
+//! list, each item still written as it is.
+
+use std::path::Path;
+
+use crate::cst::{self, Cst, CstList, CstNode, CstToken, Trivia};
+use crate::span::FileId;
+use crate::tokenizer::{self, TokenCont};
+use crate::{LyssCompError, parser};
+
+/// The line width used when none is given
+pub const DEFAULT_WIDTH: usize = 100;
+
+/// How many columns a tab counts as when measuring lines
+const TAB_WIDTH: usize = 4;
+
+#[derive(Debug, Clone)]
+pub struct Formatter {
+    width: usize,
+}
+
+impl Default for Formatter {
+    fn default() -> Self {
+        Formatter {
+            width: DEFAULT_WIDTH,
+        }
+    }
+}
+
+impl Formatter {
+    /// A formatter that breaks lists longer than `width` columns
+    #[must_use]
+    pub fn with_width(width: usize) -> Formatter {
+        Formatter { width }
+    }
+    #[must_use]
+    pub fn width(&self) -> usize {
+        self.width
+    }
+    /// Formats `content`. Only the parens and quotes have to be balanced, when they aren't the
+    /// layout could move code in or out of a list, so this fails with the syntax errors instead
+    pub fn format(
+        &self,
+        content: &str,
+        file_name: &Path,
+        file_id: FileId,
+    ) -> Result<String, Vec<LyssCompError>> {
+        let (cst, mut errors) = cst::parse(content, file_name, file_id);
+        if !cst.nodes.iter().all(closed) {
+            let (tokens, _) = tokenizer::tokenize(content, file_name, file_id);
+            errors.extend(parser::parse(&mut tokens.into_iter()).1);
+        }
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        Ok(self.format_cst(&cst))
+    }
+    /// Lays out a whole file, ending it with a newline unless it's empty
+    #[must_use]
+    pub fn format_cst(&self, cst: &Cst) -> String {
+        let mut out = String::new();
+        for (i, node) in cst.nodes.iter().enumerate() {
+            self.item(node, 0, i == 0, &mut out);
+        }
+        leading(&cst.trailing, 0, cst.nodes.is_empty(), &mut out);
+        // Every line is started with a newline, including the first
+        let mut out = out.strip_prefix('\n').map(str::to_owned).unwrap_or(out);
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        out
+    }
+    /// Writes `node` on a new line at `depth`, with the comments around it
+    fn item(&self, node: &CstNode, depth: usize, first: bool, out: &mut String) {
+        if leading(&node.first_token().leading, depth, first, out) {
+            out.push('\n');
+        }
+        out.push('\n');
+        indent(depth, out);
+        self.node(node, depth, out);
+        after(last_token(node).trailing_comment(), out);
+    }
+    /// Writes `node` starting at the end of `out`, without the comments before its first token
+    /// and after its last one
+    fn node(&self, node: &CstNode, depth: usize, out: &mut String) {
+        if let Some(flat) = flat(node)
+            && column(out) + width(&flat) <= self.width
+        {
+            out.push_str(&flat);
+            return;
+        }
+        match node {
+            CstNode::Token(token) => {
+                if !self.invocation(token, depth, out) {
+                    out.push_str(&token.text);
+                }
+            }
+            CstNode::List(list) if self.hangs(list, column(out)) => self.hanging(list, depth, out),
+            CstNode::List(list) => self.vertical(list, depth, out),
+            CstNode::Quoted { open, list, close } => {
+                self.block(open, list, close.as_deref(), depth, out);
+            }
+        }
+    }
+    /// Whether `list` can stay on its line with only its code blocks broken
+    fn hangs(&self, list: &CstList, column: usize) -> bool {
+        let Some(block) = list
+            .items
+            .iter()
+            .position(|item| matches!(item, CstNode::Quoted { .. }))
+        else {
+            return false;
+        };
+        if list.open.trailing_comment().is_some()
+            || list
+                .close
+                .as_ref()
+                .is_none_or(|close| has_comment(&close.leading))
+        {
+            return false;
+        }
+        let mut lists = 0;
+        let mut head = column + width(&list.open.text) + width("'(");
+        for (i, item) in list.items.iter().enumerate() {
+            if has_comment(&item.first_token().leading)
+                || last_token(item).trailing_comment().is_some()
+            {
+                return false;
+            }
+            if matches!(item, CstNode::Quoted { .. }) {
+                continue;
+            }
+            let Some(flat) = flat(item) else {
+                return false;
+            };
+            if i < block {
+                head += width(&flat) + 1;
+            }
+            if matches!(item, CstNode::List(_)) {
+                lists += 1;
+            }
+        }
+        lists <= 1 && head <= self.width
+    }
+    /// Writes every item on the list's line, breaking only its code blocks
+    fn hanging(&self, list: &CstList, depth: usize, out: &mut String) {
+        out.push_str(&list.open.text);
+        for (i, item) in list.items.iter().enumerate() {
+            if i > 0 {
+                out.push(' ');
+            }
+            self.node(item, depth, out);
+        }
+        out.push(' ');
+        out.push_str(close(list));
+    }
+    /// Writes the head of `list` and the tokens after it on the first line and every other item
+    /// on a line of its own
+    fn vertical(&self, list: &CstList, depth: usize, out: &mut String) {
+        out.push_str(&list.open.text);
+        let mut broken = after(list.open.trailing_comment(), out);
+        let mut rest = list.items.as_slice();
+        while !broken && let [CstNode::Token(token), tail @ ..] = rest {
+            let first = rest.len() == list.items.len();
+            // The last one needs room for the ` )` after it
+            let close = if tail.is_empty() { 2 } else { 0 };
+            if has_comment(&token.leading)
+                || token.text.contains('\n')
+                || (!first && column(out) + 1 + width(&token.text) + close > self.width)
+            {
+                break;
+            }
+            if !first {
+                out.push(' ');
+            }
+            out.push_str(&token.text);
+            broken = after(token.trailing_comment(), out);
+            rest = tail;
+        }
+        // A lone list that hangs stays on the line too, like `(return (if .. '(`
+        if !broken
+            && rest.len() < list.items.len()
+            && let [CstNode::List(last)] = rest
+            && !has_comment(&last.open.leading)
+            && self.hangs(last, column(out) + 1)
+        {
+            out.push(' ');
+            self.hanging(last, depth, out);
+            broken = after(last_token(&rest[0]).trailing_comment(), out);
+            rest = &[];
+        }
+        for (i, item) in rest.iter().enumerate() {
+            self.item(item, depth + 1, i == 0, out);
+        }
+        let trivia = list.close.iter().flat_map(|close| &close.leading);
+        if rest.is_empty() && !broken && !has_comment(trivia.clone()) {
+            out.push(' ');
+            out.push_str(close(list));
+            return;
+        }
+        leading(trivia, depth + 1, rest.is_empty(), out);
+        out.push('\n');
+        indent(depth, out);
+        out.push_str(close(list));
+    }
+    /// Breaks a single line macro invocation between the items of its content, which are
+    /// written as they are in the source. Returns `false` when the content holds comments or
+    /// doesn't parse, then the invocation is written as it is
+    fn invocation(&self, token: &CstToken, depth: usize, out: &mut String) -> bool {
+        let TokenCont::Macro { name, content, .. } = &token.token.content else {
+            return false;
+        };
+        if token.text.contains('\n') {
+            return false;
+        }
+        let (cst, errors) = cst::parse(content, Path::new(""), token.token.span.file_id);
+        let mut all = vec![];
+        for node in &cst.nodes {
+            tokens(node, &mut all);
+        }
+        if cst.nodes.is_empty()
+            || !errors.is_empty()
+            || !cst.nodes.iter().all(closed)
+            || has_comment(&cst.trailing)
+            || all
+                .iter()
+                .any(|token| has_comment(&token.leading) || has_comment(&token.trailing))
+        {
+            return false;
+        }
+        let items: Vec<_> = cst
+            .nodes
+            .iter()
+            .map(|node| {
+                let span = node.span();
+                (node, &content[span.start_byte..span.end_byte])
+            })
+            .collect();
+        out.push_str("!(");
+        out.push_str(name);
+        // The tokens after the name stay on its line, like the ones after the head of a list
+        let mut rest = items.as_slice();
+        while let [(CstNode::Token(_), text), tail @ ..] = rest
+            && !tail.is_empty()
+            && column(out) + 1 + width(text) <= self.width
+        {
+            out.push(' ');
+            out.push_str(text);
+            rest = tail;
+        }
+        for (_, text) in rest {
+            out.push('\n');
+            indent(depth + 1, out);
+            out.push_str(text);
+        }
+        out.push('\n');
+        indent(depth, out);
+        out.push(')');
+        true
+    }
+    /// Writes a code block or template with every expression on a line of its own
+    fn block(
+        &self,
+        open: &CstToken,
+        list: &CstList,
+        close: Option<&CstToken>,
+        depth: usize,
+        out: &mut String,
+    ) {
+        out.push_str(&open.text);
+        out.push_str(&list.open.text);
+        let comments = open
+            .trailing
+            .iter()
+            .chain(&list.open.leading)
+            .chain(&list.open.trailing)
+            .filter_map(comment);
+        for (i, comment) in comments.enumerate() {
+            if i == 0 {
+                out.push(' ');
+            } else {
+                out.push('\n');
+                indent(depth + 1, out);
+            }
+            out.push_str(comment);
+        }
+        for (i, item) in list.items.iter().enumerate() {
+            self.item(item, depth + 1, i == 0, out);
+        }
+        // Comments between the `)` and the quote are moved before the `)`
+        let trivia = list
+            .close
+            .iter()
+            .flat_map(|paren| paren.leading.iter().chain(&paren.trailing))
+            .chain(close.into_iter().flat_map(|quote| &quote.leading));
+        leading(trivia, depth + 1, list.items.is_empty(), out);
+        out.push('\n');
+        indent(depth, out);
+        out.push(')');
+        out.push_str(&open.text);
+    }
+}
+
+/// Writes the comments in `trivia` on lines of their own, keeping a blank line where the
+/// source has any unless nothing was written yet. Returns whether the next line should be
+/// blank too
+fn leading<'a>(
+    trivia: impl IntoIterator<Item = &'a Trivia>,
+    depth: usize,
+    mut first: bool,
+    out: &mut String,
+) -> bool {
+    let mut newlines = 0;
+    for trivia in trivia {
+        match trivia {
+            Trivia::Whitespace(_) => newlines += trivia.newlines(),
+            Trivia::Comment(comment) => {
+                if newlines > 1 && !first {
+                    out.push('\n');
+                }
+                out.push('\n');
+                indent(depth, out);
+                out.push_str(comment);
+                newlines = 0;
+                first = false;
+            }
+        }
+    }
+    newlines > 1 && !first
+}
+
+/// Writes `comment` at the end of the line, returns whether there was one
+fn after(comment: Option<&str>, out: &mut String) -> bool {
+    let Some(comment) = comment else {
+        return false;
+    };
+    out.push(' ');
+    out.push_str(comment);
+    true
+}
+
+fn indent(depth: usize, out: &mut String) {
+    out.extend(std::iter::repeat_n('\t', depth));
+}
+
+/// How many columns `text` takes up
+fn width(text: &str) -> usize {
+    text.chars()
+        .map(|c| if c == '\t' { TAB_WIDTH } else { 1 })
+        .sum()
+}
+
+/// The column the end of `out` is at
+fn column(out: &str) -> usize {
+    width(out.rsplit('\n').next().unwrap_or(out))
+}
+
+fn comment(trivia: &Trivia) -> Option<&str> {
+    match trivia {
+        Trivia::Comment(comment) => Some(comment),
+        Trivia::Whitespace(_) => None,
+    }
+}
+
+fn has_comment<'a>(trivia: impl IntoIterator<Item = &'a Trivia>) -> bool {
+    trivia.into_iter().any(|trivia| comment(trivia).is_some())
+}
+
+/// The token holding the trivia after the node
+fn last_token(node: &CstNode) -> &CstToken {
+    let list = match node {
+        CstNode::Token(token) => return token,
+        CstNode::Quoted {
+            close: Some(close), ..
+        } => return close,
+        CstNode::List(list) | CstNode::Quoted { list, .. } => list,
+    };
+    match (&list.close, list.items.last()) {
+        (Some(close), _) => close,
+        (None, Some(item)) => last_token(item),
+        (None, None) => &list.open,
+    }
+}
+
+/// Every token of `node` in source order
+fn tokens<'a>(node: &'a CstNode, out: &mut Vec<&'a CstToken>) {
+    let (open, list, close) = match node {
+        CstNode::Token(token) => return out.push(token),
+        CstNode::List(list) => (None, list, None),
+        CstNode::Quoted { open, list, close } => (Some(&**open), list, close.as_deref()),
+    };
+    out.extend(open);
+    out.push(&list.open);
+    for item in &list.items {
+        tokens(item, out);
+    }
+    out.extend(list.close.as_deref());
+    out.extend(close);
+}
+
+/// `node` on a single line, `None` when it holds a comment, a multi-line token or a code block
+/// that starts a new line in the source
+fn flat(node: &CstNode) -> Option<String> {
+    let mut all = vec![];
+    tokens(node, &mut all);
+    let last = all.len() - 1;
+    if all.iter().enumerate().any(|(i, token)| {
+        (i > 0 && has_comment(&token.leading)) || (i < last && token.trailing_comment().is_some())
+    }) {
+        return None;
+    }
+    let mut out = String::new();
+    write_flat(node, &mut out)?;
+    Some(out)
+}
+
+fn write_flat(node: &CstNode, out: &mut String) -> Option<()> {
+    let list = match node {
+        CstNode::Token(token) if token.text.contains('\n') => return None,
+        CstNode::Token(token) => {
+            out.push_str(&token.text);
+            return Some(());
+        }
+        CstNode::Quoted { open, list, .. } => {
+            let first = list.items.first().map_or(
+                list.close.iter().flat_map(|close| &close.leading).collect(),
+                |item| item.first_token().leading.iter().collect::<Vec<_>>(),
+            );
+            if first.iter().any(|trivia| trivia.newlines() > 0) {
+                return None;
+            }
+            out.push_str(&open.text);
+            out.push_str(&list.open.text);
+            if !list.items.is_empty() {
+                out.push(' ');
+            }
+            write_items(list, out)?;
+            out.push(')');
+            out.push_str(&open.text);
+            return Some(());
+        }
+        CstNode::List(list) => list,
+    };
+    out.push_str(&list.open.text);
+    write_items(list, out)?;
+    // List literals are written like their values print, `[1 2]`
+    if matches!(list.open.token.content, TokenCont::OBracket) && out.ends_with(' ') {
+        out.pop();
+    }
+    out.push_str(close(list));
+    Some(())
+}
+
+/// The text closing `list`, written even when the source is missing it
+fn close(list: &CstList) -> &'static str {
+    match list.open.token.content {
+        TokenCont::OBracket => "]",
+        _ => ")",
+    }
+}
+
+/// The items of a list separated by spaces, with a space after the last one
+fn write_items(list: &CstList, out: &mut String) -> Option<()> {
+    for item in &list.items {
+        write_flat(item, out)?;
+        out.push(' ');
+    }
+    Some(())
+}
+
+/// Whether every list and quote in `node` is closed, and it holds no stray `)` or `]`
+fn closed(node: &CstNode) -> bool {
+    let list = match node {
+        CstNode::Token(token) => {
+            return !matches!(token.token.content, TokenCont::CParam | TokenCont::CBracket);
+        }
+        CstNode::Quoted { close: None, .. } => return false,
+        CstNode::List(list) | CstNode::Quoted { list, .. } => list,
+    };
+    list.close.is_some() && list.items.iter().all(closed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[track_caller]
+    fn format_with(formatter: &Formatter, source: &str) -> String {
+        let once = formatter
+            .format(source, Path::new("test.ls"), 0)
+            .expect("the source is balanced");
+        let twice = formatter
+            .format(&once, Path::new("test.ls"), 0)
+            .expect("the formatted source is balanced");
+        assert_eq!(twice, once, "formatting isn't idempotent");
+        once
+    }
+
+    #[track_caller]
+    fn format(source: &str) -> String {
+        format_with(&Formatter::default(), source)
+    }
+
+    #[test]
+    fn examples_are_formatted() {
+        assert_eq!(format(include_str!("../fib.ls")), include_str!("../fib.ls"));
+        // Only the second blank line before the first expression goes away
+        let hello = include_str!("../hello.ls");
+        assert_eq!(format(hello), hello.replacen("\n\n\n", "\n\n", 1));
+    }
+
+    #[test]
+    fn lists_are_padded() {
+        assert_eq!(
+            format("(print  (Math.+ 1 2)\t[1  2 ] )"),
+            "(print (Math.+ 1 2 ) [1 2] )\n"
+        );
+    }
+
+    #[test]
+    fn code_blocks_keep_the_shape_of_an_if() {
+        let source = "(if '((= $.var 1))' '(\n(print \"true\" ))' else '((print \"false\"))')";
+        assert_eq!(
+            format(source),
+            "(if '( (= $.var 1 ) )' '(\n\t(print \"true\" )\n)' else '( (print \"false\" ) )' )\n"
+        );
+    }
+
+    #[test]
+    fn long_lists_are_broken() {
+        let formatter = Formatter::with_width(30);
+        assert_eq!(
+            format_with(
+                &formatter,
+                "(print \"first line\" \"second line\" (list 1 2 3 ) )"
+            ),
+            "(print \"first line\"\n\t\"second line\"\n\t(list 1 2 3 )\n)\n"
+        );
+        // The `)` after the last token has to fit too
+        assert_eq!(
+            format_with(&formatter, "(return \"twenty six chars long\" )"),
+            "(return\n\t\"twenty six chars long\"\n)\n"
+        );
+    }
+
+    #[test]
+    fn comments_and_blank_lines_are_kept() {
+        let source = "# head\n\n\n(a ) # after a\n\n# before b\n(b\n\t# inside\n)\n";
+        assert_eq!(
+            format(source),
+            "# head\n\n(a ) # after a\n\n# before b\n(b\n\t# inside\n)\n"
+        );
+    }
+
+    #[test]
+    fn macro_content_is_kept_as_it_is() {
+        for source in [
+            "!(twice (print \"body\") )\n",
+            "!(map! one (1) two (2))\n",
+            "(print !(twice (print  \"body\")) )\n",
+            "!(multi\n  line   (content)\n)\n",
+        ] {
+            assert_eq!(format(source), source);
+        }
+    }
+
+    #[test]
+    fn long_macro_invocations_are_broken_between_items() {
+        let formatter = Formatter::with_width(30);
+        assert_eq!(
+            format_with(
+                &formatter,
+                "!(defn f [a b] (int) ((print \"a\") (print \"b\")))"
+            ),
+            "!(defn f\n\t[a b]\n\t(int)\n\t((print \"a\") (print \"b\"))\n)\n"
+        );
+        assert_eq!(
+            format_with(&formatter, "(return !(twice (print \"twice over\") ) )"),
+            "(return\n\t!(twice\n\t\t(print \"twice over\")\n\t)\n)\n"
+        );
+    }
+
+    #[test]
+    fn unbalanced_sources_arent_formatted() {
+        let errors = Formatter::default()
+            .format("(print '( (a ) )", Path::new("test.ls"), 0)
+            .unwrap_err();
+        assert!(!errors.is_empty());
+    }
+}
@@ -0,0 +1,411 @@
+//! Just enough JSON for the dumps and the language server, objects keep the order of their
+//! fields so output is stable
+
+use std::fmt::Write;
+
+/// How deep arrays and objects may be nested, deeper text is refused instead of overflowing
+/// the stack
+const MAX_DEPTH: usize = 128;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Num(f64),
+    Str(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+/// Where a text stopped being JSON
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JsonError {
+    pub offset: usize,
+    pub expected: &'static str,
+}
+
+impl Json {
+    /// An object with `fields` in the given order
+    pub fn object<'k>(fields: impl IntoIterator<Item = (&'k str, Json)>) -> Json {
+        Json::Object(
+            fields
+                .into_iter()
+                .map(|(key, value)| (key.to_owned(), value))
+                .collect(),
+        )
+    }
+    /// The field `key` of an object
+    #[must_use]
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+    /// Follows `keys` through nested objects
+    #[must_use]
+    pub fn at(&self, keys: &[&str]) -> Option<&Json> {
+        keys.iter().try_fold(self, |json, key| json.get(key))
+    }
+    #[must_use]
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::Str(text) => Some(text),
+            _ => None,
+        }
+    }
+    #[must_use]
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+    /// A number that is a whole, non negative `usize`
+    #[must_use]
+    pub fn as_usize(&self) -> Option<usize> {
+        match self {
+            #[allow(
+                clippy::cast_possible_truncation,
+                clippy::cast_sign_loss,
+                clippy::cast_precision_loss
+            )]
+            Json::Num(num) if num.fract() == 0.0 && *num >= 0.0 && *num <= usize::MAX as f64 => {
+                Some(*num as usize)
+            }
+            _ => None,
+        }
+    }
+    pub fn parse(text: &str) -> Result<Json, JsonError> {
+        let mut reader = Reader {
+            text,
+            pos: 0,
+            depth: 0,
+        };
+        let json = reader.value()?;
+        reader.skip_whitespace();
+        if reader.pos < text.len() {
+            return Err(reader.error("the end of the text"));
+        }
+        Ok(json)
+    }
+}
+
+impl From<&str> for Json {
+    fn from(text: &str) -> Json {
+        Json::Str(text.to_owned())
+    }
+}
+
+impl From<String> for Json {
+    fn from(text: String) -> Json {
+        Json::Str(text)
+    }
+}
+
+impl From<bool> for Json {
+    fn from(b: bool) -> Json {
+        Json::Bool(b)
+    }
+}
+
+impl From<usize> for Json {
+    #[allow(clippy::cast_precision_loss)]
+    fn from(num: usize) -> Json {
+        Json::Num(num as f64)
+    }
+}
+
+impl From<Vec<Json>> for Json {
+    fn from(items: Vec<Json>) -> Json {
+        Json::Array(items)
+    }
+}
+
+impl<T: Into<Json>> From<Option<T>> for Json {
+    fn from(value: Option<T>) -> Json {
+        value.map_or(Json::Null, Into::into)
+    }
+}
+
+/// Writes `text` as a JSON string
+pub fn write_string(output: &mut impl Write, text: &str) -> std::fmt::Result {
+    output.write_char('"')?;
+    for c in text.chars() {
+        match c {
+            '"' => output.write_str("\\\"")?,
+            '\\' => output.write_str("\\\\")?,
+            '\n' => output.write_str("\\n")?,
+            '\r' => output.write_str("\\r")?,
+            '\t' => output.write_str("\\t")?,
+            c if c.is_control() => write!(output, "\\u{:04x}", u32::from(c))?,
+            c => output.write_char(c)?,
+        }
+    }
+    output.write_char('"')
+}
+
+struct Reader<'t> {
+    text: &'t str,
+    pos: usize,
+    /// How many arrays and objects the reader is in
+    depth: usize,
+}
+
+impl Reader<'_> {
+    fn error(&self, expected: &'static str) -> JsonError {
+        JsonError {
+            offset: self.pos,
+            expected,
+        }
+    }
+    fn peek(&self) -> Option<char> {
+        self.text[self.pos..].chars().next()
+    }
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t' | '\n' | '\r')) {
+            self.pos += 1;
+        }
+    }
+    /// Skips `c` and the whitespace before it, if it's there
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_whitespace();
+        let found = self.peek() == Some(c);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+    fn keyword(&mut self, keyword: &'static str, json: Json) -> Result<Json, JsonError> {
+        if !self.text[self.pos..].starts_with(keyword) {
+            return Err(self.error(keyword));
+        }
+        self.pos += keyword.len();
+        Ok(json)
+    }
+    fn value(&mut self) -> Result<Json, JsonError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('n') => self.keyword("null", Json::Null),
+            Some('t') => self.keyword("true", Json::Bool(true)),
+            Some('f') => self.keyword("false", Json::Bool(false)),
+            Some('"') => self.string().map(Json::Str),
+            Some('[' | '{') if self.depth >= MAX_DEPTH => Err(self.error("less nesting")),
+            Some('[') => self.nested(Reader::array),
+            Some('{') => self.nested(Reader::object),
+            Some('-' | '0'..='9') => self.number(),
+            _ => Err(self.error("a value")),
+        }
+    }
+    fn nested(
+        &mut self,
+        read: fn(&mut Self) -> Result<Json, JsonError>,
+    ) -> Result<Json, JsonError> {
+        self.depth += 1;
+        let json = read(self);
+        self.depth -= 1;
+        json
+    }
+    fn array(&mut self) -> Result<Json, JsonError> {
+        self.pos += 1;
+        let mut items = vec![];
+        if self.eat(']') {
+            return Ok(Json::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            if self.eat(']') {
+                return Ok(Json::Array(items));
+            }
+            if !self.eat(',') {
+                return Err(self.error("`,` or `]`"));
+            }
+        }
+    }
+    fn object(&mut self) -> Result<Json, JsonError> {
+        self.pos += 1;
+        let mut fields = vec![];
+        if self.eat('}') {
+            return Ok(Json::Object(fields));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some('"') {
+                return Err(self.error("a key"));
+            }
+            let key = self.string()?;
+            if !self.eat(':') {
+                return Err(self.error("`:`"));
+            }
+            fields.push((key, self.value()?));
+            if self.eat('}') {
+                return Ok(Json::Object(fields));
+            }
+            if !self.eat(',') {
+                return Err(self.error("`,` or `}`"));
+            }
+        }
+    }
+    fn number(&mut self) -> Result<Json, JsonError> {
+        let start = self.pos;
+        while matches!(self.peek(), Some('-' | '+' | '.' | 'e' | 'E' | '0'..='9')) {
+            self.pos += 1;
+        }
+        self.text[start..self.pos]
+            .parse()
+            .map(Json::Num)
+            .map_err(|_| JsonError {
+                offset: start,
+                expected: "a number",
+            })
+    }
+    /// A string starting at the current `"`
+    fn string(&mut self) -> Result<String, JsonError> {
+        self.pos += 1;
+        let mut string = String::new();
+        loop {
+            match self.next() {
+                Some('"') => return Ok(string),
+                Some('\\') => {
+                    let escaped = match self.next() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('u') => self.unicode_escape()?,
+                        _ => return Err(self.error("an escape")),
+                    };
+                    string.push(escaped);
+                }
+                Some(c) if !c.is_control() => string.push(c),
+                _ => return Err(self.error("`\"`")),
+            }
+        }
+    }
+    /// The char of a `\u` escape, joining surrogate pairs
+    fn unicode_escape(&mut self) -> Result<char, JsonError> {
+        let high = self.hex4()?;
+        if !(0xD800..0xDC00).contains(&high) {
+            return char::from_u32(high).ok_or_else(|| self.error("a unicode scalar"));
+        }
+        if !self.text[self.pos..].starts_with("\\u") {
+            return Err(self.error("a low surrogate"));
+        }
+        self.pos += 2;
+        let low = self.hex4()?;
+        if !(0xDC00..0xE000).contains(&low) {
+            return Err(self.error("a low surrogate"));
+        }
+        char::from_u32(0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00))
+            .ok_or_else(|| self.error("a unicode scalar"))
+    }
+    fn hex4(&mut self) -> Result<u32, JsonError> {
+        let digits = self
+            .text
+            .get(self.pos..self.pos + 4)
+            .filter(|digits| digits.chars().all(|c| c.is_ascii_hexdigit()))
+            .ok_or_else(|| self.error("4 hex digits"))?;
+        let value = u32::from_str_radix(digits, 16).map_err(|_| self.error("4 hex digits"))?;
+        self.pos += 4;
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn printed_json_parses_back() {
+        let json = Json::object([
+            ("null", Json::Null),
+            ("bools", vec![true.into(), false.into()].into()),
+            (
+                "nums",
+                vec![Json::Num(-1.5), Json::Num(1e300), 7.into()].into(),
+            ),
+            (
+                "text",
+                "quote \" slash \\ newline \n tab \t bell \u{7} 😀".into(),
+            ),
+            ("nested", Json::object([("empty", Json::Array(vec![]))])),
+            ("", Json::object([])),
+        ]);
+        assert_eq!(Json::parse(&json.to_string()), Ok(json));
+    }
+
+    #[test]
+    fn escapes() {
+        let parsed = Json::parse(r#"["\/\b\fAé", "😀"]"#).unwrap();
+        assert_eq!(
+            parsed,
+            Json::Array(vec!["/\u{8}\u{c}Aé".into(), "😀".into()])
+        );
+        for (text, offset) in [
+            (r#""\ud83d""#, 7),
+            (r#""\ud83dA""#, 7),
+            (r#""\ud83d\u0041""#, 13),
+            (r#""\u+041""#, 3),
+            (r#""\q""#, 3),
+            ("\"tab\t\"", 5),
+        ] {
+            assert_eq!(
+                Json::parse(text).map_err(|e| e.offset),
+                Err(offset),
+                "{text}"
+            );
+        }
+    }
+
+    #[test]
+    fn errors_say_where_and_what() {
+        let error = |text| Json::parse(text).unwrap_err();
+        assert_eq!(
+            error("[1 2]"),
+            JsonError {
+                offset: 3,
+                expected: "`,` or `]`"
+            }
+        );
+        assert_eq!(error("{\"a\" 1}").expected, "`:`");
+        assert_eq!(error("{1: 2}").expected, "a key");
+        assert_eq!(error("nul").expected, "null");
+        assert_eq!(error("1 2").offset, 2);
+        assert_eq!(error("-").expected, "a number");
+        assert_eq!(error("").expected, "a value");
+    }
+
+    #[test]
+    fn deep_nesting_is_refused() {
+        let nested = |depth| "[".repeat(depth) + &"]".repeat(depth);
+        assert!(Json::parse(&nested(MAX_DEPTH)).is_ok());
+        assert_eq!(
+            Json::parse(&nested(MAX_DEPTH + 1)).unwrap_err(),
+            JsonError {
+                offset: MAX_DEPTH,
+                expected: "less nesting"
+            }
+        );
+        assert!(Json::parse(&"[".repeat(1_000_000)).is_err());
+    }
+
+    #[test]
+    fn usizes_are_whole_and_positive() {
+        assert_eq!(Json::Num(3.0).as_usize(), Some(3));
+        assert_eq!(Json::Num(3.5).as_usize(), None);
+        assert_eq!(Json::Num(-1.0).as_usize(), None);
+        assert_eq!(Json::Str("3".to_owned()).as_usize(), None);
+    }
+}
@@ -1,20 +1,46 @@
 use std::path::PathBuf;
+use std::rc::Rc;
 
-use self::parser::{Argument, Code, ExprCont};
+use self::parser::{Argument, Code, ExprCont, Template};
+use self::runtime::function::{Function, Param, Type};
+use self::runtime::macros::Macro;
+use self::runtime::macros::composer::MacroInput;
+use self::runtime::object::Object;
+use self::span::Span;
 use self::tokenizer::Token;
+pub mod cst;
+pub mod diagnostic;
 pub mod display;
+pub mod dump;
+pub mod format;
+pub mod json;
+pub mod lsp;
 pub mod parser;
+pub mod repl;
 pub mod runtime;
+pub mod span;
+pub mod stdlib;
 pub mod tokenizer;
 
+#[cfg(test)]
+mod testing;
+
 #[derive(Debug, Clone)]
 pub enum Value {
     Bool(bool),
     Str(String),
     Num(f64),
+    Int(i64),
     List(Vec<Value>),
     //Ident(FnName),
     Code(Code),
+    Template(Template),
+    Type(Type),
+    Param(Param),
+    Func(Function),
+    Object(Rc<Object<Function>>),
+    MacroInput(MacroInput),
+    Macro(Macro),
 }
 
 impl PartialEq for Value {
@@ -22,24 +48,71 @@ impl PartialEq for Value {
         match (self, other) {
             (Value::Bool(a), Value::Bool(b)) => a == b,
             (Value::Num(a), Value::Num(b)) => a == b,
+            (Value::Int(a), Value::Int(b)) => a == b,
+            (Value::Int(int), Value::Num(num)) | (Value::Num(num), Value::Int(int)) => {
+                exact_int(*num) == Some(*int)
+            }
             (Value::Str(a), Value::Str(b)) => a == b,
-            (Value::List(_), _) => false,
-            (Value::Code(_), _) => false,
+            (Value::List(a), Value::List(b)) => a == b,
+            // Code has no identity, so it's equal to code that prints the same
+            (Value::Code(a), Value::Code(b)) => a.to_string() == b.to_string(),
+            (Value::Template(a), Value::Template(b)) => a.to_string() == b.to_string(),
+            (Value::Type(a), Value::Type(b)) => a == b,
+            (Value::Object(a), Value::Object(b)) => Rc::ptr_eq(a, b),
+            (Value::Param(_) | Value::Func(_) | Value::MacroInput(_) | Value::Macro(_), _) => false,
             _ => false,
         }
     }
 }
 
+/// `num` as an integer when it's whole and fits in one, so ints compare exactly with floats
+#[allow(clippy::cast_possible_truncation)]
+fn exact_int(num: f64) -> Option<i64> {
+    // -2^63 and 2^63 are exact floats, every whole float between them fits
+    const BOUND: f64 = 9_223_372_036_854_775_808.0;
+    (num.fract() == 0.0 && (-BOUND..BOUND).contains(&num)).then_some(num as i64)
+}
+
 #[derive(Debug)]
 pub enum LyssCompError {
     CodeWithoutRootAtom {
-        first_token: Option<Token>,
+        first_token: Option<Box<Token>>,
+    },
+    ParseFloat {
+        span: Span,
+        error: std::num::ParseFloatError,
+    },
+    ParseInt {
+        span: Span,
+        error: std::num::ParseIntError,
     },
-    ParseFloat(std::num::ParseFloatError),
     CantStopToken {
-        line: usize,
+        span: Span,
         file: PathBuf,
-        tokenizer_state: tokenizer::State,
+        tokenizer_state: Box<tokenizer::State>,
+    },
+    UnexpectedChar {
+        span: Span,
+        found: char,
+        tokenizer_state: Box<tokenizer::State>,
+    },
+    /// A string without its closing `"`, `span` is where it starts
+    UnterminatedString {
+        span: Span,
+    },
+    UnexpectedToken {
+        found: Box<Token>,
+        expected: &'static str,
+    },
+    /// The code ended before `expected`, `span` is what was left open
+    UnexpectedEof {
+        span: Span,
+        expected: &'static str,
+    },
+    /// A `\` escape in a string that doesn't make a char
+    InvalidEscape {
+        span: Span,
+        reason: &'static str,
     },
 }
 
@@ -59,10 +132,10 @@ pub enum LyssRuntimeError {
     },
     LiteralNotFound {
         expected: String,
-        got: Argument,
+        got: Box<Argument>,
     },
     UnexpectedArg {
-        arg: Argument,
+        arg: Box<Argument>,
         expected: &'static str,
     },
     NeedsArg,
@@ -78,4 +151,93 @@ pub enum LyssRuntimeError {
         got: Vec<Argument>,
         could_usize: Vec<usize>,
     },
+    MacroNotFound {
+        name: String,
+    },
+    MacroContent {
+        name: String,
+        error: Box<LyssCompError>,
+    },
+    MacroDepthLimit {
+        name: String,
+    },
+    /// A macro used where a value is expected expanded to no expressions
+    EmptyExpansion {
+        name: String,
+    },
+    UnfilledHole {
+        name: String,
+    },
+    ReentrantHostFunc,
+    WrongType {
+        expected: Type,
+        got: Value,
+    },
+    /// Raised by `return`, unwinds up to the function being called
+    Return {
+        value: Value,
+    },
+    /// Raised by `exit`, unwinds out of the whole script so the host can end with `code`
+    Exit {
+        code: i32,
+    },
+    MacroMismatch {
+        name: String,
+        expected: String,
+        got: Option<Box<Token>>,
+    },
+    /// Ran an error node left by the parser
+    SyntaxError,
+    /// Script function calls nested deeper than [`runtime::function::CALL_DEPTH_LIMIT`]
+    CallDepthLimit,
+    /// Another error, raised while running the code at `span`
+    Located {
+        span: Span,
+        error: Box<LyssRuntimeError>,
+    },
+}
+
+impl LyssRuntimeError {
+    /// Attaches `span` to the error, unless it already has a location or is a `return` or
+    /// `exit` that is still unwinding
+    #[must_use]
+    pub fn located(self, span: Span) -> LyssRuntimeError {
+        match self {
+            LyssRuntimeError::Located { .. }
+            | LyssRuntimeError::Return { .. }
+            | LyssRuntimeError::Exit { .. } => self,
+            error => LyssRuntimeError::Located {
+                span,
+                error: Box::new(error),
+            },
+        }
+    }
+    /// The error without its location
+    #[must_use]
+    pub fn unlocated(&self) -> &LyssRuntimeError {
+        match self {
+            LyssRuntimeError::Located { error, .. } => error.unlocated(),
+            error => error,
+        }
+    }
+}
+
+impl std::error::Error for LyssCompError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LyssCompError::ParseFloat { error, .. } => Some(error),
+            LyssCompError::ParseInt { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl std::error::Error for LyssRuntimeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LyssRuntimeError::MacroContent { error, .. } => Some(error.as_ref()),
+            LyssRuntimeError::Located { error, .. } => error.source(),
+            _ => None,
+        }
+    }
 }
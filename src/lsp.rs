@@ -0,0 +1,392 @@
+//! `lyss lsp`, a language server speaking JSON-RPC over stdin and stdout
+//!
+//! Documents are synced whole on every change. Diagnostics come from the tokenizer and parser,
+//! hover and completion from the functions of the standard library, and definitions from the
+//! `local`, `extern` and `defn` calls of the document, see [`analysis`].
+
+pub mod analysis;
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+use crate::json::Json;
+use crate::runtime::HostContext;
+use crate::{LyssRuntimeError, stdlib};
+use analysis::Document;
+
+/// The longest message body read, a `Content-Length` over it is refused before allocating it
+const MAX_MESSAGE_LENGTH: usize = 64 << 20;
+
+/// An error answering a request, sent back to the client with one of the JSON-RPC codes
+#[derive(Debug)]
+struct RpcError {
+    code: i32,
+    message: String,
+}
+
+impl RpcError {
+    const PARSE_ERROR: i32 = -32700;
+    const INVALID_REQUEST: i32 = -32600;
+    const METHOD_NOT_FOUND: i32 = -32601;
+    const INVALID_PARAMS: i32 = -32602;
+
+    fn new(code: i32, message: impl Into<String>) -> RpcError {
+        RpcError {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Server {
+    /// Holds the functions names are resolved against, never runs anything
+    library: HostContext,
+    /// Open documents by URI
+    documents: HashMap<String, Document>,
+    shutdown: bool,
+}
+
+impl Server {
+    pub fn new() -> Result<Server, LyssRuntimeError> {
+        let mut library = HostContext::new();
+        stdlib::install(&mut library)?;
+        Ok(Server {
+            library,
+            documents: HashMap::new(),
+            shutdown: false,
+        })
+    }
+    /// The exit code once the client asked to exit, 0 only if it asked to shut down first
+    #[must_use]
+    pub fn exit_code(&self) -> i32 {
+        i32::from(!self.shutdown)
+    }
+    /// Handles a message, giving the messages to send back or `None` when the client asked the
+    /// server to exit
+    pub fn handle(&mut self, message: &Json) -> Option<Vec<Json>> {
+        let method = message.get("method").and_then(Json::as_str).unwrap_or("");
+        let params = message.get("params").unwrap_or(&Json::Null);
+        match message.get("id") {
+            Some(id) => {
+                let result = if self.shutdown {
+                    Err(RpcError::new(RpcError::INVALID_REQUEST, "shutting down"))
+                } else {
+                    self.request(method, params)
+                };
+                Some(vec![response(id.clone(), result)])
+            }
+            None if method == "exit" => None,
+            None => Some(self.notification(method, params)),
+        }
+    }
+    fn request(&mut self, method: &str, params: &Json) -> Result<Json, RpcError> {
+        let library = &self.library.functions;
+        match method {
+            "initialize" => Ok(capabilities()),
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Json::Null)
+            }
+            "textDocument/hover" => {
+                let (_, document, offset) = self.position(params)?;
+                Ok(document.hover(library, offset).into())
+            }
+            "textDocument/definition" => {
+                let (uri, document, offset) = self.position(params)?;
+                Ok(document
+                    .definition(offset)
+                    .map(|range| Json::object([("uri", uri.into()), ("range", range)]))
+                    .into())
+            }
+            "textDocument/completion" => {
+                let (_, document, offset) = self.position(params)?;
+                Ok(document.completion(library, offset).into())
+            }
+            "textDocument/documentSymbol" => {
+                let (_, document) = self.document(params)?;
+                Ok(document.symbols(library).into())
+            }
+            _ => Err(RpcError::new(
+                RpcError::METHOD_NOT_FOUND,
+                format!("unknown method `{method}`"),
+            )),
+        }
+    }
+    /// Keeps the documents up to date, publishing their diagnostics when they change
+    fn notification(&mut self, method: &str, params: &Json) -> Vec<Json> {
+        let Some(uri) = params.at(&["textDocument", "uri"]).and_then(Json::as_str) else {
+            return vec![];
+        };
+        let text = match method {
+            "textDocument/didOpen" => params.at(&["textDocument", "text"]),
+            // Only whole documents are synced, so the last change is the whole text
+            "textDocument/didChange" => params
+                .get("contentChanges")
+                .and_then(Json::as_array)
+                .and_then(<[Json]>::last)
+                .and_then(|change| change.get("text")),
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+                return vec![publish_diagnostics(uri, vec![])];
+            }
+            _ => return vec![],
+        };
+        let Some(text) = text.and_then(Json::as_str) else {
+            return vec![];
+        };
+        let document = Document::new(text.to_owned(), Path::new(uri), &self.library.functions);
+        let diagnostics = document.diagnostics();
+        self.documents.insert(uri.to_owned(), document);
+        vec![publish_diagnostics(uri, diagnostics)]
+    }
+    fn document(&self, params: &Json) -> Result<(&str, &Document), RpcError> {
+        let uri = params
+            .at(&["textDocument", "uri"])
+            .and_then(Json::as_str)
+            .ok_or_else(|| RpcError::new(RpcError::INVALID_PARAMS, "missing the document"))?;
+        let (uri, document) = self.documents.get_key_value(uri).ok_or_else(|| {
+            RpcError::new(RpcError::INVALID_PARAMS, format!("`{uri}` is not open"))
+        })?;
+        Ok((uri, document))
+    }
+    /// The document of a request and the byte offset of its position
+    fn position(&self, params: &Json) -> Result<(&str, &Document, usize), RpcError> {
+        let (uri, document) = self.document(params)?;
+        let offset = params
+            .get("position")
+            .and_then(|position| document.offset(position))
+            .ok_or_else(|| RpcError::new(RpcError::INVALID_PARAMS, "invalid position"))?;
+        Ok((uri, document, offset))
+    }
+}
+
+fn capabilities() -> Json {
+    Json::object([
+        (
+            "capabilities",
+            Json::object([
+                ("textDocumentSync", 1.into()),
+                ("hoverProvider", true.into()),
+                ("definitionProvider", true.into()),
+                (
+                    "completionProvider",
+                    Json::object([("triggerCharacters", vec![".".into()].into())]),
+                ),
+                ("documentSymbolProvider", true.into()),
+            ]),
+        ),
+        (
+            "serverInfo",
+            Json::object([
+                ("name", "lyss".into()),
+                ("version", env!("CARGO_PKG_VERSION").into()),
+            ]),
+        ),
+    ])
+}
+
+fn response(id: Json, result: Result<Json, RpcError>) -> Json {
+    let outcome = match result {
+        Ok(result) => ("result", result),
+        Err(error) => (
+            "error",
+            Json::object([
+                ("code", Json::Num(f64::from(error.code))),
+                ("message", error.message.into()),
+            ]),
+        ),
+    };
+    Json::object([("jsonrpc", "2.0".into()), ("id", id), outcome])
+}
+
+fn publish_diagnostics(uri: &str, diagnostics: Vec<Json>) -> Json {
+    Json::object([
+        ("jsonrpc", "2.0".into()),
+        ("method", "textDocument/publishDiagnostics".into()),
+        (
+            "params",
+            Json::object([("uri", uri.into()), ("diagnostics", diagnostics.into())]),
+        ),
+    ])
+}
+
+/// Serves the client on `input` and `output` until it asks to exit, giving the exit code
+pub fn serve(mut input: impl BufRead, mut output: impl Write) -> io::Result<i32> {
+    let mut server = Server::new().map_err(|error| io::Error::other(error.to_string()))?;
+    while let Some(body) = read_message(&mut input)? {
+        let replies = match Json::parse(&body) {
+            Ok(message) => match server.handle(&message) {
+                Some(replies) => replies,
+                None => return Ok(server.exit_code()),
+            },
+            Err(error) => vec![response(
+                Json::Null,
+                Err(RpcError::new(RpcError::PARSE_ERROR, error.to_string())),
+            )],
+        };
+        for reply in replies {
+            write_message(&mut output, &reply)?;
+        }
+    }
+    Ok(server.exit_code())
+}
+
+/// Reads the body of the next message, `None` at the end of the input
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        match line.split_once(':') {
+            Some((name, value)) if name.eq_ignore_ascii_case("Content-Length") => {
+                length = value.trim().parse::<usize>().ok();
+            }
+            _ => {}
+        }
+    }
+    let length = length.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "message without a Content-Length",
+        )
+    })?;
+    if length > MAX_MESSAGE_LENGTH {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("message of {length} bytes, more than the {MAX_MESSAGE_LENGTH} allowed"),
+        ));
+    }
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    String::from_utf8(body)
+        .map(Some)
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+}
+
+fn write_message(output: &mut impl Write, message: &Json) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+    output.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    /// `messages` framed like a client sends them
+    fn framed(messages: &[&str]) -> Vec<u8> {
+        messages
+            .iter()
+            .flat_map(|body| format!("Content-Length: {}\r\n\r\n{body}", body.len()).into_bytes())
+            .collect()
+    }
+
+    /// Serves `messages`, giving the exit code and the messages sent back
+    fn session(messages: &[&str]) -> (i32, Vec<Json>) {
+        let mut output = vec![];
+        let code = serve(Cursor::new(framed(messages)), &mut output).expect("the session runs");
+        let mut output = Cursor::new(output);
+        let mut replies = vec![];
+        while let Some(body) = read_message(&mut output).expect("the replies are framed") {
+            replies.push(Json::parse(&body).expect("the replies are JSON"));
+        }
+        (code, replies)
+    }
+
+    #[test]
+    fn scripted_session() {
+        let (code, replies) = session(&[
+            r#"{"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {}}"#,
+            r#"{"jsonrpc": "2.0", "method": "initialized", "params": {}}"#,
+            r#"{"jsonrpc": "2.0", "method": "textDocument/didOpen", "params": {"textDocument":
+                {"uri": "file:///a.ls", "languageId": "lyss", "version": 1,
+                 "text": "(Builtin.print \"hi\" )\n(print "}}}"#,
+            r#"{"jsonrpc": "2.0", "id": 2, "method": "textDocument/hover", "params": {
+                "textDocument": {"uri": "file:///a.ls"}, "position": {"line": 0, "character": 3}}}"#,
+            r#"{"jsonrpc": "2.0", "id": 3, "method": "textDocument/hover", "params": {
+                "textDocument": {"uri": "file:///b.ls"}, "position": {"line": 0, "character": 0}}}"#,
+            r#"{"jsonrpc": "2.0", "id": 4, "method": "shutdown"}"#,
+            r#"{"jsonrpc": "2.0", "id": 5, "method": "textDocument/hover", "params": {}}"#,
+            r#"{"jsonrpc": "2.0", "method": "exit"}"#,
+        ]);
+        assert_eq!(code, 0);
+        let [
+            initialize,
+            diagnostics,
+            hover,
+            not_open,
+            shutdown,
+            after_shutdown,
+        ] = &replies[..]
+        else {
+            panic!("{replies:?}");
+        };
+        assert_eq!(initialize.get("id").and_then(Json::as_usize), Some(1));
+        assert!(
+            initialize
+                .at(&["result", "capabilities", "hoverProvider"])
+                .is_some()
+        );
+        assert_eq!(
+            diagnostics.get("method").and_then(Json::as_str),
+            Some("textDocument/publishDiagnostics")
+        );
+        let published = diagnostics
+            .at(&["params", "diagnostics"])
+            .and_then(Json::as_array)
+            .expect("diagnostics are published");
+        assert_eq!(published.len(), 1);
+        assert_eq!(
+            published[0]
+                .at(&["range", "start", "line"])
+                .and_then(Json::as_usize),
+            Some(1)
+        );
+        assert_eq!(
+            hover
+                .at(&["result", "contents", "value"])
+                .and_then(Json::as_str),
+            Some("`Builtin.print` function")
+        );
+        assert_eq!(
+            not_open.at(&["error", "message"]).and_then(Json::as_str),
+            Some("`file:///b.ls` is not open")
+        );
+        assert_eq!(shutdown.get("id").and_then(Json::as_usize), Some(4));
+        assert_eq!(
+            after_shutdown
+                .at(&["error", "message"])
+                .and_then(Json::as_str),
+            Some("shutting down")
+        );
+    }
+
+    #[test]
+    fn exiting_without_shutting_down_fails() {
+        let (code, replies) = session(&[r#"{"jsonrpc": "2.0", "method": "exit"}"#]);
+        assert_eq!((code, replies.len()), (1, 0));
+    }
+
+    #[test]
+    fn malformed_messages() {
+        let (_, replies) = session(&["{not json"]);
+        assert!(replies[0].at(&["error", "code"]).is_some());
+        for input in [
+            "Content-Type: text\r\n\r\n{}",
+            "Content-Length: 18446744073709551615\r\n\r\n{}",
+        ] {
+            let error = serve(Cursor::new(input), vec![]).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+}
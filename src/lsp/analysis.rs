@@ -0,0 +1,552 @@
+//! What the language server knows about an open document, read from its tokens and AST
+//! without running anything
+//!
+//! Names are resolved the way [`Context::resolve_path`](crate::runtime::Context::resolve_path)
+//! does, against the functions of the library and the scopes and aliases the document brings in
+//! at its top level.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::diagnostic::Diagnostic;
+use crate::json::Json;
+use crate::parser::{self, Argument, ArgumentCont, Atom, Expr, ExprCont};
+use crate::runtime::function::Function;
+use crate::runtime::object::{Object, ObjectEntry, ObjectSearch};
+use crate::span::Span;
+use crate::tokenizer::syntax::Syntax;
+use crate::tokenizer::{self, Token, TokenCont};
+use crate::{LyssCompError, Value};
+
+/// The `SymbolKind`s of the protocol used for document symbols
+mod symbol_kind {
+    pub const NAMESPACE: usize = 3;
+    pub const METHOD: usize = 6;
+    pub const FUNCTION: usize = 12;
+    pub const VARIABLE: usize = 13;
+    pub const OPERATOR: usize = 25;
+}
+
+/// The `CompletionItemKind`s of the protocol used for completions
+mod completion_kind {
+    pub const FUNCTION: usize = 3;
+    pub const VARIABLE: usize = 6;
+    pub const MODULE: usize = 9;
+}
+
+#[derive(Debug)]
+pub struct Document {
+    pub text: String,
+    /// Byte offset of the start of every line
+    line_starts: Vec<usize>,
+    tokens: Vec<Token>,
+    exprs: Vec<Expr>,
+    errors: Vec<LyssCompError>,
+    names: Names,
+}
+
+/// The names a document brings in, read from its `Builtin.scope`, `Builtin.alias`,
+/// `Builtin.defn`, `Builtin.local` and `Builtin.extern` calls
+#[derive(Debug, Default)]
+struct Names {
+    /// Paths of the objects in scope, in the order they were added
+    scopes: Vec<Vec<String>>,
+    aliases: HashMap<String, Vec<String>>,
+    /// Functions and the span of the string naming them
+    functions: Vec<(String, Span)>,
+    /// Variables and the span of the variable being set
+    variables: Vec<(String, Span)>,
+}
+
+impl Document {
+    #[must_use]
+    pub fn new(text: String, file_name: &Path, library: &Object<Function>) -> Document {
+        let (tokens, mut errors) = tokenizer::tokenize(&text, file_name, 0);
+        let (exprs, parse_errors) = parser::parse(&mut tokens.clone().into_iter());
+        errors.extend(parse_errors);
+        let names = Names::read(&exprs, library);
+        let line_starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Document {
+            text,
+            line_starts,
+            tokens,
+            exprs,
+            errors,
+            names,
+        }
+    }
+    /// The errors of the tokenizer and parser, as LSP diagnostics
+    #[must_use]
+    pub fn diagnostics(&self) -> Vec<Json> {
+        self.errors
+            .iter()
+            .map(|error| {
+                let diagnostic = Diagnostic::from(error);
+                let mut message = diagnostic.message;
+                for note in &diagnostic.notes {
+                    message.push_str("\nnote: ");
+                    message.push_str(note);
+                }
+                if let Some(help) = &diagnostic.help {
+                    message.push_str("\nhelp: ");
+                    message.push_str(help);
+                }
+                let span = diagnostic.span.unwrap_or(Span::file_start(0));
+                Json::object([
+                    ("range", self.range(span)),
+                    ("severity", 1.into()),
+                    ("code", diagnostic.code.into()),
+                    ("source", "lyss".into()),
+                    ("message", message.into()),
+                ])
+            })
+            .collect()
+    }
+    /// What the name under `offset` resolves to
+    #[must_use]
+    pub fn hover(&self, library: &Object<Function>, offset: usize) -> Option<Json> {
+        let (token, path) = self.name_at(offset)?;
+        let text = if let Some(name) = var_name(&path) {
+            match self.names.variables.iter().find(|(var, _)| var == name) {
+                Some((_, span)) => format!("`$.{name}` variable, set on line {}", span.line),
+                None => format!("`$.{name}` variable"),
+            }
+        } else if let Some(resolved) = self.names.resolve(library, &path) {
+            match library.find(&resolved) {
+                Ok(ObjectSearch::Branch(object)) => {
+                    format!(
+                        "`{}` object of {} entries",
+                        resolved.join("."),
+                        object.0.len()
+                    )
+                }
+                _ => format!("`{}` function", resolved.join(".")),
+            }
+        } else {
+            let name = path.join(".");
+            let (_, span) = self
+                .names
+                .functions
+                .iter()
+                .find(|(func, _)| *func == name)?;
+            format!("`{name}` function, defined on line {}", span.line)
+        };
+        Some(Json::object([
+            (
+                "contents",
+                Json::object([("kind", "markdown".into()), ("value", text.into())]),
+            ),
+            ("range", self.range(token.span)),
+        ]))
+    }
+    /// Where the variable or function under `offset` is set, the closest definition before it
+    /// or the first one when they all come after it
+    #[must_use]
+    pub fn definition(&self, offset: usize) -> Option<Json> {
+        let (_, path) = self.name_at(offset)?;
+        let definitions = match var_name(&path) {
+            Some(_) => &self.names.variables,
+            None => &self.names.functions,
+        };
+        let name = var_name(&path).map_or_else(|| path.join("."), str::to_owned);
+        let spans: Vec<Span> = definitions
+            .iter()
+            .filter(|(defined, _)| *defined == name)
+            .map(|(_, span)| *span)
+            .collect();
+        let span = spans
+            .iter()
+            .rev()
+            .find(|span| span.start_byte <= offset)
+            .or(spans.first())?;
+        Some(self.range(*span))
+    }
+    /// The entries of the object before the last `.` typed before `offset`. Without a `.`, the
+    /// names reachable directly, and the variables after `$.`
+    #[must_use]
+    pub fn completion(&self, library: &Object<Function>, offset: usize) -> Vec<Json> {
+        let typed = self.typed_path(offset);
+        let head = typed.rsplit_once('.').map_or("", |(head, _)| head);
+        let mut items: Vec<(String, usize, String)> = vec![];
+        if head == "$" {
+            for (name, _) in &self.names.variables {
+                items.push((name.clone(), completion_kind::VARIABLE, format!("$.{name}")));
+            }
+        } else if head.is_empty() {
+            entries(library, &[], &mut items);
+            for scope in &self.names.scopes {
+                entries(library, scope, &mut items);
+            }
+            for (alias, target) in &self.names.aliases {
+                items.push((alias.clone(), completion_kind::MODULE, target.join(".")));
+            }
+            for (name, _) in &self.names.functions {
+                items.push((name.clone(), completion_kind::FUNCTION, name.clone()));
+            }
+        } else {
+            let head: Vec<String> = head.split('.').map(str::to_owned).collect();
+            if let Some(object) = self.names.resolve(library, &head) {
+                entries(library, &object, &mut items);
+            }
+        }
+        items.sort();
+        items.dedup_by(|a, b| a.0 == b.0);
+        items
+            .into_iter()
+            .map(|(label, kind, detail)| {
+                Json::object([
+                    ("label", label.into()),
+                    ("kind", kind.into()),
+                    ("detail", detail.into()),
+                ])
+            })
+            .collect()
+    }
+    /// A symbol for every top-level atom and macro, named after what it defines if it defines
+    /// something
+    #[must_use]
+    pub fn symbols(&self, library: &Object<Function>) -> Vec<Json> {
+        let mut symbols = vec![];
+        for expr in &self.exprs {
+            let (name, kind, selection) = match &expr.cont {
+                ExprCont::Atom(atom) => self.names.symbol(library, atom, expr.span),
+                ExprCont::Macro(macro_use) => (
+                    format!("!({})", macro_use.name),
+                    symbol_kind::OPERATOR,
+                    expr.span,
+                ),
+                ExprCont::Hole(_) | ExprCont::Error => continue,
+            };
+            symbols.push(Json::object([
+                ("name", name.into()),
+                ("kind", kind.into()),
+                ("range", self.range(expr.span)),
+                ("selectionRange", self.range(selection)),
+            ]));
+        }
+        symbols
+    }
+    /// The byte offset of an LSP position, whose character counts UTF-16 units
+    #[must_use]
+    pub fn offset(&self, position: &Json) -> Option<usize> {
+        let line = position.get("line")?.as_usize()?;
+        let character = position.get("character")?.as_usize()?;
+        let start = *self.line_starts.get(line)?;
+        let mut units = 0;
+        for (i, c) in self.text[start..].char_indices() {
+            if units >= character || c == '\n' {
+                return Some(start + i);
+            }
+            units += c.len_utf16();
+        }
+        Some(self.text.len())
+    }
+    fn position(&self, byte: usize) -> Json {
+        let line = self.line_starts.partition_point(|start| *start <= byte) - 1;
+        let start = self.line_starts[line];
+        let character = self
+            .text
+            .get(start..byte)
+            .map_or(0, |text| text.encode_utf16().count());
+        Json::object([("line", line.into()), ("character", character.into())])
+    }
+    #[must_use]
+    pub fn range(&self, span: Span) -> Json {
+        Json::object([
+            ("start", self.position(span.start_byte)),
+            ("end", self.position(span.end_byte)),
+        ])
+    }
+    /// The identifier or path token under `offset`, touching its end counts as under it
+    fn name_at(&self, offset: usize) -> Option<(&Token, Vec<String>)> {
+        self.tokens.iter().find_map(|token| {
+            if token.span.start_byte > offset || token.span.end_byte < offset {
+                return None;
+            }
+            match &token.content {
+                TokenCont::Ident(name) => Some((token, vec![name.clone()])),
+                TokenCont::Path(path) => Some((token, path.clone())),
+                _ => None,
+            }
+        })
+    }
+    /// The part of a path typed right before `offset`
+    fn typed_path(&self, offset: usize) -> &str {
+        let syntax = Syntax::default();
+        let before = &self.text[..offset.min(self.text.len())];
+        let start = before
+            .char_indices()
+            .rev()
+            .take_while(|(_, c)| *c == '.' || syntax.is_ident_continue(*c))
+            .last()
+            .map_or(before.len(), |(i, _)| i);
+        &before[start..]
+    }
+}
+
+impl Names {
+    fn read(exprs: &[Expr], library: &Object<Function>) -> Names {
+        let mut names = Names::default();
+        let mut atoms = vec![];
+        collect_atoms(exprs, true, &mut atoms);
+        for (atom, top) in atoms {
+            let Some(callee) = names.resolve(library, &atom.fn_name.0) else {
+                continue;
+            };
+            let first = atom.arguments.first();
+            let second = atom.arguments.get(1).map(|arg| &arg.cont);
+            match (callee.join(".").as_str(), first.map(|arg| &arg.cont)) {
+                ("Builtin.scope", Some(ArgumentCont::Ident(path))) if top => {
+                    if let Some(scope) = names.resolve(library, &path.0) {
+                        names.scopes.push(scope);
+                    }
+                }
+                ("Builtin.alias", Some(ArgumentCont::Ident(target))) if top => {
+                    if let (Some(target), Some(ArgumentCont::Ident(name))) =
+                        (names.resolve(library, &target.0), second)
+                    {
+                        names.aliases.insert(name.to_string(), target);
+                    }
+                }
+                (
+                    "Builtin.local" | "Builtin.extern",
+                    Some(ArgumentCont::Var(name) | ArgumentCont::Value(Value::Str(name))),
+                ) => names.variables.push((name.clone(), first_span(atom))),
+                ("Builtin.defn", Some(ArgumentCont::Value(Value::Str(name)))) => {
+                    names.functions.push((name.clone(), first_span(atom)));
+                }
+                _ => {}
+            }
+        }
+        names
+    }
+    /// The full path in `library` that `path` refers to, trying the scopes first, then the
+    /// aliases and then the path as it is
+    fn resolve(&self, library: &Object<Function>, path: &[String]) -> Option<Vec<String>> {
+        let (head, rest) = path.split_first()?;
+        self.scopes
+            .iter()
+            .map(|scope| [scope.as_slice(), path].concat())
+            .chain(
+                self.aliases
+                    .get(head)
+                    .map(|target| [target.as_slice(), rest].concat()),
+            )
+            .chain(std::iter::once(path.to_vec()))
+            .find(|full| library.find(full).is_ok())
+    }
+    /// The name, kind and name span of the symbol for a top-level atom
+    fn symbol(&self, library: &Object<Function>, atom: &Atom, span: Span) -> (String, usize, Span) {
+        let callee = self
+            .resolve(library, &atom.fn_name.0)
+            .map(|path| path.join("."));
+        let first = atom.arguments.first().map(|arg| (&arg.cont, arg.span));
+        match (callee.as_deref(), first) {
+            (Some("Builtin.local" | "Builtin.extern"), Some((ArgumentCont::Var(name), at))) => {
+                (format!("$.{name}"), symbol_kind::VARIABLE, at)
+            }
+            (
+                Some("Builtin.defn" | "Builtin.Macro.def"),
+                Some((ArgumentCont::Value(Value::Str(name)), at)),
+            ) => (name.clone(), symbol_kind::FUNCTION, at),
+            (Some("Builtin.scope"), Some((ArgumentCont::Ident(path), at))) => {
+                (path.to_string(), symbol_kind::NAMESPACE, at)
+            }
+            (Some("Builtin.alias"), _) => match atom.arguments.get(1) {
+                Some(Argument {
+                    cont: ArgumentCont::Ident(name),
+                    span: at,
+                }) => (name.to_string(), symbol_kind::NAMESPACE, *at),
+                _ => (atom.fn_name.to_string(), symbol_kind::METHOD, span),
+            },
+            _ => (atom.fn_name.to_string(), symbol_kind::METHOD, span),
+        }
+    }
+}
+
+/// `Some(name)` for a `$.name` path
+fn var_name(path: &[String]) -> Option<&str> {
+    match path {
+        [dollar, name] if dollar == "$" => Some(name),
+        _ => None,
+    }
+}
+
+fn first_span(atom: &Atom) -> Span {
+    atom.arguments.first().map_or(atom.span, |arg| arg.span)
+}
+
+/// Adds the entries of the object at `path` as completions
+fn entries(library: &Object<Function>, path: &[String], items: &mut Vec<(String, usize, String)>) {
+    let object = if path.is_empty() {
+        library
+    } else {
+        match library.find_branch(path) {
+            Ok(object) => object,
+            Err(_) => return,
+        }
+    };
+    for (name, entry) in &object.0 {
+        let kind = match entry {
+            ObjectEntry::Branch(_) => completion_kind::MODULE,
+            ObjectEntry::Leaf(_) => completion_kind::FUNCTION,
+        };
+        let full = path.iter().chain(std::iter::once(name)).cloned();
+        items.push((name.clone(), kind, full.collect::<Vec<_>>().join(".")));
+    }
+}
+
+/// Every atom in `exprs` in source order, with whether it's at the top level
+fn collect_atoms<'e>(exprs: &'e [Expr], top: bool, atoms: &mut Vec<(&'e Atom, bool)>) {
+    for expr in exprs {
+        if let ExprCont::Atom(atom) = &expr.cont {
+            atoms.push((atom, top));
+            collect_argument_atoms(&atom.arguments, atoms);
+        }
+    }
+}
+
+fn collect_argument_atoms<'e>(args: &'e [Argument], atoms: &mut Vec<(&'e Atom, bool)>) {
+    for argument in args {
+        match &argument.cont {
+            ArgumentCont::Atom(atom) => {
+                atoms.push((atom, false));
+                collect_argument_atoms(&atom.arguments, atoms);
+            }
+            ArgumentCont::List(items) => collect_argument_atoms(items, atoms),
+            ArgumentCont::Value(Value::Code(code)) => collect_atoms(&code.exprs, false, atoms),
+            ArgumentCont::Value(Value::Template(template)) => {
+                collect_atoms(&template.exprs, false, atoms);
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::library;
+
+    const SOURCE: &str = "(Builtin.scope Builtin )
+(local $.x 1 )
+(defn \"twice\" (list (Types.int \"n\" ) ) Types.int '( (Math.* $.n 2 ) )' )
+(print $.x (twice 2 ) )
+(local $.x \"😀\" )
+(print $.x )
+";
+
+    fn with_document(source: &str, test: impl FnOnce(&Document, &Object<Function>)) {
+        let ctx = library();
+        let document = Document::new(source.to_owned(), Path::new("test.ls"), &ctx.functions);
+        test(&document, &ctx.functions);
+    }
+
+    /// The byte offset of the `nth` occurrence of `needle`
+    fn offset_of(source: &str, needle: &str, nth: usize) -> usize {
+        source
+            .match_indices(needle)
+            .nth(nth)
+            .expect("the needle is there")
+            .0
+    }
+
+    fn labels(items: &[Json]) -> Vec<&str> {
+        items
+            .iter()
+            .filter_map(|item| item.get("label").and_then(Json::as_str))
+            .collect()
+    }
+
+    #[test]
+    fn hover_resolves_through_scopes() {
+        with_document(SOURCE, |document, library| {
+            let hover = |offset| {
+                document.hover(library, offset).and_then(|hover| {
+                    hover
+                        .at(&["contents", "value"])
+                        .and_then(Json::as_str)
+                        .map(str::to_owned)
+                })
+            };
+            let print = offset_of(SOURCE, "print", 0);
+            assert_eq!(hover(print).as_deref(), Some("`Builtin.print` function"));
+            let twice = offset_of(SOURCE, "twice", 1);
+            assert_eq!(
+                hover(twice).as_deref(),
+                Some("`twice` function, defined on line 3")
+            );
+            assert_eq!(
+                hover(offset_of(SOURCE, "$.x", 1)).as_deref(),
+                Some("`$.x` variable, set on line 2")
+            );
+            assert_eq!(hover(0), None);
+        });
+    }
+
+    #[test]
+    fn definitions_are_the_closest_before() {
+        with_document(SOURCE, |document, _| {
+            let line = |offset| {
+                document
+                    .definition(offset)
+                    .and_then(|range| range.at(&["start", "line"]).and_then(Json::as_usize))
+            };
+            assert_eq!(line(offset_of(SOURCE, "$.x", 1)), Some(1));
+            assert_eq!(line(offset_of(SOURCE, "$.x", 3)), Some(4));
+            assert_eq!(line(offset_of(SOURCE, "twice", 1)), Some(2));
+        });
+    }
+
+    #[test]
+    fn completion_lists_what_can_follow() {
+        let source = "(Builtin.scope Builtin )\n(local $.x 1 )\n(Builtin.Ma $. )";
+        with_document(source, |document, library| {
+            let after = |needle| offset_of(source, needle, 0) + needle.len();
+            let math = document.completion(library, after("Builtin.Ma"));
+            assert!(labels(&math).contains(&"Math"));
+            assert!(labels(&math).contains(&"print"));
+            let vars = document.completion(library, after("$."));
+            assert_eq!(labels(&vars), ["x"]);
+        });
+    }
+
+    #[test]
+    fn symbols_name_what_they_define() {
+        with_document(SOURCE, |document, library| {
+            let symbols = document.symbols(library);
+            let names: Vec<_> = symbols
+                .iter()
+                .filter_map(|symbol| symbol.get("name").and_then(Json::as_str))
+                .collect();
+            assert_eq!(names.len(), 6);
+            assert!(names.contains(&"twice"));
+        });
+    }
+
+    #[test]
+    fn positions_count_utf16_units() {
+        with_document(SOURCE, |document, _| {
+            let byte = offset_of(SOURCE, "\" )", 1);
+            let line_start = offset_of(SOURCE, "(local $.x \"", 0);
+            assert_eq!(byte - line_start, 16);
+            let position = Json::object([("line", 4.into()), ("character", 14.into())]);
+            assert_eq!(document.offset(&position), Some(byte));
+        });
+    }
+
+    #[test]
+    fn errors_become_diagnostics() {
+        with_document("(print \"\\q\" )\n(print ", |document, _| {
+            let lines: Vec<_> = document
+                .diagnostics()
+                .iter()
+                .filter_map(|diagnostic| diagnostic.at(&["range", "start", "line"]))
+                .filter_map(Json::as_usize)
+                .collect();
+            assert_eq!(lines, [0, 1]);
+        });
+    }
+}
@@ -1,172 +1,374 @@
-use std::collections::HashMap;
+//! The `lyss` command line
+
+use std::io::{BufRead, Read, Write};
 use std::path::PathBuf;
-use std::rc::Rc;
 
-use lyss::display::DisplayValue;
-use lyss::runtime::HostFunc;
-use lyss::runtime::api::Api;
-use lyss::runtime::object::ObjectEntry;
+use lyss::diagnostic::Diagnostic;
+use lyss::dump::{self, DumpFormat};
+use lyss::format::Formatter;
+use lyss::repl::{Repl, Reply};
+use lyss::runtime::{Engine, HostContext};
+use lyss::span::SourceMap;
 use lyss::{LyssRuntimeError, Value};
 
+const USAGE: &str = "\
+usage: lyss run [--engine <engine>] [--dump-..] [--json] <file> [-- args..]
+       lyss fmt [--check] <file>..
+       lyss repl
+       lyss lsp
+
+run  runs a script, `-` reads it from stdin. The arguments after the file are the script's
+     `$.args` list. --engine picks how it's run, `bytecode` by default or `tree` to walk
+     the AST like older versions did. These print to stderr while the script runs:
+       --dump-tokens    the tokens of the file
+       --dump-ast       the parsed file, also when it has errors
+       --dump-expanded  every macro expansion, in the order they ran
+       --dump-context   the context the script left behind
+     --json prints the tokens and trees as JSON
+fmt  rewrites files in the canonical layout, --check only reports the ones that aren't
+repl runs inputs one at a time in the same context, `:help` lists its commands. The inputs
+     are saved to $LYSS_HISTORY, ~/.lyss_history by default
+lsp  serves the language server protocol on stdin and stdout, for editors
+
+Exits with 0 when everything went fine, with the code a script gave `Builtin.exit`, with 1
+when a script or file had errors and with 2 when lyss was used wrong.";
+
+/// Scripts run on a thread with this much stack, enough for
+/// [`CALL_DEPTH_LIMIT`](lyss::runtime::function::CALL_DEPTH_LIMIT) nested calls even in debug
+/// builds, where every call takes tens of kilobytes
+const STACK_SIZE: usize = 256 << 20;
+
 fn main() {
-    let file_name = PathBuf::from("hello.ls");
-    let file = std::fs::read_to_string(&file_name).unwrap();
-    let o = lyss::tokenizer::tokenize(&file, &file_name);
-    let mut i = o.unwrap().into_iter();
-    let exprs = lyss::parser::parse(&mut i).unwrap();
-
-    let mut ctx = lyss::runtime::HostContext::new();
-    let mut builtins: HashMap<String, ObjectEntry<HostFunc>> = HashMap::new();
-    let mut math: HashMap<String, ObjectEntry<HostFunc>> = HashMap::new();
-
-    builtins.insert(
-        "local".to_owned(),
-        ObjectEntry::Leaf(HostFunc(|ctx, args| {
-            Api::assert_args_count(args, 2)?;
-            let var_name = Api::needs_nth_arg(args, 0)?;
-            let name = Api::expect_var(var_name).ok_or(LyssRuntimeError::UnexpectedArg {
-                arg: var_name.clone(),
-                expected: "Identifier",
-            })?;
-            let value = Api::needs_nth_arg(args, 1)?;
-            let value = ctx.eval_argument(value)?;
-            ctx.set_var(name.to_string(), value.clone());
-            Ok(value)
-        })),
-    );
-
-    builtins.insert(
-        "print".to_owned(),
-        ObjectEntry::Leaf(HostFunc(|ctx, args| {
-            let mut out = String::new();
-            for arg in args {
-                let value = ctx.eval_argument(arg)?;
-                let cnt = DisplayValue(value).to_string();
-                out.push_str(&cnt);
+    let code = std::thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(command)
+        .expect("the main thread spawns")
+        .join()
+        .unwrap_or(101);
+    std::process::exit(code);
+}
+
+fn command() -> i32 {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.split_first() {
+        Some((command, args)) => match command.as_str() {
+            "run" => run(args),
+            "fmt" => fmt(args),
+            "repl" if args.is_empty() => repl(),
+            "repl" => usage("`repl` takes no arguments"),
+            "lsp" if args.is_empty() => lsp(),
+            "lsp" => usage("`lsp` takes no arguments"),
+            "help" | "--help" | "-h" => {
+                println!("{USAGE}");
+                0
             }
-            print!("{out}");
-            Ok(Value::Num(out.len() as f64))
-        })),
-    );
-
-    builtins.insert(
-        "alias".to_owned(),
-        ObjectEntry::Leaf(HostFunc(|ctx, args| {
-            let to = Api::needs_nth_arg(args, 0)?;
-            let from = Api::needs_nth_arg(args, 1)?;
-            let to = Api::expect_ident(to).ok_or(LyssRuntimeError::UnexpectedArg {
-                arg: to.clone(),
-                expected: "Identifier Path",
-            })?;
-            let from = Api::expect_ident(from).ok_or(LyssRuntimeError::UnexpectedArg {
-                arg: from.clone(),
-                expected: "Single Identifier",
-            })?;
-            assert!(from.0.len() == 1);
-            let from = from.0[0].to_owned();
-            let to = match ctx.functions.find(&to.0)? {
-                lyss::runtime::object::ObjectSearch::Leaf(l) => ObjectEntry::Leaf(l.clone()),
-                lyss::runtime::object::ObjectSearch::Branch(b) => ObjectEntry::Branch(Rc::clone(b)),
-            };
-
-            ctx.register(from.clone(), to);
-            Ok(Value::Str(from))
-        })),
-    );
-
-    builtins.insert(
-        "scope".to_owned(),
-        ObjectEntry::Leaf(HostFunc(|ctx, args| {
-            let to = Api::needs_nth_arg(args, 0)?;
-            let to_name = Api::expect_ident(to).ok_or(LyssRuntimeError::UnexpectedArg {
-                arg: to.clone(),
-                expected: "Identifier Path",
-            })?;
-            let to = ctx.functions.find_branch(&to_name.0)?;
-            ctx.scopes.push(Rc::clone(to));
-
-            Ok(Value::List(
-                to_name
-                    .0
-                    .iter()
-                    .map(String::to_owned)
-                    .map(Value::Str)
-                    .collect(),
-            ))
-        })),
-    );
-
-    math.insert(
-        "=".to_owned(),
-        ObjectEntry::Leaf(HostFunc(|ctx, args| {
-            let lhs = Api::needs_nth_arg(args, 0)?;
-            let rhs = Api::needs_nth_arg(args, 1)?;
-            let lhs = ctx.eval_argument(lhs)?;
-            let rhs = ctx.eval_argument(rhs)?;
-            return Ok(Value::Bool(lhs == rhs));
-        })),
-    );
-
-    builtins.insert(
-        "Math".to_owned(),
-        ObjectEntry::Branch(Rc::new(lyss::runtime::object::Object(math))),
-    );
-    ctx.register_object(
-        "Builtin".to_owned(),
-        lyss::runtime::object::Object(builtins),
-    );
-
-    ctx.register_entry(
-        "if".to_owned(),
-        ObjectEntry::Leaf(HostFunc(|ctx, args| {
-            if args.len() != 4 {
-                return Err(LyssRuntimeError::UnmatchedArgCount {
-                    got: args.to_vec(),
-                    could_usize: vec![2, 4],
-                });
+            _ => usage(&format!("unknown command `{command}`")),
+        },
+        None => usage("missing command"),
+    }
+}
+
+/// Reports a wrong use of the command line
+fn usage(error: &str) -> i32 {
+    eprintln!("lyss: {error}\n\n{USAGE}");
+    2
+}
+
+/// Reads the file at `path`, or stdin for `-`
+fn read_source(path: &str) -> Result<(PathBuf, String), i32> {
+    let read = if path == "-" {
+        let mut file = String::new();
+        std::io::stdin()
+            .read_to_string(&mut file)
+            .map(|_| (PathBuf::from("<stdin>"), file))
+    } else {
+        std::fs::read_to_string(path).map(|file| (PathBuf::from(path), file))
+    };
+    read.map_err(|error| {
+        eprintln!("lyss: can't read {path}: {error}");
+        2
+    })
+}
+
+/// `lyss run [--engine <engine>] [--dump-..] [--json] <file> [-- args..]`
+fn run(args: &[String]) -> i32 {
+    let mut engine = Engine::default();
+    let mut dump_tokens = false;
+    let mut dump_ast = false;
+    let mut dump_expanded = false;
+    let mut dump_context = false;
+    let mut format = DumpFormat::Human;
+    let mut args = args.iter();
+    let path = loop {
+        match args.next().map(String::as_str) {
+            Some("--dump-tokens") => dump_tokens = true,
+            Some("--dump-ast") => dump_ast = true,
+            Some("--dump-expanded") => dump_expanded = true,
+            Some("--dump-context") => dump_context = true,
+            Some("--json") => format = DumpFormat::Json,
+            Some("--engine") => match args.next().map(String::as_str) {
+                Some("tree") => engine = Engine::Tree,
+                Some("bytecode") => engine = Engine::Bytecode,
+                Some(name) => return usage(&format!("unknown engine `{name}`")),
+                None => return usage("`--engine` needs `tree` or `bytecode`"),
+            },
+            Some(flag) if flag.starts_with("--") => {
+                return usage(&format!("unknown flag `{flag}`"));
             }
-            let if_code = Api::needs_nth_arg(args, 0)?;
-            Api::expect_this_text(Api::needs_nth_arg(args, 2)?, "else")?;
-
-            let Some(Value::Code(if_code)) = Api::expect_literal(if_code) else {
-                return Err(LyssRuntimeError::UnexpectedArg {
-                    arg: if_code.clone(),
-                    expected: "code",
-                });
-            };
-            let Some(if_res) = ctx.run(&if_code.exprs)? else {
-                return Err(LyssRuntimeError::NeedsArg);
-            };
-            let Value::Bool(if_res) = if_res else {
-                return Err(LyssRuntimeError::UnexpectedArg {
-                    arg: lyss::parser::Argument::Value(if_res),
-                    expected: "boolean",
-                });
-            };
-
-            Ok(if if_res {
-                let true_code = Api::needs_nth_arg(args, 1)?;
-                let Some(Value::Code(true_code)) = Api::expect_literal(true_code) else {
-                    return Err(LyssRuntimeError::UnexpectedArg {
-                        arg: true_code.clone(),
-                        expected: "code",
-                    });
-                };
-                ctx.run(&true_code.exprs)?
-                    .ok_or(LyssRuntimeError::NeedsArg)?
+            Some(path) => break path,
+            None => return usage("missing the file to run"),
+        }
+    };
+    let args = args.as_slice();
+    let script_args = args.strip_prefix(&["--".to_owned()]).unwrap_or(args);
+
+    let (file_name, file) = match read_source(path) {
+        Ok(source) => source,
+        Err(code) => return code,
+    };
+    let mut sources = SourceMap::new();
+    let file_id = sources.add(&file_name, file.clone());
+    let (tokens, mut errors) = lyss::tokenizer::tokenize(&file, &file_name, file_id);
+    if dump_tokens {
+        eprint!("{}", dump::tokens(&tokens, format));
+    }
+    let (exprs, parse_errors) = lyss::parser::parse(&mut tokens.into_iter());
+    errors.extend(parse_errors);
+    if dump_ast {
+        eprint!("{}", dump::exprs(&exprs, format));
+    }
+    if !errors.is_empty() {
+        for error in &errors {
+            eprint!("{}", Diagnostic::from(error).render(&sources));
+        }
+        return 1;
+    }
+
+    let mut ctx = HostContext::new();
+    ctx.engine = engine;
+    if let Err(error) = lyss::stdlib::install(&mut ctx) {
+        eprintln!("lyss: can't install the standard library: {error}");
+        return 1;
+    }
+    let script_args = script_args.iter().cloned().map(Value::Str).collect();
+    ctx.set_var("args", Value::List(script_args));
+    if dump_expanded {
+        ctx.record_expansions();
+    }
+    let result = ctx.run(&exprs);
+    if dump_expanded {
+        eprint!("{}", dump::expansions(&ctx.take_expansions(), format));
+    }
+    let code = match result {
+        Ok(_) => 0,
+        Err(error) => {
+            if let LyssRuntimeError::Exit { code } = error.unlocated() {
+                *code
             } else {
-                let false_code = Api::needs_nth_arg(args, 3)?;
-                let Some(Value::Code(false_code)) = Api::expect_literal(false_code) else {
-                    return Err(LyssRuntimeError::UnexpectedArg {
-                        arg: false_code.clone(),
-                        expected: "code",
-                    });
-                };
-                ctx.run(&false_code.exprs)?
-                    .ok_or(LyssRuntimeError::NeedsArg)?
-            })
-        })),
-    );
-    ctx.run(&exprs).unwrap();
-    println!("{ctx:?}");
+                eprint!(
+                    "{}",
+                    Diagnostic::from_runtime(&error, &ctx).render(&sources)
+                );
+                1
+            }
+        }
+    };
+    if dump_context {
+        eprintln!("{ctx:?}");
+    }
+    code
+}
+
+/// `lyss fmt [--check] <file>..`, rewrites the files in the canonical layout. With `--check` the
+/// files are left alone and the exit code says whether they all were formatted
+fn fmt(args: &[String]) -> i32 {
+    let check = args.iter().any(|arg| arg == "--check");
+    let files: Vec<&String> = args.iter().filter(|arg| *arg != "--check").collect();
+    if files.is_empty() {
+        return usage("missing the files to format");
+    }
+    let formatter = Formatter::default();
+    let mut sources = SourceMap::new();
+    let mut code = 0;
+    for file_name in files {
+        let file_name = PathBuf::from(file_name);
+        let file = match std::fs::read_to_string(&file_name) {
+            Ok(file) => file,
+            Err(error) => {
+                eprintln!("lyss: can't read {}: {error}", file_name.display());
+                code = 1;
+                continue;
+            }
+        };
+        let file_id = sources.add(&file_name, file.clone());
+        let output = match formatter.format(&file, &file_name, file_id) {
+            Ok(output) => output,
+            Err(errors) => {
+                for error in &errors {
+                    eprint!("{}", Diagnostic::from(error).render(&sources));
+                }
+                code = 1;
+                continue;
+            }
+        };
+        if output == file {
+            continue;
+        }
+        if check {
+            eprintln!("{} is not formatted", file_name.display());
+            code = 1;
+        } else if let Err(error) = std::fs::write(&file_name, output) {
+            eprintln!("lyss: can't write {}: {error}", file_name.display());
+            code = 1;
+        }
+    }
+    code
+}
+
+/// `lyss repl`, reads inputs from stdin until it ends or the session is quit. An input goes on
+/// over more lines while it's incomplete, an empty line runs it anyway to show the errors
+fn repl() -> i32 {
+    let mut repl = match Repl::new() {
+        Ok(repl) => repl,
+        Err(error) => {
+            eprintln!("lyss: can't install the standard library: {error}");
+            return 1;
+        }
+    };
+    let mut history = history_file().and_then(|path| {
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .ok()
+    });
+    let mut lines = std::io::stdin().lock().lines();
+    let mut input = String::new();
+    loop {
+        print!("{}", if input.is_empty() { "lyss> " } else { "....> " });
+        let _ = std::io::stdout().flush();
+        let Some(Ok(line)) = lines.next() else {
+            println!();
+            return 0;
+        };
+        let more = !line.trim().is_empty();
+        input.push_str(&line);
+        input.push('\n');
+        if input.trim().is_empty() || (more && Repl::is_incomplete(&input)) {
+            if input.trim().is_empty() {
+                input.clear();
+            }
+            continue;
+        }
+        if let Some(history) = &mut history {
+            let _ = write!(history, "{input}");
+        }
+        match repl.eval(&input) {
+            Reply::Output(output) if output.is_empty() => {}
+            Reply::Output(output) => println!("{}", output.trim_end()),
+            Reply::Error(error) => eprintln!("{}", error.trim_end()),
+            Reply::Exit(code) => return code,
+        }
+        input.clear();
+    }
+}
+
+/// `lyss lsp`, exits with 0 when the client shut the server down before exiting it
+fn lsp() -> i32 {
+    match lyss::lsp::serve(std::io::stdin().lock(), std::io::stdout().lock()) {
+        Ok(code) => code,
+        Err(error) => {
+            eprintln!("lyss: {error}");
+            1
+        }
+    }
+}
+
+/// Where `lyss repl` saves its inputs, `None` when there's no home to put it in
+fn history_file() -> Option<PathBuf> {
+    match std::env::var_os("LYSS_HISTORY") {
+        Some(path) => Some(PathBuf::from(path)),
+        None => std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".lyss_history")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A script in the temp dir, removed when the test is done with it
+    struct Script {
+        path: String,
+    }
+
+    impl Drop for Script {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+
+    /// Writes `source` to a file of its own in the temp dir
+    fn script(name: &str, source: &str) -> Script {
+        let path = std::env::temp_dir().join(format!("lyss-{}-{name}.ls", std::process::id()));
+        std::fs::write(&path, source).expect("the temp dir is writable");
+        Script {
+            path: path.display().to_string(),
+        }
+    }
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| (*arg).to_owned()).collect()
+    }
+
+    #[test]
+    fn wrong_uses_exit_with_2() {
+        let empty = script("empty", "");
+        let path = &empty.path;
+        for wrong in [
+            &[][..],
+            &["--nope", path],
+            &["--engine", "jit", path],
+            &["--engine"],
+            &["/nonexistent/lyss/script.ls"],
+        ] {
+            assert_eq!(run(&args(wrong)), 2, "{wrong:?}");
+        }
+        assert_eq!(fmt(&args(&["--check"])), 2);
+        assert_eq!(run(&args(&[path])), 0);
+    }
+
+    #[test]
+    fn script_errors_exit_with_1() {
+        assert_eq!(run(&args(&[&script("syntax", "(print ").path])), 1);
+        assert_eq!(run(&args(&[&script("runtime", "(missing )").path])), 1);
+    }
+
+    #[test]
+    fn scripts_give_their_exit_code_and_get_their_args() {
+        let script = script(
+            "args",
+            r#"(if '( (Builtin.Math.= $.args ["a" "b c"] ) )' '( (Builtin.exit 5 ) )' else '( (Builtin.exit 6 ) )' )"#,
+        );
+        let path = &script.path;
+        for engine in ["tree", "bytecode"] {
+            assert_eq!(run(&args(&["--engine", engine, path, "--", "a", "b c"])), 5);
+            assert_eq!(run(&args(&["--engine", engine, path, "a", "b c"])), 5);
+            assert_eq!(run(&args(&["--engine", engine, path, "a"])), 6);
+        }
+    }
+
+    #[test]
+    fn fmt_check_reports_unformatted_files() {
+        let formatted = script("formatted", "(print 1 )\n");
+        let formatted = &formatted.path;
+        let unformatted = script("unformatted", "(print   1)");
+        let unformatted = &unformatted.path;
+        assert_eq!(fmt(&args(&["--check", formatted])), 0);
+        assert_eq!(fmt(&args(&["--check", formatted, unformatted])), 1);
+        assert_eq!(fmt(&args(&[unformatted])), 0);
+        assert_eq!(
+            std::fs::read_to_string(unformatted).expect("it was written"),
+            "(print 1 )\n"
+        );
+    }
 }
@@ -1,11 +1,16 @@
 #![allow(dead_code)]
 
+use std::cell::OnceCell;
+use std::rc::Rc;
+
+use crate::runtime::vm::Chunk;
+use crate::span::Span;
 use crate::tokenizer::{Token, TokenCont};
 use crate::{LyssCompError, Value};
 
 #[derive(Debug, Clone)]
 pub struct Expr {
-    pub line_span: std::ops::Range<usize>,
+    pub span: Span,
     pub cont: ExprCont,
 }
 
@@ -14,37 +19,134 @@ pub struct FnName(pub Vec<String>);
 
 #[derive(Debug, Clone)]
 pub struct Atom {
-    pub line_span: std::ops::Range<usize>,
+    pub span: Span,
     pub fn_name: FnName,
     pub arguments: Vec<Argument>,
 }
 
 #[derive(Debug, Clone)]
-pub enum Argument {
+pub struct Argument {
+    pub span: Span,
+    pub cont: ArgumentCont,
+}
+
+impl Argument {
+    /// An argument that wasn't read from a file
+    #[must_use]
+    pub fn new(cont: ArgumentCont) -> Argument {
+        Argument {
+            span: Span::default(),
+            cont,
+        }
+    }
+    /// An already evaluated argument, not read from a file
+    #[must_use]
+    pub fn value(value: Value) -> Argument {
+        Argument::new(ArgumentCont::Value(value))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum ArgumentCont {
     Var(String),
     Ident(FnName),
     Atom(Atom),
     Value(Value),
     Macro(MacroUse),
+    Hole(Hole),
+    /// A `[ .. ]` list literal, its items are evaluated in order
+    List(Vec<Argument>),
+    /// Source that couldn't be parsed
+    Error,
 }
 
-#[derive(Debug, Clone)]
+/// A quoted block, cheap to clone since the expressions and their bytecode are shared
+#[derive(Clone)]
 pub struct Code {
-    pub line_span: std::ops::Range<usize>,
-    pub exprs: Vec<Expr>,
+    pub span: Span,
+    pub exprs: Rc<[Expr]>,
+    /// Compiled the first time the block runs with the bytecode engine
+    chunk: Rc<OnceCell<Chunk>>,
+}
+
+impl Code {
+    #[must_use]
+    pub fn new(span: Span, exprs: Vec<Expr>) -> Code {
+        Code {
+            span,
+            exprs: exprs.into(),
+            chunk: Rc::default(),
+        }
+    }
+    /// The bytecode of the block, compiled once and shared by every clone
+    #[must_use]
+    pub fn chunk(&self) -> &Chunk {
+        self.chunk.get_or_init(|| Chunk::compile(&self.exprs))
+    }
+}
+
+impl std::fmt::Debug for Code {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Code")
+            .field("span", &self.span)
+            .field("exprs", &self.exprs)
+            .finish_non_exhaustive()
+    }
 }
 
 #[derive(Debug, Clone)]
 pub enum ExprCont {
     Atom(Atom),
     Macro(MacroUse),
+    Hole(Hole),
+    /// Source that couldn't be parsed
+    Error,
     //Code(Code),
 }
 
+/// A backtick quoted block, instantiated by macros after filling its holes
+#[derive(Debug, Clone)]
+pub struct Template {
+    pub span: Span,
+    pub exprs: Vec<Expr>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HoleKind {
+    /// `_.name`, replaced by a single value
+    Value,
+    /// `$name`, a list is spread into many arguments and code into many expressions
+    Splice,
+}
+
+#[derive(Debug, Clone)]
+pub struct Hole {
+    pub name: String,
+    pub kind: HoleKind,
+}
+
+impl Hole {
+    fn from_path(path: &[String]) -> Option<Hole> {
+        match path {
+            [underscore, name] if underscore == "_" => Some(Hole {
+                name: name.clone(),
+                kind: HoleKind::Value,
+            }),
+            [name] if name.len() > 1 && name.starts_with('$') => Some(Hole {
+                name: name[1..].to_owned(),
+                kind: HoleKind::Splice,
+            }),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct MacroUse {
+    pub span: Span,
     pub name: String,
     pub content: String,
+    pub content_span: Span,
 }
 
 #[derive(Debug)]
@@ -53,140 +155,733 @@ pub enum AtomState {
     OnArgs(FnName, Vec<Argument>),
 }
 
+/// Parses a whole file, skipping over what it can't parse so every syntax error is reported at
+/// once. The content that was skipped is left as [`ExprCont::Error`] and [`ArgumentCont::Error`]
+/// nodes, the AST is only safe to run when no errors were returned
+pub fn parse(tokens: &mut impl Iterator<Item = Token>) -> (Vec<Expr>, Vec<LyssCompError>) {
+    let mut parser = Parser::new(tokens);
+    let mut exprs = vec![];
+    while let Some(token) = parser.tokens.next() {
+        exprs.push(parser.expr(token, false));
+    }
+    (exprs, parser.errors)
+}
+
+/// Parses the rest of an atom whose `(` was at `start`, failing on the first syntax error
 pub fn parse_atom(
-    start_line: usize,
+    start: Span,
     tokens: &mut impl Iterator<Item = Token>,
 ) -> Result<Atom, LyssCompError> {
-    use AtomState as State;
-    let mut state = State::OnAtom;
-    while let Some(Token { line, content }) = tokens.next() {
-        state = match (state, content) {
-            (State::OnAtom, TokenCont::Ident(cnt)) => State::OnArgs(FnName(vec![cnt]), vec![]),
-            (State::OnAtom, TokenCont::Path(cnt)) => State::OnArgs(FnName(cnt), vec![]),
-            (State::OnArgs(fn_name, mut args), TokenCont::OParam) => {
-                args.push(Argument::Atom(parse_atom(line, tokens)?));
-                State::OnArgs(fn_name, args)
-            }
-            (State::OnArgs(fn_name, mut args), TokenCont::String(cnt)) => {
-                args.push(Argument::Value(Value::Str(cnt)));
-                State::OnArgs(fn_name, args)
-            }
-            (State::OnArgs(fn_name, mut args), TokenCont::Digit(cnt)) => {
-                let num = cnt.parse().map_err(LyssCompError::ParseFloat)?;
-                args.push(Argument::Value(Value::Num(num)));
-                State::OnArgs(fn_name, args)
-            }
-            (State::OnArgs(fn_name, mut args), TokenCont::Path(mut secs)) => {
+    let mut parser = Parser::new(tokens);
+    let atom = parser.atom(start);
+    match (parser.errors.into_iter().next(), atom) {
+        (None, Ok(atom)) => Ok(atom),
+        (Some(error), _) => Err(error),
+        (None, Err(span)) => unreachable!("the atom at {span:?} was skipped without an error"),
+    }
+}
+
+/// Reads a single value written like the repr [`Value`]'s `Display` gives: a string, number,
+/// `true`, `false`, code block, template or a `[ .. ]` list of those
+pub fn parse_value(tokens: &mut impl Iterator<Item = Token>) -> Result<Value, LyssCompError> {
+    let mut parser = Parser::new(tokens);
+    let Some(first) = parser.tokens.next() else {
+        return Err(LyssCompError::UnexpectedEof {
+            span: Span::default(),
+            expected: "a value",
+        });
+    };
+    let argument = parser.argument(first.clone());
+    if let Some(extra) = parser.tokens.next() {
+        parser.unexpected(extra, "the end of the value");
+    }
+    if let Some(error) = parser.errors.into_iter().next() {
+        return Err(error);
+    }
+    literal(argument.cont).ok_or(LyssCompError::UnexpectedToken {
+        found: Box::new(first),
+        expected: "a value",
+    })
+}
+
+/// The value of an argument made only of literals
+fn literal(argument: ArgumentCont) -> Option<Value> {
+    match argument {
+        ArgumentCont::Value(value) => Some(value),
+        ArgumentCont::List(items) => items
+            .into_iter()
+            .map(|item| literal(item.cont))
+            .collect::<Option<_>>()
+            .map(Value::List),
+        _ => None,
+    }
+}
+
+/// A parse that couldn't be completed, holding the span of what was skipped
+type Skipped<T> = Result<T, Span>;
+
+struct Parser<I: Iterator<Item = Token>> {
+    tokens: std::iter::Peekable<I>,
+    errors: Vec<LyssCompError>,
+}
+
+impl<I: Iterator<Item = Token>> Parser<I> {
+    fn new(tokens: I) -> Self {
+        Parser {
+            tokens: tokens.peekable(),
+            errors: vec![],
+        }
+    }
+    fn unexpected(&mut self, found: Token, expected: &'static str) {
+        self.errors.push(LyssCompError::UnexpectedToken {
+            found: Box::new(found),
+            expected,
+        });
+    }
+    fn eof(&mut self, span: Span, expected: &'static str) {
+        self.errors
+            .push(LyssCompError::UnexpectedEof { span, expected });
+    }
+    /// Skips to the `)` closing the atom being parsed from `depth` lists inside it, returning
+    /// its span
+    fn skip_atom(&mut self, mut depth: usize) -> Option<Span> {
+        for token in self.tokens.by_ref() {
+            match token.content {
+                TokenCont::OParam => depth += 1,
+                TokenCont::CParam if depth == 0 => return Some(token.span),
+                TokenCont::CParam => depth -= 1,
+                _ => {}
+            }
+        }
+        None
+    }
+    /// Skips to the next token that can start an expression, or that closes the block when
+    /// `in_block`, returning the span of the last skipped token
+    fn skip_exprs(&mut self, mut last: Span, in_block: bool) -> Span {
+        while let Some(token) = self.tokens.peek() {
+            match token.content {
+                TokenCont::OParam | TokenCont::Macro { .. } => break,
+                TokenCont::CParam if in_block => break,
+                _ => last = self.tokens.next().map_or(last, |token| token.span),
+            }
+        }
+        last
+    }
+    fn atom(&mut self, start: Span) -> Skipped<Atom> {
+        use AtomState as State;
+        let mut state = State::OnAtom;
+        let mut last = start;
+        while let Some(Token { span, content }) = self.tokens.next() {
+            last = span;
+            state = match (state, content) {
+                (State::OnAtom, TokenCont::Ident(cnt)) => State::OnArgs(FnName(vec![cnt]), vec![]),
+                (State::OnAtom, TokenCont::Path(cnt)) => State::OnArgs(FnName(cnt), vec![]),
+                (State::OnAtom, TokenCont::CParam) => {
+                    let found = Token {
+                        span,
+                        content: TokenCont::CParam,
+                    };
+                    self.unexpected(found, "a function name");
+                    return Err(start.to(span));
+                }
+                (State::OnAtom, content) => {
+                    // A list in the head is skipped whole, not taken as the end of the atom
+                    let depth = usize::from(matches!(content, TokenCont::OParam));
+                    // The tokenizer already reported what it couldn't read
+                    if !matches!(content, TokenCont::Error) {
+                        self.unexpected(Token { span, content }, "a function name");
+                    }
+                    let Some(end) = self.skip_atom(depth) else {
+                        self.eof(start, "`)`");
+                        return Err(start.to(span));
+                    };
+                    return Err(start.to(end));
+                }
+                (State::OnArgs(fn_name, arguments), TokenCont::CParam) => {
+                    return Ok(Atom {
+                        fn_name,
+                        arguments,
+                        span: start.to(span),
+                    });
+                }
+                (State::OnArgs(fn_name, mut args), content) => {
+                    args.push(self.argument(Token { span, content }));
+                    State::OnArgs(fn_name, args)
+                }
+            };
+        }
+        self.eof(start, "`)`");
+        // What was read of an unclosed atom is kept, so editors still see it
+        match state {
+            State::OnAtom => Err(start),
+            State::OnArgs(fn_name, arguments) => Ok(Atom {
+                span: start.to(last),
+                fn_name,
+                arguments,
+            }),
+        }
+    }
+    fn argument(&mut self, Token { span, content }: Token) -> Argument {
+        let arg = |cont| Argument { span, cont };
+        let skipped = |span| Argument {
+            span,
+            cont: ArgumentCont::Error,
+        };
+        match content {
+            TokenCont::OParam => match self.atom(span) {
+                Ok(atom) => Argument {
+                    span: atom.span,
+                    cont: ArgumentCont::Atom(atom),
+                },
+                Err(span) => skipped(span),
+            },
+            TokenCont::OBracket => self.list(span),
+            TokenCont::String(cnt) => arg(ArgumentCont::Value(Value::Str(cnt))),
+            TokenCont::Digit(cnt) => match parse_number(span, &cnt) {
+                Ok(num) => arg(ArgumentCont::Value(num)),
+                Err(error) => {
+                    self.errors.push(error);
+                    skipped(span)
+                }
+            },
+            TokenCont::Path(mut secs) => {
                 if secs.len() == 2 && secs.first().map(String::as_str) == Some("$") {
-                    args.push(Argument::Var(secs.swap_remove(1)));
+                    arg(ArgumentCont::Var(secs.swap_remove(1)))
                 } else {
-                    args.push(Argument::Ident(FnName(secs)));
+                    arg(ArgumentCont::Ident(FnName(secs)))
+                }
+            }
+            TokenCont::Ident(cnt) => match cnt.as_str() {
+                "true" => arg(ArgumentCont::Value(Value::Bool(true))),
+                "false" => arg(ArgumentCont::Value(Value::Bool(false))),
+                "inf" => arg(ArgumentCont::Value(Value::Num(f64::INFINITY))),
+                "-inf" => arg(ArgumentCont::Value(Value::Num(f64::NEG_INFINITY))),
+                "nan" => arg(ArgumentCont::Value(Value::Num(f64::NAN))),
+                _ => arg(ArgumentCont::Ident(FnName(vec![cnt]))),
+            },
+            TokenCont::Macro {
+                name,
+                content,
+                content_span,
+            } => arg(ArgumentCont::Macro(MacroUse {
+                span,
+                name,
+                content,
+                content_span,
+            })),
+            TokenCont::SingleQuote => match self.code(span) {
+                Ok((exprs, end)) => {
+                    let span = span.to(end);
+                    Argument {
+                        span,
+                        cont: ArgumentCont::Value(Value::Code(Code::new(span, exprs))),
+                    }
+                }
+                Err(span) => skipped(span),
+            },
+            TokenCont::MacroQuote => match self.template(span) {
+                Ok((exprs, end)) => {
+                    let span = span.to(end);
+                    Argument {
+                        span,
+                        cont: ArgumentCont::Value(Value::Template(Template { span, exprs })),
+                    }
+                }
+                Err(span) => skipped(span),
+            },
+            content @ (TokenCont::CParam | TokenCont::CBracket) => {
+                self.unexpected(Token { span, content }, "an argument");
+                skipped(span)
+            }
+            TokenCont::Error => skipped(span),
+        }
+    }
+    /// Parses the items of a list literal whose `[` was at `start`, a `)` ends it early so the
+    /// atom around it stays closed
+    fn list(&mut self, start: Span) -> Argument {
+        let mut items: Vec<Argument> = vec![];
+        let end = loop {
+            let last = items.last().map_or(start, |item| item.span);
+            match self
+                .tokens
+                .next_if(|token| !matches!(token.content, TokenCont::CParam))
+            {
+                Some(Token {
+                    content: TokenCont::CBracket,
+                    span,
+                }) => break span,
+                Some(token) => items.push(self.argument(token)),
+                None => {
+                    match self.tokens.peek().cloned() {
+                        Some(found) => self.unexpected(found, "`]`"),
+                        None => self.eof(start, "`]`"),
+                    }
+                    break last;
+                }
+            }
+        };
+        Argument {
+            span: start.to(end),
+            cont: ArgumentCont::List(items),
+        }
+    }
+    fn code(&mut self, quote: Span) -> Skipped<(Vec<Expr>, Span)> {
+        self.block(quote, "`'`", |t| matches!(t, TokenCont::SingleQuote))
+    }
+    /// Parses a backtick quoted block, turning every `_.name` and `$name` into a [`Hole`]
+    fn template(&mut self, quote: Span) -> Skipped<(Vec<Expr>, Span)> {
+        let (mut exprs, end) =
+            self.block(quote, "`` ` ``", |t| matches!(t, TokenCont::MacroQuote))?;
+        for expr in &mut exprs {
+            make_expr_holes(expr);
+        }
+        Ok((exprs, end))
+    }
+    /// Parses `( exprs... )` followed by the quote `end`, a missing closing quote is reported
+    /// but the block is kept
+    fn block(
+        &mut self,
+        quote: Span,
+        end: &'static str,
+        is_end: fn(&TokenCont) -> bool,
+    ) -> Skipped<(Vec<Expr>, Span)> {
+        let start = match self.tokens.peek() {
+            Some(Token {
+                content: TokenCont::OParam,
+                span,
+            }) => *span,
+            token => {
+                let first_token = token.cloned().map(Box::new);
+                self.errors
+                    .push(LyssCompError::CodeWithoutRootAtom { first_token });
+                return Err(quote);
+            }
+        };
+        self.tokens.next();
+        let mut exprs = vec![];
+        let close = loop {
+            match self.tokens.next() {
+                Some(Token {
+                    content: TokenCont::CParam,
+                    span,
+                }) => break span,
+                Some(token) => exprs.push(self.expr(token, true)),
+                None => {
+                    self.eof(start, "`)`");
+                    let last = exprs.last().map_or(start, |expr| expr.span);
+                    return Ok((exprs, last));
                 }
-                State::OnArgs(fn_name, args)
             }
-            (State::OnArgs(fn_name, mut args), TokenCont::Ident(cnt)) => {
-                args.push(Argument::Ident(FnName(vec![cnt])));
-                State::OnArgs(fn_name, args)
+        };
+        match self.tokens.peek() {
+            Some(token) if is_end(&token.content) => {
+                let span = token.span;
+                self.tokens.next();
+                Ok((exprs, span))
             }
-            (State::OnArgs(fn_name, mut args), TokenCont::Macro { name, content, .. }) => {
-                args.push(Argument::Macro(MacroUse { name, content }));
-                State::OnArgs(fn_name, args)
+            Some(token) => {
+                let found = token.clone();
+                self.unexpected(found, end);
+                Ok((exprs, close))
             }
-            (State::OnArgs(fn_name, mut args), TokenCont::SingleQuote) => {
-                let (exprs, last_line) = parse_code(tokens)?;
-                args.push(Argument::Value(Value::Code(Code {
-                    exprs,
-                    line_span: line..last_line,
-                })));
-                State::OnArgs(fn_name, args)
+            None => {
+                self.eof(start, end);
+                Ok((exprs, close))
+            }
+        }
+    }
+    fn expr(&mut self, Token { span, content }: Token, in_block: bool) -> Expr {
+        match content {
+            TokenCont::OParam => match self.atom(span) {
+                Ok(atom) => Expr {
+                    span: atom.span,
+                    cont: ExprCont::Atom(atom),
+                },
+                Err(span) => Expr {
+                    span,
+                    cont: ExprCont::Error,
+                },
+            },
+            TokenCont::Macro {
+                name,
+                content,
+                content_span,
+            } => {
+                let atom = MacroUse {
+                    span,
+                    name,
+                    content,
+                    content_span,
+                };
+                let cont = ExprCont::Macro(atom);
+                Expr { span, cont }
             }
-            (State::OnArgs(fn_name, arguments), TokenCont::CParam) => {
-                return Ok(Atom {
-                    fn_name,
-                    arguments,
-                    line_span: start_line..line,
-                });
+            TokenCont::Error => Expr {
+                span,
+                cont: ExprCont::Error,
+            },
+            content => {
+                self.unexpected(Token { span, content }, "`(` or a macro invocation");
+                let end = self.skip_exprs(span, in_block);
+                Expr {
+                    span: span.to(end),
+                    cont: ExprCont::Error,
+                }
             }
+        }
+    }
+}
 
-            (s, t) => panic!("#{line}: Can't advance {s:?} with {t:?}"),
-        };
+/// Reads a number literal like `-1_000`, `0xff` or `1.5e-3`, those without a `.` or an
+/// exponent are exact integers
+fn parse_number(span: Span, text: &str) -> Result<Value, LyssCompError> {
+    let digits = text.replace('_', "");
+    let (sign, unsigned) = match digits.strip_prefix('-') {
+        Some(unsigned) => ("-", unsigned),
+        None => ("", digits.as_str()),
+    };
+    let radix = match unsigned.get(..2) {
+        Some("0x") => 16,
+        Some("0o") => 8,
+        Some("0b") => 2,
+        _ if unsigned.contains(['.', 'e', 'E']) => {
+            return digits
+                .parse()
+                .map(Value::Num)
+                .map_err(|error| LyssCompError::ParseFloat { span, error });
+        }
+        _ => 10,
+    };
+    let unsigned = if radix == 10 {
+        unsigned
+    } else {
+        &unsigned[2..]
+    };
+    i64::from_str_radix(&format!("{sign}{unsigned}"), radix)
+        .map(Value::Int)
+        .map_err(|error| LyssCompError::ParseInt { span, error })
+}
+
+fn make_expr_holes(expr: &mut Expr) {
+    let ExprCont::Atom(atom) = &mut expr.cont else {
+        return;
+    };
+    if atom.arguments.is_empty()
+        && let Some(hole) = Hole::from_path(&atom.fn_name.0)
+        && hole.kind == HoleKind::Splice
+    {
+        expr.cont = ExprCont::Hole(hole);
+    } else {
+        make_atom_holes(atom);
     }
-    panic!()
 }
 
-pub enum CodeState {
-    BeforeCode, // needs (
-    OnCode,
-    OnCodeEnd, // read ', needs )
+fn make_atom_holes(atom: &mut Atom) {
+    make_argument_holes(&mut atom.arguments);
 }
 
-pub fn parse_code(
-    tokens: &mut impl Iterator<Item = Token>,
-) -> Result<(Vec<Expr>, usize), LyssCompError> {
-    match tokens.next() {
-        Some(Token {
-            content: TokenCont::OParam,
-            ..
-        }) => {
-            let mut exprs = vec![];
-            loop {
-                let token = tokens.next().unwrap();
-                if let TokenCont::CParam = token.content {
-                    break;
+fn make_argument_holes(arguments: &mut [Argument]) {
+    for argument in arguments {
+        match &mut argument.cont {
+            ArgumentCont::Ident(path) => {
+                if let Some(hole) = Hole::from_path(&path.0) {
+                    argument.cont = ArgumentCont::Hole(hole);
                 }
-                let expr = parse_once(token, tokens)?;
-                exprs.push(expr);
             }
-            let Token { line, content } = tokens.next().unwrap();
-            if let TokenCont::SingleQuote = content {
-                Ok((exprs, line))
-            } else {
-                panic!("Didn't end with close param {content:?}");
+            ArgumentCont::Atom(atom) => make_atom_holes(atom),
+            ArgumentCont::List(items) => make_argument_holes(items),
+            ArgumentCont::Value(Value::Code(Code { exprs, .. })) => {
+                for expr in Rc::make_mut(exprs) {
+                    make_expr_holes(expr);
+                }
             }
+            _ => {}
         }
-        tkn => Err(LyssCompError::CodeWithoutRootAtom { first_token: tkn }),
     }
 }
 
-pub fn parse_once(
-    Token { line, content }: Token,
-    tokens: &mut impl Iterator<Item = Token>,
-) -> Result<Expr, LyssCompError> {
-    Ok(match content {
-        TokenCont::OParam => {
-            let atom = parse_atom(line, tokens)?;
-            let line_span = atom.line_span.clone();
-            let cont = ExprCont::Atom(atom);
-            Expr { line_span, cont }
-        }
-        //TokenCont::SingleQuote => {
-        //    let (exprs, end_line) = parse_code(tokens)?;
-        //    Expr {
-        //        line_span: line..end_line,
-        //        cont: ExprCont::Code(Code {
-        //            exprs,
-        //            line_span: line..end_line,
-        //        }),
-        //    }
-        //}
-        TokenCont::Macro {
-            name,
-            content,
-            line_span,
-        } => {
-            let atom = MacroUse { name, content };
-            let cont = ExprCont::Macro(atom);
-            Expr { line_span, cont }
-        }
-        t => panic!("Top level expression must be either macro or atom, got {t:?}"),
-    })
-}
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
 
-pub fn parse(tokens: &mut impl Iterator<Item = Token>) -> Result<Vec<Expr>, LyssCompError> {
-    let mut exprs = vec![];
-    while let Some(token) = tokens.next() {
-        let expr = parse_once(token, tokens)?;
-        exprs.push(expr);
+    use super::*;
+    use crate::{testing, tokenizer};
+
+    /// The only argument of the only atom of `source`
+    #[track_caller]
+    fn only_argument(source: &str) -> Argument {
+        match &testing::parse(source)[..] {
+            [
+                Expr {
+                    cont: ExprCont::Atom(atom),
+                    ..
+                },
+            ] if atom.arguments.len() == 1 => atom.arguments[0].clone(),
+            _ => panic!("{source:?} isn't an atom with one argument"),
+        }
+    }
+
+    #[test]
+    fn templates_turn_placeholders_into_holes() {
+        let ArgumentCont::Value(Value::Template(template)) =
+            only_argument("(f `( (g _.a $b c ) ($code ) )` )").cont
+        else {
+            panic!("not a template");
+        };
+        let [first, second] = &template.exprs[..] else {
+            panic!("{:?}", template.exprs);
+        };
+        let ExprCont::Atom(atom) = &first.cont else {
+            panic!("{first:?}");
+        };
+        let kinds: Vec<_> = atom
+            .arguments
+            .iter()
+            .map(|argument| match &argument.cont {
+                ArgumentCont::Hole(hole) => Some((hole.name.as_str(), hole.kind)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                Some(("a", HoleKind::Value)),
+                Some(("b", HoleKind::Splice)),
+                None
+            ]
+        );
+        assert!(matches!(
+            &second.cont,
+            ExprCont::Hole(Hole { name, kind: HoleKind::Splice }) if name == "code"
+        ));
+    }
+
+    #[test]
+    fn placeholders_outside_templates_stay_paths() {
+        assert!(matches!(
+            only_argument("(f '( (g _.a ) )' )").cont,
+            ArgumentCont::Value(Value::Code(code))
+                if matches!(&code.exprs[0].cont, ExprCont::Atom(atom)
+                    if matches!(&atom.arguments[0].cont, ArgumentCont::Ident(_)))
+        ));
+    }
+
+    fn codes_and_lines(source: &str) -> Vec<(&'static str, Option<usize>)> {
+        testing::compile_errors(source)
+            .iter()
+            .map(|error| (error.code(), error.span().map(|span| span.line)))
+            .collect()
+    }
+
+    #[test]
+    fn every_syntax_error_is_reported() {
+        assert_eq!(
+            codes_and_lines("(f ) )\n(1 2 )\n(g '( (h ) )' )\n(k (l )"),
+            [("E0006", Some(1)), ("E0006", Some(2)), ("E0007", Some(4))]
+        );
+    }
+
+    #[test]
+    fn unparsable_arguments_become_error_nodes() {
+        let (tokens, _) = tokenizer::tokenize("(f (1 ) 2 )", Path::new("test.ls"), 0);
+        let (exprs, errors) = parse(&mut tokens.into_iter());
+        assert_eq!(errors.len(), 1);
+        let ExprCont::Atom(atom) = &exprs[0].cont else {
+            panic!("{exprs:?}");
+        };
+        assert!(matches!(
+            &atom.arguments[..],
+            [
+                Argument {
+                    cont: ArgumentCont::Error,
+                    ..
+                },
+                Argument {
+                    cont: ArgumentCont::Value(Value::Int(_)),
+                    ..
+                }
+            ]
+        ));
+    }
+
+    #[test]
+    fn error_tokens_dont_add_parse_errors() {
+        assert_eq!(
+            codes_and_lines("(f @ (g ;) )\n(h 1 )"),
+            [("E0004", Some(1)), ("E0004", Some(1))]
+        );
+    }
+
+    #[test]
+    fn lists_in_the_head_are_skipped_whole() {
+        let errors = testing::compile_errors("((f 1) 2)");
+        assert_eq!(errors.len(), 1, "{errors:?}");
+        assert_eq!(
+            codes_and_lines(
+                "((f 1 ) 2 )
+(g )"
+            ),
+            [("E0006", Some(1))]
+        );
+    }
+
+    /// The values the arguments of the only atom of `source` parse to, `Debug` formatted
+    fn literals(source: &str) -> Vec<String> {
+        let exprs = testing::parse(source);
+        let ExprCont::Atom(atom) = &exprs[0].cont else {
+            panic!("{exprs:?}");
+        };
+        atom.arguments
+            .iter()
+            .map(|argument| match &argument.cont {
+                ArgumentCont::Value(value) => format!("{value:?}"),
+                cont => panic!("{cont:?} isn't a literal"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn number_literals() {
+        assert_eq!(
+            literals("(f 1_000 -5 0xff -0x10 0b101 0o17 1.5e-3 -2.5 1e3 1E+2 )"),
+            [
+                "Int(1000)",
+                "Int(-5)",
+                "Int(255)",
+                "Int(-16)",
+                "Int(5)",
+                "Int(15)",
+                "Num(0.0015)",
+                "Num(-2.5)",
+                "Num(1000.0)",
+                "Num(100.0)"
+            ]
+        );
+        assert_eq!(
+            literals("(f inf -inf nan )"),
+            ["Num(inf)", "Num(-inf)", "Num(NaN)"]
+        );
+    }
+
+    #[test]
+    fn integers_are_exact_up_to_their_limits() {
+        assert_eq!(
+            literals("(f 9223372036854775807 -9223372036854775808 )"),
+            ["Int(9223372036854775807)", "Int(-9223372036854775808)"]
+        );
+        let codes: Vec<_> = testing::compile_errors("(f 9223372036854775808 0b102 )")
+            .iter()
+            .map(LyssCompError::code)
+            .collect();
+        assert_eq!(codes, ["E0009", "E0009"]);
+    }
+
+    /// A xorshift generator, so the property tests are the same on every run
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+        fn below(&mut self, n: usize) -> usize {
+            usize::try_from(self.next() % n as u64).expect("it's below n")
+        }
+        fn pick<T: Clone>(&mut self, items: &[T]) -> T {
+            items[self.below(items.len())].clone()
+        }
+    }
+
+    const CHARS: &[char] = &[
+        'a', 'Z', '0', ' ', '"', '\\', '\n', '\t', '\r', '\0', '\u{7}', '\u{1b}', '\u{7f}', 'é',
+        '名', '😀', '(', ')', '[', ']', '#', '\'', '`', '$', '!', '.',
+    ];
+
+    const INTS: &[i64] = &[0, 1, -1, 10, i64::MAX, i64::MIN, i64::MIN + 1];
+
+    const FLOATS: &[f64] = &[
+        0.0,
+        1.0,
+        -2.5,
+        0.1,
+        1e300,
+        -1e-300,
+        f64::MAX,
+        f64::MIN,
+        f64::MIN_POSITIVE,
+        f64::EPSILON,
+        5e-324,
+        9_007_199_254_740_993.0,
+        f64::INFINITY,
+        f64::NAN,
+    ];
+
+    fn value(rng: &mut Rng, depth: usize) -> Value {
+        match rng.below(if depth < 4 { 6 } else { 5 }) {
+            0 => Value::Bool(rng.below(2) == 0),
+            1 => Value::Str((0..rng.below(8)).map(|_| rng.pick(CHARS)).collect()),
+            2 if rng.below(2) == 0 => Value::Int(rng.pick(INTS)),
+            2 => Value::Int(rng.next().cast_signed()),
+            3 if rng.below(2) == 0 => Value::Num(rng.pick(FLOATS)),
+            3 | 4 => Value::Num(f64::from_bits(rng.next())),
+            _ => Value::List((0..rng.below(4)).map(|_| value(rng, depth + 1)).collect()),
+        }
+    }
+
+    /// `==`, except that floats are the same when their bits are, NaN included
+    fn same(lhs: &Value, rhs: &Value) -> bool {
+        match (lhs, rhs) {
+            (Value::Num(lhs), Value::Num(rhs)) => {
+                lhs.to_bits() == rhs.to_bits() || (lhs.is_nan() && rhs.is_nan())
+            }
+            (Value::List(lhs), Value::List(rhs)) => {
+                lhs.len() == rhs.len() && lhs.iter().zip(rhs).all(|(lhs, rhs)| same(lhs, rhs))
+            }
+            _ => lhs == rhs,
+        }
+    }
+
+    #[track_caller]
+    fn round_trip(value: &Value) {
+        let repr = value.to_string();
+        let (tokens, errors) = crate::tokenizer::tokenize(&repr, std::path::Path::new(""), 0);
+        assert!(errors.is_empty(), "{repr} doesn't tokenize: {errors:?}");
+        let parsed = parse_value(&mut tokens.into_iter())
+            .unwrap_or_else(|error| panic!("{repr} doesn't parse: {error}"));
+        assert!(same(&parsed, value), "{repr} parsed to {parsed:?}");
+        // Equal ints and floats compare equal, the repr tells them apart
+        assert_eq!(parsed.to_string(), repr);
+    }
+
+    #[test]
+    fn reprs_parse_back_to_the_value() {
+        let mut rng = Rng(0x5eed_1e55);
+        for _ in 0..2000 {
+            round_trip(&value(&mut rng, 0));
+        }
+    }
+
+    #[test]
+    fn edge_values_parse_back() {
+        for &int in INTS {
+            round_trip(&Value::Int(int));
+        }
+        for &float in FLOATS {
+            round_trip(&Value::Num(float));
+            round_trip(&Value::Num(-float));
+        }
+        round_trip(&Value::Str(CHARS.iter().collect()));
+        round_trip(&Value::List(vec![Value::List(vec![Value::List(vec![])])]));
+        for source in [
+            "(f '( (g \"a\\n\" [1 [2.0 true]] ) )' )",
+            "(f `( (g _.a \"\\\"\" ) ($code ) )` )",
+        ] {
+            let ArgumentCont::Value(value) = only_argument(source).cont else {
+                panic!("{source:?} isn't a literal");
+            };
+            round_trip(&value);
+        }
     }
-    Ok(exprs)
 }
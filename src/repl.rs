@@ -0,0 +1,243 @@
+//! An interactive session keeping one context alive between inputs, for `lyss repl`
+//!
+//! Inputs are run as they are entered, lines starting with `:` are commands for the session
+//! itself. The input/output loop is left to the host, this only turns inputs into replies.
+
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use crate::diagnostic::Diagnostic;
+use crate::runtime::HostContext;
+use crate::runtime::object::ObjectSearch;
+use crate::span::SourceMap;
+use crate::{LyssCompError, LyssRuntimeError, parser, stdlib, tokenizer};
+
+pub const HELP: &str = "\
+:vars           lists the variables and their values
+:funcs [path]   lists the functions, only the ones under `path` when it's given
+:load <file>    runs a file in the session
+:reset          starts over with a fresh context
+:help           shows this
+:quit           ends the session";
+
+/// What the session answers to an input
+#[derive(Debug)]
+pub enum Reply {
+    /// Text to show, empty when there's nothing to show
+    Output(String),
+    /// Rendered diagnostics
+    Error(String),
+    /// The session is over and the host should end with this code
+    Exit(i32),
+}
+
+#[derive(Debug)]
+pub struct Repl {
+    ctx: HostContext,
+    /// Every input so far, so diagnostics can show them
+    sources: SourceMap,
+    inputs: usize,
+}
+
+impl Repl {
+    /// A session with the whole `Builtin` library
+    pub fn new() -> Result<Repl, LyssRuntimeError> {
+        let mut ctx = HostContext::new();
+        stdlib::install(&mut ctx)?;
+        Ok(Repl {
+            ctx,
+            sources: SourceMap::new(),
+            inputs: 0,
+        })
+    }
+    /// Whether `input` stops in the middle of something, like a string, a list or a code block
+    /// missing its closing quote, so more lines should be read before running it
+    #[must_use]
+    pub fn is_incomplete(input: &str) -> bool {
+        let (tokens, errors) = tokenizer::tokenize(input, Path::new("<repl>"), 0);
+        if !errors.is_empty() {
+            return errors.iter().any(is_eof);
+        }
+        parser::parse(&mut tokens.into_iter()).1.iter().any(is_eof)
+    }
+    /// Runs an input or a `:` command
+    pub fn eval(&mut self, input: &str) -> Reply {
+        if let Some(command) = input.trim().strip_prefix(':') {
+            return self.command(command);
+        }
+        self.inputs += 1;
+        let name = PathBuf::from(format!("<repl:{}>", self.inputs));
+        self.run(&name, input.to_owned())
+    }
+    fn command(&mut self, command: &str) -> Reply {
+        let (name, arg) = command
+            .split_once(char::is_whitespace)
+            .map_or((command, ""), |(name, arg)| (name, arg.trim()));
+        match (name, arg) {
+            ("vars", "") => Reply::Output(self.vars()),
+            ("funcs", prefix) => Reply::Output(self.funcs(prefix)),
+            ("load", "") => Reply::Error("`:load` needs a file".to_owned()),
+            ("load", path) => match std::fs::read_to_string(path) {
+                Ok(file) => self.run(Path::new(path), file),
+                Err(error) => Reply::Error(format!("can't read {path}: {error}")),
+            },
+            ("reset", "") => match Repl::new() {
+                Ok(fresh) => {
+                    *self = fresh;
+                    Reply::Output(String::new())
+                }
+                Err(error) => Reply::Error(error.to_string()),
+            },
+            ("help", "") => Reply::Output(HELP.to_owned()),
+            ("quit" | "q", "") => Reply::Exit(0),
+            _ => Reply::Error(format!("unknown command `:{command}`, see `:help`")),
+        }
+    }
+    fn run(&mut self, name: &Path, content: String) -> Reply {
+        let file_id = self.sources.add(name, content);
+        let content = self.sources.get(file_id).map_or("", |file| &file.content);
+        let (tokens, mut errors) = tokenizer::tokenize(content, name, file_id);
+        let (exprs, parse_errors) = parser::parse(&mut tokens.into_iter());
+        errors.extend(parse_errors);
+        if !errors.is_empty() {
+            return Reply::Error(
+                errors
+                    .iter()
+                    .map(|error| Diagnostic::from(error).render(&self.sources))
+                    .collect(),
+            );
+        }
+        match self.ctx.run(&exprs) {
+            Ok(value) => Reply::Output(value.map(|value| value.to_string()).unwrap_or_default()),
+            Err(error) => {
+                if let LyssRuntimeError::Exit { code } = error.unlocated() {
+                    return Reply::Exit(*code);
+                }
+                Reply::Error(Diagnostic::from_runtime(&error, &self.ctx).render(&self.sources))
+            }
+        }
+    }
+    /// `name = value` for every variable, sorted by name
+    fn vars(&self) -> String {
+        let variables = self.ctx.variables.borrow();
+        let mut vars: Vec<_> = variables
+            .iter()
+            .map(|(slot, value)| (slot.name(), value))
+            .collect();
+        vars.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let mut output = String::new();
+        for (name, value) in vars {
+            let _ = writeln!(output, "$.{name} = {value}");
+        }
+        output
+    }
+    /// The path of every function under `prefix`, sorted
+    fn funcs(&self, prefix: &str) -> String {
+        let prefix: Vec<String> = prefix
+            .split('.')
+            .filter(|sec| !sec.is_empty())
+            .map(str::to_owned)
+            .collect();
+        let mut paths: Vec<String> = self
+            .ctx
+            .functions
+            .paths()
+            .into_iter()
+            .filter(|path| path.starts_with(&prefix))
+            .filter(|path| matches!(self.ctx.functions.find(path), Ok(ObjectSearch::Leaf(_))))
+            .map(|path| path.join("."))
+            .collect();
+        paths.sort();
+        paths.into_iter().map(|path| path + "\n").collect()
+    }
+}
+
+/// Whether the error is about the input ending too early
+fn is_eof(error: &LyssCompError) -> bool {
+    matches!(
+        error,
+        LyssCompError::UnexpectedEof { .. } | LyssCompError::UnterminatedString { .. }
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempFile;
+
+    #[track_caller]
+    fn output(reply: Reply) -> String {
+        match reply {
+            Reply::Output(output) => output,
+            reply => panic!("expected output, got {reply:?}"),
+        }
+    }
+
+    #[track_caller]
+    fn error(reply: Reply) -> String {
+        match reply {
+            Reply::Error(error) => error,
+            reply => panic!("expected an error, got {reply:?}"),
+        }
+    }
+
+    #[test]
+    fn inputs_share_the_context() {
+        let mut repl = Repl::new().unwrap();
+        assert_eq!(output(repl.eval("(Builtin.local $.x 2 )\n")), "2");
+        assert_eq!(output(repl.eval("(Builtin.Math.+ $.x 1 )\n")), "3");
+        assert_eq!(output(repl.eval(":vars")), "$.x = 2\n");
+        assert_eq!(output(repl.eval(":reset")), "");
+        assert_eq!(output(repl.eval(":vars")), "");
+    }
+
+    #[test]
+    fn errors_point_at_their_input() {
+        let mut repl = Repl::new().unwrap();
+        output(repl.eval("(Builtin.list )\n"));
+        assert!(error(repl.eval("(missing )\n")).contains("<repl:2>:1:1"));
+        assert!(error(repl.eval("(print \n")).contains("<repl:3>"));
+    }
+
+    #[test]
+    fn commands() {
+        let mut repl = Repl::new().unwrap();
+        assert_eq!(output(repl.eval(":help")), HELP);
+        assert_eq!(
+            output(repl.eval(":funcs Builtin.List")),
+            "Builtin.List.filter\nBuiltin.List.map\n"
+        );
+        assert!(error(repl.eval(":load")).contains("needs a file"));
+        assert!(error(repl.eval(":load /nonexistent/lyss.ls")).starts_with("can't read"));
+        assert!(error(repl.eval(":nope")).contains("unknown command `:nope`"));
+        assert!(matches!(repl.eval(":quit"), Reply::Exit(0)));
+        assert!(matches!(repl.eval("(Builtin.exit 3 )"), Reply::Exit(3)));
+    }
+
+    #[test]
+    fn load_runs_the_file_in_the_session() {
+        let file = TempFile::new("repl", "(Builtin.local $.loaded \"yes\" )");
+        let mut repl = Repl::new().unwrap();
+        assert_eq!(
+            output(repl.eval(&format!(":load {}", file.path.display()))),
+            "\"yes\""
+        );
+        assert_eq!(output(repl.eval(":vars")), "$.loaded = \"yes\"\n");
+    }
+
+    #[test]
+    fn unfinished_inputs_are_incomplete() {
+        for input in [
+            "(print ",
+            "(print \"abc",
+            "(if '( (a ) )' '(\n",
+            "(print \"\"\"\n\ttext\n",
+            "!(twice (print )",
+        ] {
+            assert!(Repl::is_incomplete(input), "{input:?}");
+        }
+        for input in ["(print 1 )", "(print ) )", "(print \"\\q\" )", ""] {
+            assert!(!Repl::is_incomplete(input), "{input:?}");
+        }
+    }
+}
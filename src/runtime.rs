@@ -1,34 +1,240 @@
 pub mod api;
+pub mod convert;
+pub mod function;
+pub mod macros;
 pub mod object;
+pub mod vm;
 
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 use std::rc::Rc;
 
-use crate::parser::{Argument, Atom, Expr};
+use crate::parser::{Argument, ArgumentCont, Atom, Code, Expr};
+use crate::tokenizer::syntax::Syntax;
 use crate::{LyssRuntimeError, Value};
+use function::Function;
+use macros::{Expansion, Macro};
 use object::*;
 
 pub type HostContext = Context<'static>;
 type ParentContext<'p> = &'p Context<'p>;
 
-#[derive(Debug, Clone)]
-pub struct HostFunc(
-    pub fn(&mut Context, arguments: &[Argument]) -> Result<Value, LyssRuntimeError>,
-);
+pub type HostFn = dyn Fn(&mut Context, &[Argument]) -> Result<Value, LyssRuntimeError>;
+/// Takes the values of the arguments along with the arguments as written, for errors
+pub type StrictFn =
+    dyn Fn(&mut Context, Vec<Value>, &[Argument]) -> Result<Value, LyssRuntimeError>;
+
+/// A function implemented by the host, may capture state from the embedding application
+#[derive(Clone)]
+pub struct HostFunc {
+    func: Rc<HostFn>,
+    /// Set for functions that evaluate all their arguments before anything else, along with how
+    /// many they take if it's fixed. Compiled code evaluates the arguments itself and calls this
+    strict: Option<(Option<usize>, Rc<StrictFn>)>,
+}
 
 impl HostFunc {
-    fn call(self, ctx: &mut Context, arguments: &[Argument]) -> Result<Value, LyssRuntimeError> {
-        self.0(ctx, arguments)
+    /// A function getting its arguments as written, it evaluates the ones it needs itself
+    pub fn new(
+        func: impl Fn(&mut Context, &[Argument]) -> Result<Value, LyssRuntimeError> + 'static,
+    ) -> HostFunc {
+        HostFunc {
+            func: Rc::new(func),
+            strict: None,
+        }
+    }
+    /// A function taking exactly `arity` arguments, evaluated in order before it's called
+    pub fn strict(
+        arity: usize,
+        func: impl Fn(&mut Context, Vec<Value>, &[Argument]) -> Result<Value, LyssRuntimeError>
+        + 'static,
+    ) -> HostFunc {
+        HostFunc::evaluating(Some(arity), Rc::new(func))
+    }
+    /// A function taking any number of arguments, evaluated in order before it's called
+    pub fn variadic(
+        func: impl Fn(&mut Context, Vec<Value>, &[Argument]) -> Result<Value, LyssRuntimeError>
+        + 'static,
+    ) -> HostFunc {
+        HostFunc::evaluating(None, Rc::new(func))
+    }
+    fn evaluating(arity: Option<usize>, strict: Rc<StrictFn>) -> HostFunc {
+        let func = Rc::clone(&strict);
+        HostFunc {
+            func: Rc::new(move |ctx, arguments| {
+                if let Some(arity) = arity {
+                    api::Api::assert_args_count(arguments, arity)?;
+                }
+                let values = api::Api::eval_all(ctx, arguments)?;
+                func(ctx, values, arguments)
+            }),
+            strict: Some((arity, strict)),
+        }
+    }
+    /// Wraps a closure that mutates its captured state, calling it again while it's still
+    /// running fails with [`LyssRuntimeError::ReentrantHostFunc`]
+    pub fn new_mut(
+        func: impl FnMut(&mut Context, &[Argument]) -> Result<Value, LyssRuntimeError> + 'static,
+    ) -> HostFunc {
+        let func = RefCell::new(func);
+        HostFunc::new(move |ctx, arguments| {
+            let mut func = func
+                .try_borrow_mut()
+                .map_err(|_| LyssRuntimeError::ReentrantHostFunc)?;
+            func(ctx, arguments)
+        })
+    }
+    fn call(&self, ctx: &mut Context, arguments: &[Argument]) -> Result<Value, LyssRuntimeError> {
+        (self.func)(ctx, arguments)
+    }
+}
+
+impl std::fmt::Debug for HostFunc {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "HostFunc({:p})", Rc::as_ptr(&self.func))
+    }
+}
+
+/// How a context runs code, both give the same results
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Engine {
+    /// Walks the AST, resolving every call as it gets to it
+    Tree,
+    /// Compiles code blocks once and runs them on the [`vm`], caching what calls resolve to
+    #[default]
+    Bytecode,
+}
+
+/// Changes every time a function, scope or macro is added to a context, so cached lookups and
+/// expansions can tell they might be stale. Values are never reused, even across context trees
+#[derive(Debug, Clone)]
+struct Generation(Rc<Cell<u64>>);
+
+impl Generation {
+    fn next() -> u64 {
+        thread_local! {
+            static LAST: Cell<u64> = const { Cell::new(0) };
+        }
+        LAST.with(|last| {
+            last.set(last.get() + 1);
+            last.get()
+        })
+    }
+    fn get(&self) -> u64 {
+        self.0.get()
+    }
+    fn bump(&self) {
+        self.0.set(Generation::next());
+    }
+}
+
+impl Default for Generation {
+    fn default() -> Generation {
+        Generation(Rc::new(Cell::new(Generation::next())))
+    }
+}
+
+/// A field of [`Context`] open to direct edits, every mutable access bumps the generation so the
+/// bytecode engine drops what it cached about it
+pub struct Tracked<T> {
+    value: T,
+    generation: Generation,
+}
+
+impl<T> Tracked<T> {
+    fn new(value: T, generation: &Generation) -> Tracked<T> {
+        Tracked {
+            value,
+            generation: generation.clone(),
+        }
+    }
+}
+
+impl<T> std::ops::Deref for Tracked<T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T> std::ops::DerefMut for Tracked<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.generation.bump();
+        &mut self.value
+    }
+}
+
+impl<T: std::fmt::Debug> std::fmt::Debug for Tracked<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.value.fmt(f)
+    }
+}
+
+/// A variable name interned to the index it's stored at. Every context on the thread gives a
+/// name the same slot, so compiled code holds slots instead of names
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Slot(usize);
+
+/// The name of every slot and the slot of every name
+#[derive(Default)]
+struct Slots {
+    names: Vec<Rc<str>>,
+    slots: HashMap<Rc<str>, Slot>,
+}
+
+thread_local! {
+    static SLOTS: RefCell<Slots> = RefCell::default();
+}
+
+impl Slot {
+    /// The slot of the variable `name`
+    #[must_use]
+    pub fn of(name: &str) -> Slot {
+        SLOTS.with_borrow_mut(|interned| {
+            if let Some(slot) = interned.slots.get(name) {
+                return *slot;
+            }
+            let slot = Slot(interned.names.len());
+            let name: Rc<str> = Rc::from(name);
+            interned.names.push(Rc::clone(&name));
+            interned.slots.insert(name, slot);
+            slot
+        })
+    }
+    /// The name the slot was interned from
+    #[must_use]
+    pub fn name(self) -> Rc<str> {
+        SLOTS.with_borrow(|interned| Rc::clone(&interned.names[self.0]))
     }
 }
 
-#[derive(Debug, Default)]
+impl std::fmt::Debug for Slot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Slot({}, {:?})", self.0, self.name())
+    }
+}
+
+#[derive(Debug)]
 pub struct Context<'p> {
     pub paret: Option<ParentContext<'p>>,
     pub object_store: Object<Value>,
-    pub functions: Object<HostFunc>,
-    pub scopes: Vec<Rc<Object<HostFunc>>>,
-    pub variables: HashMap<String, Value>,
+    pub functions: Tracked<Object<Function>>,
+    pub scopes: Tracked<Vec<Rc<Object<Function>>>>,
+    pub variables: RefCell<HashMap<Slot, Value>>,
+    pub macros: Tracked<HashMap<String, Macro>>,
+    /// How macro contents are tokenized, shared with the children
+    pub syntax: Rc<Syntax>,
+    /// Inherited by the children
+    pub engine: Engine,
+    /// Shared with the children
+    generation: Generation,
+    /// How many macro expansions are running, shared with the children so expansions nested
+    /// through code blocks count too
+    macro_depth: Rc<Cell<usize>>,
+    /// How many script function calls are running, shared with the children
+    call_depth: Rc<Cell<usize>>,
+    /// The expansions recorded for `--dump-expanded`, shared with the children
+    expansions: Option<Rc<RefCell<Vec<Expansion>>>>,
 }
 
 impl HostContext {
@@ -38,46 +244,275 @@ impl HostContext {
     }
 }
 
+impl Default for Context<'_> {
+    fn default() -> Self {
+        let generation = Generation::default();
+        Context {
+            paret: None,
+            object_store: Object::default(),
+            functions: Tracked::new(Object::default(), &generation),
+            scopes: Tracked::new(vec![], &generation),
+            variables: RefCell::default(),
+            macros: Tracked::new(HashMap::new(), &generation),
+            syntax: Rc::default(),
+            engine: Engine::default(),
+            generation,
+            macro_depth: Rc::default(),
+            call_depth: Rc::default(),
+            expansions: None,
+        }
+    }
+}
+
+impl<'p> Context<'p> {
+    #[must_use]
+    pub fn child(paret: ParentContext<'p>) -> Context<'p> {
+        // Not `..Context::default()`, that would allocate a syntax and a generation per call
+        Context {
+            paret: Some(paret),
+            object_store: Object::default(),
+            functions: Tracked::new(Object::default(), &paret.generation),
+            scopes: Tracked::new(vec![], &paret.generation),
+            variables: RefCell::default(),
+            macros: Tracked::new(HashMap::new(), &paret.generation),
+            syntax: Rc::clone(&paret.syntax),
+            engine: paret.engine,
+            generation: paret.generation.clone(),
+            macro_depth: Rc::clone(&paret.macro_depth),
+            call_depth: Rc::clone(&paret.call_depth),
+            expansions: paret.expansions.clone(),
+        }
+    }
+}
+
 impl Context<'_> {
+    /// Runs `code` with the engine of this context, giving the value of the last expression
     pub fn run(&mut self, code: &[Expr]) -> Result<Option<Value>, LyssRuntimeError> {
+        if self.engine == Engine::Bytecode {
+            return vm::run(self, &vm::Chunk::compile(code));
+        }
         let mut result = Ok(None);
         for expr in code {
             result = Ok(Some(self.execute_expr(expr)?));
         }
         result
     }
-    pub fn register(&mut self, name: String, entry: ObjectEntry<HostFunc>) {
+    /// Like [`Context::run`], but with the bytecode engine a block is only compiled once
+    pub fn run_code(&mut self, code: &Code) -> Result<Option<Value>, LyssRuntimeError> {
+        match self.engine {
+            Engine::Tree => self.run(&code.exprs),
+            Engine::Bytecode => vm::run(self, code.chunk()),
+        }
+    }
+    pub fn register(&mut self, name: String, entry: ObjectEntry<Function>) {
         self.functions.0.insert(name, entry);
     }
-    pub fn register_object(&mut self, name: String, entry: Object<HostFunc>) {
+    pub fn register_object(&mut self, name: String, entry: Object<Function>) {
         self.functions
             .0
             .insert(name, ObjectEntry::Branch(Rc::new(entry)));
     }
-    pub fn register_entry(&mut self, name: String, entry: ObjectEntry<HostFunc>) {
+    pub fn register_entry(&mut self, name: String, entry: ObjectEntry<Function>) {
         self.functions.0.insert(name, entry);
     }
-    pub fn set_var(&mut self, name: String, value: Value) {
-        self.variables.insert(name, value);
+    /// Makes the functions of `object` callable without its path
+    pub fn add_scope(&mut self, object: Rc<Object<Function>>) {
+        self.scopes.push(object);
+    }
+    /// Registers `entry` at a dotted `path` like `Db.query`, creating the objects in between
+    pub fn register_path(
+        &mut self,
+        path: &str,
+        entry: ObjectEntry<Function>,
+    ) -> Result<(), LyssRuntimeError> {
+        let path: Vec<String> = path.split('.').map(str::to_owned).collect();
+        self.functions.insert_path(&path, entry)
+    }
+    /// Stores `value` at `path`, so the bare path evaluates to it
+    pub fn register_value(&mut self, path: &str, value: Value) -> Result<(), LyssRuntimeError> {
+        let path: Vec<String> = path.split('.').map(str::to_owned).collect();
+        self.generation.bump();
+        self.object_store
+            .insert_path(&path, ObjectEntry::Leaf(value))
+    }
+    pub fn register_fn(
+        &mut self,
+        path: &str,
+        func: impl Fn(&mut Context, &[Argument]) -> Result<Value, LyssRuntimeError> + 'static,
+    ) -> Result<(), LyssRuntimeError> {
+        self.register_path(path, ObjectEntry::Leaf(HostFunc::new(func).into()))
+    }
+    /// Like [`Context::register_fn`], for functions that need the values of all their arguments
+    pub fn register_variadic(
+        &mut self,
+        path: &str,
+        func: impl Fn(&mut Context, Vec<Value>, &[Argument]) -> Result<Value, LyssRuntimeError>
+        + 'static,
+    ) -> Result<(), LyssRuntimeError> {
+        self.register_path(path, ObjectEntry::Leaf(HostFunc::variadic(func).into()))
+    }
+    /// Runs `code` in a child context, so its bindings don't leak into this one
+    pub fn run_block(&self, code: &Code) -> Result<Option<Value>, LyssRuntimeError> {
+        Context::child(self).run_code(code)
+    }
+    /// Binds `name` in this context, shadowing any binding from the parents
+    pub fn set_var(&mut self, name: &str, value: Value) {
+        self.set_slot(Slot::of(name), value);
+    }
+    /// Like [`Context::set_var`], for an interned name
+    pub fn set_slot(&mut self, slot: Slot, value: Value) {
+        self.variables.get_mut().insert(slot, value);
     }
-    pub fn get_var(&mut self, name: &str) -> Option<&Value> {
-        self.variables.get(name)
+    /// Updates the closest binding of `name`, or binds it in the outermost context if there is
+    /// none
+    pub fn set_extern_var(&self, name: &str, value: Value) {
+        let slot = Slot::of(name);
+        let mut ctx: &Context = self;
+        loop {
+            if ctx.variables.borrow().contains_key(&slot) {
+                break;
+            }
+            match ctx.paret {
+                Some(paret) => ctx = paret,
+                None => break,
+            }
+        }
+        ctx.variables.borrow_mut().insert(slot, value);
+    }
+    /// Finds the closest binding of `name`
+    pub fn get_var(&self, name: &str) -> Option<Value> {
+        self.get_slot(Slot::of(name))
+    }
+    /// Like [`Context::get_var`], for an interned name
+    pub fn get_slot(&self, slot: Slot) -> Option<Value> {
+        let mut ctx: &Context = self;
+        loop {
+            if let Some(value) = ctx.variables.borrow().get(&slot) {
+                return Some(value.clone());
+            }
+            ctx = ctx.paret?;
+        }
     }
     fn execute_expr(&mut self, expr: &Expr) -> Result<Value, LyssRuntimeError> {
         match &expr.cont {
             crate::parser::ExprCont::Atom(atom) => self.execute_atom(atom),
             //crate::parser::ExprCont::Code(code) => todo!(),
-            crate::parser::ExprCont::Macro(m) => todo!("Execute macros {m}"),
+            crate::parser::ExprCont::Macro(m) => self.execute_macro(m),
+            crate::parser::ExprCont::Hole(hole) => Err(LyssRuntimeError::UnfilledHole {
+                name: hole.name.clone(),
+            }),
+            crate::parser::ExprCont::Error => Err(LyssRuntimeError::SyntaxError),
         }
+        .map_err(|e| e.located(expr.span))
     }
     pub fn execute_atom(&mut self, atom: &Atom) -> Result<Value, LyssRuntimeError> {
-        for scope in &self.scopes {
-            if let Ok(host_fn) = scope.find_leaf(&atom.fn_name.0) {
-                return host_fn.call(self, &atom.arguments);
-            }
+        self.resolve_callee(&atom.fn_name.0)
+            .and_then(|(func, depth)| func.call(self, depth, &atom.arguments))
+            .map_err(|e| e.located(atom.span))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{both, run_in};
+
+    #[test]
+    fn block_locals_dont_leak() {
+        let outcome = both(
+            "(local $.x 1 ) (if '( (= 1 1 ) )' '( (local $.x 2 ) )' else '( (list ) )' ) \
+             (list $.x )",
+        );
+        assert_eq!(outcome.result, Ok(Some("[1]".to_owned())));
+    }
+
+    #[test]
+    fn extern_updates_the_closest_binding() {
+        let outcome = both(
+            "(local $.x 1 ) (if '( (= 1 1 ) )' '( (extern $.x 2 ) )' else '( (list ) )' ) \
+             (list $.x )",
+        );
+        assert_eq!(outcome.result, Ok(Some("[2]".to_owned())));
+    }
+
+    #[test]
+    fn extern_without_a_binding_binds_in_the_root() {
+        let outcome =
+            both("(if '( (= 1 1 ) )' '( (extern $.y 3 ) )' else '( (list ) )' ) (list $.y )");
+        assert_eq!(outcome.result, Ok(Some("[3]".to_owned())));
+    }
+
+    #[test]
+    fn functions_dont_see_the_locals_of_their_caller() {
+        let outcome = both(
+            "(defn \"peek\" (list ) '( (list $.secret ) )' ) \
+             (if '( (= 1 1 ) )' '( (local $.secret 1 ) (peek ) )' else '( (list ) )' )",
+        );
+        assert!(outcome.error().starts_with("error[E0103]: "));
+    }
+
+    #[test]
+    fn host_functions_keep_captured_state() {
+        let calls = Rc::new(Cell::new(0));
+        let mut ctx = HostContext::new();
+        let counted = Rc::clone(&calls);
+        ctx.register_fn("count", move |_, _| {
+            counted.set(counted.get() + 1);
+            Ok(Value::Bool(true))
+        })
+        .unwrap();
+        let mut total = 0;
+        ctx.register_path(
+            "tally",
+            ObjectEntry::Leaf(
+                HostFunc::new_mut(move |_, _| {
+                    total += 1;
+                    Ok(Value::Int(total))
+                })
+                .into(),
+            ),
+        )
+        .unwrap();
+        let result = run_in(&mut ctx, "(count ) (count ) (tally ) (tally )").unwrap();
+        assert_eq!(calls.get(), 2);
+        assert_eq!(result.map(|value| value.to_string()), Some("2".to_owned()));
+    }
+
+    #[test]
+    fn mutable_host_functions_arent_reentrant() {
+        for engine in [Engine::Tree, Engine::Bytecode] {
+            let mut ctx = HostContext::new();
+            ctx.engine = engine;
+            ctx.register_path(
+                "reenter",
+                ObjectEntry::Leaf(
+                    HostFunc::new_mut(|ctx, args| {
+                        api::Api::eval_all(ctx, args).map(|_| Value::Bool(true))
+                    })
+                    .into(),
+                ),
+            )
+            .unwrap();
+            assert!(run_in(&mut ctx, "(reenter 1 )").is_ok());
+            let error = run_in(&mut ctx, "(reenter (reenter 1 ) )").unwrap_err();
+            assert_eq!(error.code(), "E0114");
         }
-        self.functions
-            .find_leaf(&atom.fn_name.0)?
-            .call(self, &atom.arguments)
+    }
+
+    #[test]
+    fn names_keep_their_slot() {
+        let slot = Slot::of("slot-test");
+        assert_eq!(Slot::of("slot-test"), slot);
+        assert_ne!(Slot::of("slot-test-2"), slot);
+        assert_eq!(&*slot.name(), "slot-test");
+        let mut ctx = HostContext::new();
+        ctx.set_var("slot-test", Value::Int(1));
+        let mut child = Context::child(&ctx);
+        child.set_slot(Slot::of("inner"), Value::Int(2));
+        assert_eq!(child.get_var("slot-test"), Some(Value::Int(1)));
+        assert_eq!(child.get_slot(slot), Some(Value::Int(1)));
+        let variables = child.variables.borrow();
+        assert_eq!(variables.len(), 1);
+        assert_eq!(variables.get(&Slot::of("inner")), Some(&Value::Int(2)));
     }
 }
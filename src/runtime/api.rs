@@ -1,28 +1,36 @@
 use crate::parser::FnName;
 
 use super::*;
+use convert::FromLyss;
 pub struct Api;
 
 impl Context<'_> {
     pub fn eval_argument(&mut self, argument: &Argument) -> Result<Value, LyssRuntimeError> {
-        Ok(match argument {
-            Argument::Var(name) => self
-                .get_var(name)
-                .ok_or(LyssRuntimeError::VarNotFound {
-                    name: name.to_owned(),
-                })?
-                .clone(),
-            Argument::Atom(atom) => self.execute_atom(atom)?,
-            Argument::Value(v) => v.clone(),
-            Argument::Macro(m) => todo!("macro argument {m}"),
-            Argument::Ident(path) => todo!(),
-        })
+        match &argument.cont {
+            ArgumentCont::Var(name) => self.get_var(name).ok_or(LyssRuntimeError::VarNotFound {
+                name: name.to_owned(),
+            }),
+            ArgumentCont::Atom(atom) => self.execute_atom(atom),
+            ArgumentCont::Value(v) => Ok(v.clone()),
+            ArgumentCont::Macro(m) => self.execute_macro(m),
+            ArgumentCont::Ident(path) => self.resolve_path(&path.0),
+            ArgumentCont::Hole(hole) => Err(LyssRuntimeError::UnfilledHole {
+                name: hole.name.clone(),
+            }),
+            ArgumentCont::List(items) => items
+                .iter()
+                .map(|item| self.eval_argument(item))
+                .collect::<Result<_, _>>()
+                .map(Value::List),
+            ArgumentCont::Error => Err(LyssRuntimeError::SyntaxError),
+        }
+        .map_err(|e| e.located(argument.span))
     }
 }
 
 impl Api {
     pub fn expect_this_text(argument: &Argument, text: &str) -> Result<(), LyssRuntimeError> {
-        if let Argument::Ident(path) = argument
+        if let ArgumentCont::Ident(path) = &argument.cont
             && path.0.len() == 1
             && path.0.first().map(String::as_str) == Some(text)
         {
@@ -30,38 +38,56 @@ impl Api {
         } else {
             Err(LyssRuntimeError::LiteralNotFound {
                 expected: text.to_string(),
-                got: argument.clone(),
+                got: Box::new(argument.clone()),
             })
         }
     }
     #[must_use]
     pub fn expect_var(argument: &Argument) -> Option<&str> {
-        match argument {
-            Argument::Var(txt) => Some(txt),
+        match &argument.cont {
+            ArgumentCont::Var(txt) => Some(txt),
             _ => None,
         }
     }
     #[must_use]
     pub fn expect_ident(argument: &Argument) -> Option<&FnName> {
-        match argument {
-            Argument::Ident(cnt) => Some(cnt),
+        match &argument.cont {
+            ArgumentCont::Ident(cnt) => Some(cnt),
             _ => None,
         }
     }
     #[must_use]
     pub fn expect_atom(argument: &Argument) -> Option<&Atom> {
-        match argument {
-            Argument::Atom(atom) => Some(atom),
+        match &argument.cont {
+            ArgumentCont::Atom(atom) => Some(atom),
             _ => None,
         }
     }
     #[must_use]
     pub fn expect_literal(argument: &Argument) -> Option<&Value> {
-        match argument {
-            Argument::Value(v) => Some(v),
+        match &argument.cont {
+            ArgumentCont::Value(v) => Some(v),
             _ => None,
         }
     }
+    /// Evaluates `argument` and converts it, failing with [`LyssRuntimeError::UnexpectedArg`]
+    pub fn eval_as<T: FromLyss>(
+        ctx: &mut Context,
+        argument: &Argument,
+    ) -> Result<T, LyssRuntimeError> {
+        Api::convert(ctx.eval_argument(argument)?, argument)
+    }
+    /// Converts the value of `argument`, failing with [`LyssRuntimeError::UnexpectedArg`]
+    pub fn convert<T: FromLyss>(value: Value, argument: &Argument) -> Result<T, LyssRuntimeError> {
+        T::from_lyss(value).ok_or_else(|| LyssRuntimeError::UnexpectedArg {
+            arg: Box::new(argument.clone()),
+            expected: T::EXPECTED,
+        })
+    }
+    /// Evaluates every argument
+    pub fn eval_all(ctx: &mut Context, args: &[Argument]) -> Result<Vec<Value>, LyssRuntimeError> {
+        args.iter().map(|arg| ctx.eval_argument(arg)).collect()
+    }
     pub fn needs_nth_arg(args: &[Argument], index: usize) -> Result<&Argument, LyssRuntimeError> {
         args.get(index).ok_or(LyssRuntimeError::NeedsArg)
     }
@@ -0,0 +1,359 @@
+//! Conversions between [`Value`]s and rust types, used to register plain rust functions as
+//! host functions with [`HostFunc::typed`].
+
+use crate::parser::{Code, Template};
+
+use super::*;
+use api::Api;
+use function::{Function, Param, Type};
+use macros::composer::MacroInput;
+
+pub trait FromLyss: Sized {
+    /// What kind of value is expected, used in [`LyssRuntimeError::UnexpectedArg`]
+    const EXPECTED: &'static str;
+    fn from_lyss(value: Value) -> Option<Self>;
+}
+
+pub trait IntoLyss {
+    fn into_lyss(self) -> Value;
+}
+
+/// What a typed host function may return, either a value or a result of one
+pub trait IntoLyssResult {
+    fn into_lyss_result(self) -> Result<Value, LyssRuntimeError>;
+}
+
+impl<T: IntoLyss> IntoLyssResult for T {
+    fn into_lyss_result(self) -> Result<Value, LyssRuntimeError> {
+        Ok(self.into_lyss())
+    }
+}
+
+impl<T: IntoLyss> IntoLyssResult for Result<T, LyssRuntimeError> {
+    fn into_lyss_result(self) -> Result<Value, LyssRuntimeError> {
+        self.map(IntoLyss::into_lyss)
+    }
+}
+
+impl FromLyss for Value {
+    const EXPECTED: &'static str = "any";
+    fn from_lyss(value: Value) -> Option<Self> {
+        Some(value)
+    }
+}
+
+impl IntoLyss for Value {
+    fn into_lyss(self) -> Value {
+        self
+    }
+}
+
+/// Integers are promoted to floats
+impl FromLyss for f64 {
+    const EXPECTED: &'static str = "number";
+    #[allow(clippy::cast_precision_loss)]
+    fn from_lyss(value: Value) -> Option<Self> {
+        match value {
+            Value::Num(n) => Some(n),
+            Value::Int(n) => Some(n as f64),
+            _ => None,
+        }
+    }
+}
+
+impl IntoLyss for f64 {
+    fn into_lyss(self) -> Value {
+        Value::Num(self)
+    }
+}
+
+impl FromLyss for i64 {
+    const EXPECTED: &'static str = "integer";
+    fn from_lyss(value: Value) -> Option<Self> {
+        match value {
+            Value::Int(n) => Some(n),
+            _ => None,
+        }
+    }
+}
+
+impl IntoLyss for i64 {
+    fn into_lyss(self) -> Value {
+        Value::Int(self)
+    }
+}
+
+impl FromLyss for bool {
+    const EXPECTED: &'static str = "boolean";
+    fn from_lyss(value: Value) -> Option<Self> {
+        match value {
+            Value::Bool(b) => Some(b),
+            _ => None,
+        }
+    }
+}
+
+impl IntoLyss for bool {
+    fn into_lyss(self) -> Value {
+        Value::Bool(self)
+    }
+}
+
+impl FromLyss for String {
+    const EXPECTED: &'static str = "string";
+    fn from_lyss(value: Value) -> Option<Self> {
+        match value {
+            Value::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+impl IntoLyss for String {
+    fn into_lyss(self) -> Value {
+        Value::Str(self)
+    }
+}
+
+impl IntoLyss for &str {
+    fn into_lyss(self) -> Value {
+        Value::Str(self.to_owned())
+    }
+}
+
+impl IntoLyss for Code {
+    fn into_lyss(self) -> Value {
+        Value::Code(self)
+    }
+}
+
+impl IntoLyss for Function {
+    fn into_lyss(self) -> Value {
+        Value::Func(self)
+    }
+}
+
+macro_rules! impl_from_variant {
+    ($($ty:ty => $variant:ident, $expected:literal;)*) => {$(
+        impl FromLyss for $ty {
+            const EXPECTED: &'static str = $expected;
+            fn from_lyss(value: Value) -> Option<Self> {
+                match value {
+                    Value::$variant(inner) => Some(inner),
+                    _ => None,
+                }
+            }
+        }
+    )*};
+}
+
+impl_from_variant! {
+    Code => Code, "code";
+    Function => Func, "function";
+    Template => Template, "template";
+    Type => Type, "type";
+    Param => Param, "parameter";
+    MacroInput => MacroInput, "macro input";
+}
+
+/// The empty list
+impl IntoLyss for () {
+    fn into_lyss(self) -> Value {
+        Value::List(vec![])
+    }
+}
+
+impl<T: FromLyss> FromLyss for Vec<T> {
+    const EXPECTED: &'static str = "list";
+    fn from_lyss(value: Value) -> Option<Self> {
+        match value {
+            Value::List(items) => items.into_iter().map(T::from_lyss).collect(),
+            _ => None,
+        }
+    }
+}
+
+impl<T: IntoLyss> IntoLyss for Vec<T> {
+    fn into_lyss(self) -> Value {
+        Value::List(self.into_iter().map(IntoLyss::into_lyss).collect())
+    }
+}
+
+/// A list with zero or one items, like the `maybe` type
+impl<T: FromLyss> FromLyss for Option<T> {
+    const EXPECTED: &'static str = "maybe";
+    fn from_lyss(value: Value) -> Option<Self> {
+        match value {
+            Value::List(items) if items.len() <= 1 => match items.into_iter().next() {
+                Some(item) => T::from_lyss(item).map(Some),
+                None => Some(None),
+            },
+            _ => None,
+        }
+    }
+}
+
+impl<T: IntoLyss> IntoLyss for Option<T> {
+    fn into_lyss(self) -> Value {
+        Value::List(self.into_iter().map(IntoLyss::into_lyss).collect())
+    }
+}
+
+/// Tuples are lists with exactly as many items
+macro_rules! impl_tuple {
+    ($($item:ident),+) => {
+        impl<$($item: FromLyss),+> FromLyss for ($($item,)+) {
+            const EXPECTED: &'static str = "list";
+            #[allow(non_snake_case)]
+            fn from_lyss(value: Value) -> Option<Self> {
+                let Value::List(items) = value else {
+                    return None;
+                };
+                let [$($item),+] = <[Value; count!($($item)+)]>::try_from(items).ok()?;
+                Some(($($item::from_lyss($item)?,)+))
+            }
+        }
+
+        impl<$($item: IntoLyss),+> IntoLyss for ($($item,)+) {
+            #[allow(non_snake_case)]
+            fn into_lyss(self) -> Value {
+                let ($($item,)+) = self;
+                Value::List(vec![$($item.into_lyss()),+])
+            }
+        }
+    };
+}
+
+macro_rules! count {
+    () => { 0 };
+    ($head:ident $($tail:ident)*) => { 1 + count!($($tail)*) };
+}
+
+impl_tuple!(A);
+impl_tuple!(A, B);
+impl_tuple!(A, B, C);
+impl_tuple!(A, B, C, D);
+
+/// A rust function that can be called from lyss, the `Args` tuple only tells the impls apart
+pub trait IntoHostFunc<Args> {
+    fn into_host_func(self) -> HostFunc;
+}
+
+macro_rules! impl_into_host_func {
+    ($($arg:ident),*) => {
+        impl<Func, Out, $($arg),*> IntoHostFunc<($($arg,)*)> for Func
+        where
+            Func: Fn($($arg),*) -> Out + 'static,
+            Out: IntoLyssResult,
+            $($arg: FromLyss,)*
+        {
+            #[allow(non_snake_case, unused_variables, unused_mut)]
+            fn into_host_func(self) -> HostFunc {
+                HostFunc::strict(count!($($arg)*), move |_, values, arguments| {
+                    let mut values = values.into_iter().zip(arguments);
+                    $(
+                        let $arg = match values.next() {
+                            Some((value, argument)) => Api::convert::<$arg>(value, argument)?,
+                            None => return Err(LyssRuntimeError::NeedsArg),
+                        };
+                    )*
+                    self($($arg),*).into_lyss_result()
+                })
+            }
+        }
+    };
+}
+
+impl_into_host_func!();
+impl_into_host_func!(A);
+impl_into_host_func!(A, B);
+impl_into_host_func!(A, B, C);
+impl_into_host_func!(A, B, C, D);
+impl_into_host_func!(A, B, C, D, E);
+impl_into_host_func!(A, B, C, D, E, F);
+
+impl HostFunc {
+    /// Wraps a plain rust function, the argument count is checked, then every argument is
+    /// evaluated and their types are checked before it's called
+    pub fn typed<Args>(func: impl IntoHostFunc<Args>) -> HostFunc {
+        func.into_host_func()
+    }
+}
+
+impl Context<'_> {
+    /// Like [`Context::register_fn`], for plain rust functions
+    pub fn register_typed<Args>(
+        &mut self,
+        path: &str,
+        func: impl IntoHostFunc<Args>,
+    ) -> Result<(), LyssRuntimeError> {
+        self.register_path(path, ObjectEntry::Leaf(HostFunc::typed(func).into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::run_in;
+
+    fn run_typed(source: &str) -> Result<Option<Value>, LyssRuntimeError> {
+        let mut ctx = HostContext::new();
+        ctx.register_fn("list", |ctx, args| {
+            let items = args
+                .iter()
+                .map(|arg| ctx.eval_argument(arg))
+                .collect::<Result<_, _>>()?;
+            Ok(Value::List(items))
+        })
+        .unwrap();
+        ctx.register_typed("scale", |items: Vec<f64>, by: f64| {
+            items.into_iter().map(|n| n * by).collect::<Vec<_>>()
+        })
+        .unwrap();
+        ctx.register_typed("head", |items: Vec<Value>| items.into_iter().next())
+            .unwrap();
+        ctx.register_typed("yes", || true).unwrap();
+        ctx.register_typed("pair", |pair: (String, bool)| (pair.1, pair.0))
+            .unwrap();
+        run_in(&mut ctx, source)
+    }
+
+    fn debug(source: &str) -> String {
+        format!("{:?}", run_typed(source).unwrap().unwrap())
+    }
+
+    #[test]
+    fn arguments_and_results_convert() {
+        assert_eq!(
+            debug("(scale (list 1 2 ) 2 )"),
+            "List([Num(2.0), Num(4.0)])"
+        );
+        assert_eq!(debug("(head (list ) )"), "List([])");
+        assert_eq!(debug(r#"(head (list "a" 1 ) )"#), r#"List([Str("a")])"#);
+        assert_eq!(
+            debug(r#"(pair (list "x" (yes ) ) )"#),
+            r#"List([Bool(true), Str("x")])"#
+        );
+    }
+
+    #[test]
+    fn mismatched_arguments_name_the_expected_type() {
+        let error = run_typed(r#"(scale (list "a" ) 2 )"#).unwrap_err();
+        assert!(matches!(
+            error,
+            LyssRuntimeError::UnexpectedArg { expected, .. } if expected.contains("list")
+        ));
+        assert!(matches!(
+            run_typed(r#"(pair (list "x" ) )"#),
+            Err(LyssRuntimeError::UnexpectedArg { .. })
+        ));
+    }
+
+    #[test]
+    fn argument_counts_are_checked() {
+        assert!(matches!(
+            run_typed("(scale (list 1 ) )"),
+            Err(LyssRuntimeError::TooFewArgs { needs: 2, .. })
+        ));
+    }
+}
@@ -0,0 +1,443 @@
+use crate::parser::Code;
+
+use super::*;
+use api::Api;
+
+/// How many script function calls may be nested inside each other before giving up, the thread
+/// running the script needs a stack big enough for them, see `STACK_SIZE` in `main.rs`
+pub const CALL_DEPTH_LIMIT: usize = 1000;
+
+#[derive(Debug, Clone)]
+pub enum Function {
+    Host(HostFunc),
+    Script(Rc<ScriptFunc>),
+}
+
+/// A function defined from lyss code with `defn`
+#[derive(Debug)]
+pub struct ScriptFunc {
+    pub params: Vec<Param>,
+    pub output: Type,
+    pub body: Code,
+}
+
+#[derive(Debug, Clone)]
+pub struct Param {
+    pub name: String,
+    pub kind: Type,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    Any,
+    Bool,
+    Str,
+    Num,
+    Int,
+    Code,
+    Func,
+    List(Box<Type>),
+    /// A list with zero or one items
+    Maybe(Box<Type>),
+}
+
+impl Type {
+    #[must_use]
+    pub fn matches(&self, value: &Value) -> bool {
+        match (self, value) {
+            (Type::Any, _)
+            | (Type::Bool, Value::Bool(_))
+            | (Type::Str, Value::Str(_))
+            | (Type::Num, Value::Num(_) | Value::Int(_))
+            | (Type::Int, Value::Int(_))
+            | (Type::Code, Value::Code(_))
+            | (Type::Func, Value::Func(_)) => true,
+            (Type::List(item), Value::List(items)) => items.iter().all(|i| item.matches(i)),
+            (Type::Maybe(item), Value::List(items)) => {
+                items.len() <= 1 && items.iter().all(|i| item.matches(i))
+            }
+            _ => false,
+        }
+    }
+    fn check(&self, value: Value) -> Result<Value, LyssRuntimeError> {
+        if self.matches(&value) {
+            Ok(value)
+        } else {
+            Err(LyssRuntimeError::WrongType {
+                expected: self.clone(),
+                got: value,
+            })
+        }
+    }
+}
+
+impl From<HostFunc> for Function {
+    fn from(host_fn: HostFunc) -> Self {
+        Function::Host(host_fn)
+    }
+}
+
+impl Function {
+    /// Calls the function found `depth` contexts above `ctx`, script functions run in a child of
+    /// that context
+    pub fn call(
+        self,
+        ctx: &mut Context,
+        depth: usize,
+        arguments: &[Argument],
+    ) -> Result<Value, LyssRuntimeError> {
+        match self {
+            Function::Host(host_fn) => host_fn.call(ctx, arguments),
+            Function::Script(script_fn) => {
+                Api::assert_args_count(arguments, script_fn.params.len())?;
+                let values = arguments
+                    .iter()
+                    .map(|arg| ctx.eval_argument(arg))
+                    .collect::<Result<Vec<_>, _>>()?;
+                script_fn.call(ctx.ancestor(depth), values)
+            }
+        }
+    }
+    /// Calls the function with already evaluated arguments, script functions run in a child
+    /// of `ctx`
+    pub fn call_values(
+        self,
+        ctx: &mut Context,
+        values: Vec<Value>,
+    ) -> Result<Value, LyssRuntimeError> {
+        let arguments: Vec<_> = values.into_iter().map(Argument::value).collect();
+        self.call(ctx, 0, &arguments)
+    }
+}
+
+impl ScriptFunc {
+    pub fn call(&self, parent: &Context, values: Vec<Value>) -> Result<Value, LyssRuntimeError> {
+        let depth = parent.call_depth.get();
+        if depth >= CALL_DEPTH_LIMIT {
+            return Err(LyssRuntimeError::CallDepthLimit);
+        }
+        parent.call_depth.set(depth + 1);
+        let result = self.run(parent, values);
+        parent.call_depth.set(depth);
+        result
+    }
+    fn run(&self, parent: &Context, values: Vec<Value>) -> Result<Value, LyssRuntimeError> {
+        let mut ctx = Context::child(parent);
+        for (param, value) in self.params.iter().zip(values) {
+            let value = param.kind.check(value)?;
+            ctx.set_var(&param.name, value);
+        }
+        let result = match ctx.run_code(&self.body) {
+            Ok(result) => result.ok_or(LyssRuntimeError::NeedsArg)?,
+            Err(LyssRuntimeError::Return { value }) => value,
+            Err(e) => return Err(e),
+        };
+        self.output.check(result)
+    }
+}
+
+impl Context<'_> {
+    /// Finds the function at `path` in this context or its parents, along with how many
+    /// contexts above this one it was found
+    pub fn resolve_function(&self, path: &[String]) -> Result<(Function, usize), LyssRuntimeError> {
+        let mut ctx: &Context = self;
+        let mut depth = 0;
+        let mut first_err = None;
+        loop {
+            for scope in ctx.scopes.iter() {
+                if let Ok(func) = scope.find_leaf(path) {
+                    return Ok((func, depth));
+                }
+            }
+            let err = match ctx.functions.find_leaf(path) {
+                Ok(func) => return Ok((func, depth)),
+                Err(e) => e,
+            };
+            match ctx.paret {
+                Some(paret) => {
+                    first_err.get_or_insert(err);
+                    ctx = paret;
+                    depth += 1;
+                }
+                None => return Err(first_err.unwrap_or(err)),
+            }
+        }
+    }
+    /// Evaluates a bare path, into a reference to a function or an object of functions, or into
+    /// a value of the object store
+    pub fn resolve_path(&self, path: &[String]) -> Result<Value, LyssRuntimeError> {
+        let to_value = |found: ObjectSearch<'_, Function>| match found {
+            ObjectSearch::Leaf(func) => Value::Func(func.clone()),
+            ObjectSearch::Branch(object) => Value::Object(Rc::clone(object)),
+        };
+        let mut ctx: &Context = self;
+        let mut first_err = None;
+        loop {
+            for scope in ctx.scopes.iter() {
+                if let Ok(found) = scope.find(path) {
+                    return Ok(to_value(found));
+                }
+            }
+            let err = match ctx.functions.find(path) {
+                Ok(found) => return Ok(to_value(found)),
+                Err(e) => e,
+            };
+            if let Ok(value) = ctx.object_store.find_leaf(path) {
+                return Ok(value);
+            }
+            match ctx.paret {
+                Some(paret) => {
+                    first_err.get_or_insert(err);
+                    ctx = paret;
+                }
+                None => return Err(first_err.unwrap_or(err)),
+            }
+        }
+    }
+    /// Finds the function stored in the variable named by the head of `path`, looking inside
+    /// the object when the variable holds one
+    fn resolve_var_function(&self, path: &[String]) -> Result<Function, LyssRuntimeError> {
+        let name = &path[1];
+        let value = self
+            .get_var(name)
+            .ok_or_else(|| LyssRuntimeError::VarNotFound { name: name.clone() })?;
+        match (value, &path[2..]) {
+            (Value::Func(func), []) => Ok(func),
+            (Value::Object(object), rest) if !rest.is_empty() => object.find_leaf(rest),
+            (value, _) => Err(LyssRuntimeError::UnexpectedArg {
+                arg: Box::new(Argument::value(value)),
+                expected: "function",
+            }),
+        }
+    }
+    /// Like [`Context::resolve_function`], but paths starting with `$` are taken from variables
+    pub fn resolve_callee(&self, path: &[String]) -> Result<(Function, usize), LyssRuntimeError> {
+        if path.len() >= 2 && path[0] == "$" {
+            Ok((self.resolve_var_function(path)?, 0))
+        } else {
+            self.resolve_function(path)
+        }
+    }
+    pub(crate) fn ancestor(&self, depth: usize) -> &Context<'_> {
+        let mut ctx: &Context = self;
+        for _ in 0..depth {
+            ctx = ctx
+                .paret
+                .expect("ancestor depth comes from resolve_function");
+        }
+        ctx
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Code;
+    use crate::runtime::object::ObjectEntry;
+    use crate::span::Span;
+    use crate::testing::{parse, run_in};
+
+    fn host(ctx: &mut HostContext, name: &str, host_fn: HostFunc) {
+        ctx.register(name.to_owned(), ObjectEntry::Leaf(Function::Host(host_fn)));
+    }
+
+    fn define(
+        ctx: &mut HostContext,
+        name: &str,
+        params: &[(&str, Type)],
+        output: Type,
+        body: &str,
+    ) {
+        let params = params
+            .iter()
+            .map(|(name, kind)| Param {
+                name: (*name).to_owned(),
+                kind: kind.clone(),
+            })
+            .collect();
+        let body = Code::new(Span::default(), parse(body));
+        let script_fn = ScriptFunc {
+            params,
+            output,
+            body,
+        };
+        ctx.register(
+            name.to_owned(),
+            ObjectEntry::Leaf(Function::Script(Rc::new(script_fn))),
+        );
+    }
+
+    /// A context with `sum`, `list`, `return` and `step`, which subtracts one from its argument
+    /// or returns `0` from the function calling it when it is zero, and the script functions
+    /// `add` on ints, `early` returning its list before running the unknown `unreachable`, `lie`
+    /// giving a list instead of an int and `down`, counting down to zero one call at a time
+    fn context() -> HostContext {
+        let mut ctx = HostContext::new();
+        host(
+            &mut ctx,
+            "sum",
+            HostFunc::new(|ctx, args| {
+                let mut sum = 0;
+                for arg in args {
+                    if let Value::Int(n) = ctx.eval_argument(arg)? {
+                        sum += n;
+                    }
+                }
+                Ok(Value::Int(sum))
+            }),
+        );
+        host(
+            &mut ctx,
+            "list",
+            HostFunc::new(|ctx, args| {
+                let items = args
+                    .iter()
+                    .map(|arg| ctx.eval_argument(arg))
+                    .collect::<Result<_, _>>()?;
+                Ok(Value::List(items))
+            }),
+        );
+        host(
+            &mut ctx,
+            "return",
+            HostFunc::new(|ctx, args| {
+                let value = ctx.eval_argument(Api::needs_nth_arg(args, 0)?)?;
+                Err(LyssRuntimeError::Return { value })
+            }),
+        );
+        host(
+            &mut ctx,
+            "step",
+            HostFunc::new(|ctx, args| {
+                let value = ctx.eval_argument(Api::needs_nth_arg(args, 0)?)?;
+                match value {
+                    Value::Int(n) if n < 1 => Err(LyssRuntimeError::Return {
+                        value: Value::Int(0),
+                    }),
+                    Value::Int(n) => Ok(Value::Int(n - 1)),
+                    value => Ok(value),
+                }
+            }),
+        );
+        define(
+            &mut ctx,
+            "add",
+            &[("a", Type::Int), ("b", Type::Int)],
+            Type::Int,
+            "(sum $.a $.b )",
+        );
+        define(
+            &mut ctx,
+            "early",
+            &[("items", Type::List(Box::new(Type::Any)))],
+            Type::Any,
+            "(return $.items ) (unreachable )",
+        );
+        define(&mut ctx, "lie", &[], Type::Int, r#"(list "no" )"#);
+        define(
+            &mut ctx,
+            "down",
+            &[("n", Type::Int)],
+            Type::Int,
+            "(down (step $.n ) )",
+        );
+        ctx
+    }
+
+    fn run(source: &str) -> Result<Value, LyssRuntimeError> {
+        run_in(&mut context(), source).map(|value| value.expect("the source has a value"))
+    }
+
+    #[test]
+    fn bodies_give_their_last_value() {
+        assert_eq!(run("(add 2 3 )").unwrap(), Value::Int(5));
+    }
+
+    #[test]
+    fn return_leaves_the_body() {
+        let value = run("(early (list 7 8 ) )").unwrap();
+        assert_eq!(format!("{value:?}"), "List([Int(7), Int(8)])");
+    }
+
+    #[test]
+    fn parameters_are_type_checked() {
+        assert!(matches!(
+            run(r#"(add 2 "3" )"#),
+            Err(LyssRuntimeError::WrongType {
+                expected: Type::Int,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn results_are_type_checked() {
+        assert!(matches!(
+            run("(lie )"),
+            Err(LyssRuntimeError::WrongType {
+                expected: Type::Int,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn argument_counts_are_checked() {
+        assert!(matches!(
+            run("(add 1 )"),
+            Err(LyssRuntimeError::TooFewArgs { needs: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn deep_recursion_fails_before_the_stack_runs_out() {
+        let run_deep = |source: String| {
+            std::thread::Builder::new()
+                .stack_size(256 << 20)
+                .spawn(move || format!("{:?}", run(&source)))
+                .unwrap()
+                .join()
+                .unwrap()
+        };
+        assert_eq!(run_deep("(down 100000 )".to_owned()), "Err(CallDepthLimit)");
+        let value = run_deep(format!("(down {} )", CALL_DEPTH_LIMIT - 1));
+        assert_eq!(value, "Ok(Int(0))");
+    }
+
+    #[test]
+    fn paths_evaluate_to_functions_and_objects() {
+        let mut ctx = context();
+        let math = Object(HashMap::from([(
+            "add".to_owned(),
+            ctx.functions.0["add"].clone(),
+        )]));
+        ctx.register_object("Math".to_owned(), math);
+        let value = run_in(&mut ctx, "(list Math.add Math )").unwrap();
+        assert!(matches!(
+            value,
+            Some(Value::List(items)) if matches!(&items[..], [Value::Func(_), Value::Object(_)])
+        ));
+    }
+
+    #[test]
+    fn variables_holding_functions_are_callable() {
+        let mut ctx = context();
+        let add = ctx.resolve_path(&["add".to_owned()]).unwrap();
+        ctx.set_var("add", add);
+        let funcs = Object(HashMap::from([(
+            "sum".to_owned(),
+            ctx.functions.0["sum"].clone(),
+        )]));
+        ctx.set_var("funcs", Value::Object(Rc::new(funcs)));
+        let value = run_in(&mut ctx, "(list ($.add 1 2 ) ($.funcs.sum 2 3 ) )").unwrap();
+        assert_eq!(format!("{value:?}"), "Some(List([Int(3), Int(5)]))");
+    }
+
+    #[test]
+    fn unknown_paths_are_errors() {
+        assert!(matches!(
+            run("(list Nope )"),
+            Err(LyssRuntimeError::EntryNotFound { .. })
+        ));
+    }
+}
@@ -0,0 +1,262 @@
+pub mod composer;
+
+use std::path::PathBuf;
+
+use crate::parser::MacroUse;
+use crate::tokenizer::{self, Token};
+
+use super::*;
+use composer::ComposedMacro;
+
+/// How many macro expansions may be nested inside each other before giving up
+pub const MACRO_DEPTH_LIMIT: usize = 128;
+
+/// A macro implemented by the host, receives the tokens of the invocation's content and
+/// returns the expressions to be spliced in its place
+#[derive(Debug, Clone)]
+pub struct HostMacro(
+    pub fn(&mut Context, content: Vec<Token>) -> Result<Vec<Expr>, LyssRuntimeError>,
+);
+
+impl HostMacro {
+    fn call(self, ctx: &mut Context, content: Vec<Token>) -> Result<Vec<Expr>, LyssRuntimeError> {
+        self.0(ctx, content)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Macro {
+    Host(HostMacro),
+    Composed(Rc<ComposedMacro>),
+}
+
+impl Macro {
+    fn call(
+        self,
+        ctx: &mut Context,
+        name: &str,
+        content: Vec<Token>,
+    ) -> Result<Vec<Expr>, LyssRuntimeError> {
+        match self {
+            Macro::Host(host_macro) => host_macro.call(ctx, content),
+            Macro::Composed(composed) => composed.expand(ctx, name, &content),
+        }
+    }
+}
+
+impl From<HostMacro> for Macro {
+    fn from(host_macro: HostMacro) -> Self {
+        Macro::Host(host_macro)
+    }
+}
+
+/// A macro use and what it expanded to, recorded for `--dump-expanded`
+#[derive(Debug, Clone)]
+pub struct Expansion {
+    pub macro_use: MacroUse,
+    pub exprs: Vec<Expr>,
+}
+
+impl Context<'_> {
+    pub fn register_macro(&mut self, name: String, entry: Macro) {
+        self.macros.insert(name, entry);
+    }
+    /// Finds the macro `name` in this context or its parents
+    pub fn find_macro(&self, name: &str) -> Option<Macro> {
+        let mut ctx: &Context = self;
+        loop {
+            if let Some(found) = ctx.macros.get(name) {
+                return Some(found.clone());
+            }
+            ctx = ctx.paret?;
+        }
+    }
+    /// Tokenizes the content of `macro_use` and feeds it to the macro, returning the expanded
+    /// expressions, their spans point into the file the macro was used in
+    pub fn expand_macro(&mut self, macro_use: &MacroUse) -> Result<Vec<Expr>, LyssRuntimeError> {
+        let found =
+            self.find_macro(&macro_use.name)
+                .ok_or_else(|| LyssRuntimeError::MacroNotFound {
+                    name: macro_use.name.clone(),
+                })?;
+        let file = PathBuf::from(format!("<macro {}>", macro_use.name));
+        let (content, errors) = tokenizer::tokenize_with(
+            &macro_use.content,
+            &file,
+            macro_use.content_span,
+            &self.syntax,
+        );
+        if let Some(error) = errors.into_iter().next() {
+            return Err(LyssRuntimeError::MacroContent {
+                name: macro_use.name.clone(),
+                error: Box::new(error),
+            });
+        }
+        found.call(self, &macro_use.name, content)
+    }
+    /// Starts recording every expansion that runs in this context and its children
+    pub fn record_expansions(&mut self) {
+        self.expansions = Some(Rc::default());
+    }
+    /// The expansions recorded so far, in the order they ran
+    #[must_use]
+    pub fn take_expansions(&self) -> Vec<Expansion> {
+        self.expansions
+            .as_ref()
+            .map(|expansions| expansions.take())
+            .unwrap_or_default()
+    }
+    pub(super) fn record_expansion(&self, macro_use: &MacroUse, exprs: &[Expr]) {
+        if let Some(expansions) = &self.expansions {
+            expansions.borrow_mut().push(Expansion {
+                macro_use: macro_use.clone(),
+                exprs: exprs.to_vec(),
+            });
+        }
+    }
+    /// Runs `expansion` in place of `macro_use`, counting it as one more expansion running and
+    /// failing if too many are nested
+    pub(super) fn in_macro(
+        &mut self,
+        macro_use: &MacroUse,
+        expansion: impl FnOnce(&mut Self) -> Result<Option<Value>, LyssRuntimeError>,
+    ) -> Result<Value, LyssRuntimeError> {
+        let depth = self.macro_depth.get();
+        if depth >= MACRO_DEPTH_LIMIT {
+            return Err(LyssRuntimeError::MacroDepthLimit {
+                name: macro_use.name.clone(),
+            });
+        }
+        self.macro_depth.set(depth + 1);
+        let result = expansion(self);
+        self.macro_depth.set(depth);
+        result?.ok_or_else(|| LyssRuntimeError::EmptyExpansion {
+            name: macro_use.name.clone(),
+        })
+    }
+    /// Expands `macro_use` and executes the resulting expressions in its place
+    pub fn execute_macro(&mut self, macro_use: &MacroUse) -> Result<Value, LyssRuntimeError> {
+        self.in_macro(macro_use, |ctx| {
+            let exprs = ctx.expand_macro(macro_use)?;
+            ctx.record_expansion(macro_use, &exprs);
+            ctx.run(&exprs)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+    use crate::runtime::object::ObjectEntry;
+    use crate::testing::{parse, run_in};
+
+    /// A context with `count`, which evaluates its arguments and adds one to `$.n`, `twice`,
+    /// which expands to its content two times, `nothing`, `block`, which runs its code in a child
+    /// context, and `forever`, which expands into a block using itself
+    fn context() -> HostContext {
+        let mut ctx = HostContext::new();
+        ctx.register(
+            "count".to_owned(),
+            ObjectEntry::Leaf(Function::Host(HostFunc::new(|ctx, args| {
+                for arg in args {
+                    ctx.eval_argument(arg)?;
+                }
+                let n = match ctx.get_var("n") {
+                    Some(Value::Num(n)) => n,
+                    _ => 0.0,
+                };
+                ctx.set_var("n", Value::Num(n + 1.0));
+                Ok(Value::Num(n + 1.0))
+            }))),
+        );
+        ctx.register_macro(
+            "twice".to_owned(),
+            HostMacro(|_, content| {
+                let (mut exprs, _) = parser::parse(&mut content.into_iter());
+                exprs.extend(exprs.clone());
+                Ok(exprs)
+            })
+            .into(),
+        );
+        ctx.register_macro(
+            "nothing".to_owned(),
+            HostMacro(|_, _| Ok(Vec::new())).into(),
+        );
+        ctx.register(
+            "block".to_owned(),
+            ObjectEntry::Leaf(Function::Host(HostFunc::new(|ctx, args| {
+                match ctx.eval_argument(&args[0])? {
+                    Value::Code(code) => Ok(ctx.run_block(&code)?.unwrap_or(Value::Bool(true))),
+                    value => Ok(value),
+                }
+            }))),
+        );
+        ctx.register_macro(
+            "forever".to_owned(),
+            HostMacro(|_, _| Ok(parse("(block '( !(forever ) )' )"))).into(),
+        );
+        ctx
+    }
+
+    fn run(source: &str) -> (HostContext, Result<Option<Value>, LyssRuntimeError>) {
+        let mut ctx = context();
+        let result = run_in(&mut ctx, source);
+        (ctx, result)
+    }
+
+    #[test]
+    fn expansions_run_in_place() {
+        let (ctx, result) = run("!(twice (count ) ) (count )");
+        assert_eq!(result.unwrap(), Some(Value::Num(3.0)));
+        assert_eq!(ctx.get_var("n"), Some(Value::Num(3.0)));
+    }
+
+    #[test]
+    fn expansions_give_their_last_value() {
+        let (_, result) = run("(count ) (count !(twice (count ) ) )");
+        assert_eq!(result.unwrap(), Some(Value::Num(4.0)));
+    }
+
+    #[test]
+    fn unknown_macros_are_errors() {
+        let (_, result) = run("!(missing a b )");
+        assert!(matches!(
+            result,
+            Err(LyssRuntimeError::MacroNotFound { name }) if name == "missing"
+        ));
+    }
+
+    #[test]
+    fn empty_expansions_name_the_macro() {
+        let (_, result) = run("(count !(nothing ) )");
+        assert!(matches!(
+            result,
+            Err(LyssRuntimeError::EmptyExpansion { name }) if name == "nothing"
+        ));
+    }
+
+    #[test]
+    fn recursion_through_code_blocks_hits_the_limit() {
+        let (_, result) = run("!(forever )");
+        assert!(matches!(
+            result,
+            Err(LyssRuntimeError::MacroDepthLimit { name }) if name == "forever"
+        ));
+    }
+
+    #[test]
+    fn recording_doesnt_change_what_runs() {
+        let mut ctx = context();
+        ctx.record_expansions();
+        let result = ctx.run(&parse(
+            "(count !(twice (count ) ) ) (count '( !(twice (g ) ) )' )",
+        ));
+        assert_eq!(result.unwrap(), Some(Value::Num(4.0)));
+        let expansions = ctx.take_expansions();
+        assert_eq!(expansions.len(), 1);
+        assert_eq!(expansions[0].macro_use.name, "twice");
+        assert_eq!(expansions[0].exprs.len(), 2);
+        assert!(ctx.take_expansions().is_empty());
+    }
+}
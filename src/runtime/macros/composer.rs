@@ -0,0 +1,494 @@
+//! Macros written in lyss, built from a list of inputs that are matched against the tokens of
+//! the invocation, an optional preparation block and a template.
+//!
+//! Every named input is bound as a variable in a child context before the preparation block
+//! runs there, and every hole in the template is filled with the variable of the same name once
+//! it finishes.
+
+use crate::LyssCompError;
+use crate::parser::{self, Code, ExprCont, Hole, HoleKind, Template};
+use crate::tokenizer::TokenCont;
+
+use super::*;
+
+#[derive(Debug, Clone)]
+pub enum MacroInput {
+    /// Matches a single identifier, captured as a string
+    Ident(String),
+    /// Matches an identifier, parenthesis or bracket with exactly this text, captures nothing
+    Literal(String),
+    /// Matches the inputs zero or more times, captured as a list with one list of captures
+    /// per repetition. Every capture inside is also a list with one item per repetition
+    Many(String, Vec<MacroInput>),
+    /// Optionally matches the inputs, every capture inside is a list with zero or one items
+    Maybe(Vec<MacroInput>),
+    /// Matches a single atom, captured as code
+    TypeAtom(String),
+    /// Matches a parenthesized list of atoms, captured as code
+    MacroAtom(String),
+}
+
+#[derive(Debug)]
+pub struct ComposedMacro {
+    pub inputs: Vec<MacroInput>,
+    pub preparation: Option<Code>,
+    pub template: Template,
+}
+
+type Captures = Vec<(String, Value)>;
+
+impl MacroInput {
+    fn capture_names(&self, names: &mut Vec<String>) {
+        match self {
+            MacroInput::Literal(_) => {}
+            MacroInput::Ident(name) | MacroInput::TypeAtom(name) | MacroInput::MacroAtom(name) => {
+                names.push(name.clone());
+            }
+            MacroInput::Many(name, inputs) => {
+                names.push(name.clone());
+                for input in inputs {
+                    input.capture_names(names);
+                }
+            }
+            MacroInput::Maybe(inputs) => {
+                for input in inputs {
+                    input.capture_names(names);
+                }
+            }
+        }
+    }
+    fn describe(&self) -> String {
+        match self {
+            MacroInput::Ident(_) => "identifier".to_owned(),
+            MacroInput::Literal(text) => format!("`{text}`"),
+            MacroInput::Many(..) | MacroInput::Maybe(..) => "repetition".to_owned(),
+            MacroInput::TypeAtom(_) => "atom".to_owned(),
+            MacroInput::MacroAtom(_) => "atom list".to_owned(),
+        }
+    }
+}
+
+impl ComposedMacro {
+    pub fn expand(
+        &self,
+        ctx: &mut Context,
+        name: &str,
+        content: &[Token],
+    ) -> Result<Vec<Expr>, LyssRuntimeError> {
+        let mut captures = Vec::new();
+        let matcher = Matcher {
+            name,
+            tokens: content,
+        };
+        let end = matcher.match_inputs(&self.inputs, 0, &mut captures)?;
+        if end != content.len() {
+            return Err(matcher.mismatch("end of macro".to_owned(), end));
+        }
+        let mut scope = Context::child(ctx);
+        for (name, value) in captures {
+            scope.set_var(&name, value);
+        }
+        if let Some(preparation) = &self.preparation {
+            scope.run_code(preparation)?;
+        }
+        fill_exprs(&scope, &self.template.exprs)
+    }
+}
+
+struct Matcher<'t> {
+    name: &'t str,
+    tokens: &'t [Token],
+}
+
+impl Matcher<'_> {
+    fn mismatch(&self, expected: String, pos: usize) -> LyssRuntimeError {
+        LyssRuntimeError::MacroMismatch {
+            name: self.name.to_owned(),
+            expected,
+            got: self.tokens.get(pos).cloned().map(Box::new),
+        }
+    }
+    fn comp_error(&self, error: LyssCompError) -> LyssRuntimeError {
+        LyssRuntimeError::MacroContent {
+            name: self.name.to_owned(),
+            error: Box::new(error),
+        }
+    }
+    fn match_inputs(
+        &self,
+        inputs: &[MacroInput],
+        mut pos: usize,
+        captures: &mut Captures,
+    ) -> Result<usize, LyssRuntimeError> {
+        for input in inputs {
+            pos = self.match_input(input, pos, captures)?;
+        }
+        Ok(pos)
+    }
+    fn match_input(
+        &self,
+        input: &MacroInput,
+        pos: usize,
+        captures: &mut Captures,
+    ) -> Result<usize, LyssRuntimeError> {
+        let token = self.tokens.get(pos).map(|t| &t.content);
+        match (input, token) {
+            (MacroInput::Ident(name), Some(TokenCont::Ident(ident))) => {
+                captures.push((name.clone(), Value::Str(ident.clone())));
+                Ok(pos + 1)
+            }
+            (MacroInput::Literal(text), Some(TokenCont::Ident(ident))) if ident == text => {
+                Ok(pos + 1)
+            }
+            (MacroInput::Literal(text), Some(TokenCont::OParam)) if text == "(" => Ok(pos + 1),
+            (MacroInput::Literal(text), Some(TokenCont::CParam)) if text == ")" => Ok(pos + 1),
+            (MacroInput::Literal(text), Some(TokenCont::OBracket)) if text == "[" => Ok(pos + 1),
+            (MacroInput::Literal(text), Some(TokenCont::CBracket)) if text == "]" => Ok(pos + 1),
+            (MacroInput::TypeAtom(name), Some(TokenCont::OParam))
+                if matches!(
+                    self.tokens.get(pos + 1).map(|t| &t.content),
+                    Some(TokenCont::Ident(_) | TokenCont::Path(_))
+                ) =>
+            {
+                let end = self.group_end(pos)?;
+                let start = self.tokens[pos].span;
+                let mut group = self.tokens[pos + 1..end].iter().cloned();
+                let atom = parser::parse_atom(start, &mut group).map_err(|e| self.comp_error(e))?;
+                let span = atom.span;
+                let code = Code::new(
+                    span,
+                    vec![Expr {
+                        span,
+                        cont: ExprCont::Atom(atom),
+                    }],
+                );
+                captures.push((name.clone(), Value::Code(code)));
+                Ok(end)
+            }
+            (MacroInput::MacroAtom(name), Some(TokenCont::OParam)) => {
+                let end = self.group_end(pos)?;
+                let span = self.tokens[pos].span.to(self.tokens[end - 1].span);
+                let mut group = self.tokens[pos + 1..end - 1].iter().cloned();
+                let (exprs, errors) = parser::parse(&mut group);
+                if let Some(error) = errors.into_iter().next() {
+                    return Err(self.comp_error(error));
+                }
+                captures.push((name.clone(), Value::Code(Code::new(span, exprs))));
+                Ok(end)
+            }
+            (MacroInput::Many(name, inputs), _) => {
+                let mut names = Vec::new();
+                for input in inputs {
+                    input.capture_names(&mut names);
+                }
+                let mut pos = pos;
+                let mut repetitions = Vec::new();
+                let mut columns = vec![Vec::new(); names.len()];
+                loop {
+                    let mut inner = Vec::new();
+                    match self.match_inputs(inputs, pos, &mut inner) {
+                        Ok(next) if next > pos => {
+                            pos = next;
+                            for (column, (_, value)) in columns.iter_mut().zip(&inner) {
+                                column.push(value.clone());
+                            }
+                            repetitions.push(Value::List(
+                                inner.into_iter().map(|(_, value)| value).collect(),
+                            ));
+                        }
+                        _ => break,
+                    }
+                }
+                captures.push((name.clone(), Value::List(repetitions)));
+                captures.extend(
+                    names
+                        .into_iter()
+                        .zip(columns)
+                        .map(|(name, column)| (name, Value::List(column))),
+                );
+                Ok(pos)
+            }
+            (MacroInput::Maybe(inputs), _) => {
+                let mut inner = Vec::new();
+                if let Ok(next) = self.match_inputs(inputs, pos, &mut inner) {
+                    captures.extend(
+                        inner
+                            .into_iter()
+                            .map(|(name, value)| (name, Value::List(vec![value]))),
+                    );
+                    Ok(next)
+                } else {
+                    let mut names = Vec::new();
+                    for input in inputs {
+                        input.capture_names(&mut names);
+                    }
+                    captures.extend(names.into_iter().map(|name| (name, Value::List(vec![]))));
+                    Ok(pos)
+                }
+            }
+            (input, _) => Err(self.mismatch(input.describe(), pos)),
+        }
+    }
+    /// Index after the `)` that closes the `(` at `pos`
+    fn group_end(&self, pos: usize) -> Result<usize, LyssRuntimeError> {
+        let mut depth = 0usize;
+        for (index, token) in self.tokens.iter().enumerate().skip(pos) {
+            match token.content {
+                TokenCont::OParam => depth += 1,
+                TokenCont::CParam => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Ok(index + 1);
+                    }
+                }
+                _ => {}
+            }
+        }
+        Err(self.mismatch("`)`".to_owned(), self.tokens.len()))
+    }
+}
+
+fn hole_value(ctx: &Context, hole: &Hole) -> Result<Value, LyssRuntimeError> {
+    ctx.get_var(&hole.name)
+        .ok_or_else(|| LyssRuntimeError::UnfilledHole {
+            name: hole.name.clone(),
+        })
+}
+
+fn fill_exprs(ctx: &Context, exprs: &[Expr]) -> Result<Vec<Expr>, LyssRuntimeError> {
+    let mut filled = Vec::with_capacity(exprs.len());
+    for expr in exprs {
+        match &expr.cont {
+            ExprCont::Hole(hole) => match hole_value(ctx, hole)? {
+                Value::Code(code) => filled.extend(code.exprs.iter().cloned()),
+                value => {
+                    return Err(LyssRuntimeError::UnexpectedArg {
+                        arg: Box::new(Argument::value(value)),
+                        expected: "code",
+                    });
+                }
+            },
+            ExprCont::Atom(atom) => filled.push(Expr {
+                span: expr.span,
+                cont: ExprCont::Atom(fill_atom(ctx, atom)?),
+            }),
+            ExprCont::Macro(_) | ExprCont::Error => filled.push(expr.clone()),
+        }
+    }
+    Ok(filled)
+}
+
+fn fill_atom(ctx: &Context, atom: &Atom) -> Result<Atom, LyssRuntimeError> {
+    Ok(Atom {
+        span: atom.span,
+        fn_name: atom.fn_name.clone(),
+        arguments: fill_arguments(ctx, &atom.arguments)?,
+    })
+}
+
+fn fill_arguments(ctx: &Context, args: &[Argument]) -> Result<Vec<Argument>, LyssRuntimeError> {
+    let mut arguments = Vec::with_capacity(args.len());
+    for argument in args {
+        // Filled arguments keep the location of what they replaced
+        let filled = |cont| Argument {
+            span: argument.span,
+            cont,
+        };
+        match &argument.cont {
+            ArgumentCont::Hole(hole) => match (hole.kind, hole_value(ctx, hole)?) {
+                (HoleKind::Splice, Value::List(items)) => {
+                    arguments.extend(items.into_iter().map(ArgumentCont::Value).map(filled));
+                }
+                (_, value) => arguments.push(filled(ArgumentCont::Value(value))),
+            },
+            ArgumentCont::Atom(inner) => {
+                arguments.push(filled(ArgumentCont::Atom(fill_atom(ctx, inner)?)));
+            }
+            ArgumentCont::List(items) => {
+                arguments.push(filled(ArgumentCont::List(fill_arguments(ctx, items)?)));
+            }
+            ArgumentCont::Value(Value::Code(code)) => {
+                arguments.push(filled(ArgumentCont::Value(Value::Code(Code::new(
+                    code.span,
+                    fill_exprs(ctx, &code.exprs)?,
+                )))));
+            }
+            _ => arguments.push(argument.clone()),
+        }
+    }
+    Ok(arguments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::api::Api;
+    use crate::runtime::object::ObjectEntry;
+    use crate::span::Span;
+    use crate::testing::{parse, run_in};
+
+    fn code(source: &str) -> Code {
+        Code::new(Span::default(), parse(source))
+    }
+
+    /// The template `source` is parsed into, as the argument of an atom
+    fn template(source: &str) -> Template {
+        let exprs = parse(&format!("(f {source} )"));
+        let ExprCont::Atom(atom) = &exprs[0].cont else {
+            panic!("{exprs:?}");
+        };
+        let ArgumentCont::Value(Value::Template(template)) = &atom.arguments[0].cont else {
+            panic!("{source:?} isn't a template");
+        };
+        template.clone()
+    }
+
+    /// Runs `source` with `list`, `local` and the macro `m` made of `inputs`, `preparation` and
+    /// `template`, giving the value of the last expression
+    fn expand(
+        inputs: Vec<MacroInput>,
+        preparation: Option<&str>,
+        template_source: &str,
+        source: &str,
+    ) -> Result<Value, LyssRuntimeError> {
+        let mut ctx = HostContext::new();
+        ctx.register(
+            "list".to_owned(),
+            ObjectEntry::Leaf(Function::Host(HostFunc::new(|ctx, args| {
+                let items = args
+                    .iter()
+                    .map(|arg| ctx.eval_argument(arg))
+                    .collect::<Result<_, _>>()?;
+                Ok(Value::List(items))
+            }))),
+        );
+        ctx.register(
+            "local".to_owned(),
+            ObjectEntry::Leaf(Function::Host(HostFunc::new(|ctx, args| {
+                let name = Api::expect_var(Api::needs_nth_arg(args, 0)?).expect("a variable");
+                let value = ctx.eval_argument(Api::needs_nth_arg(args, 1)?)?;
+                ctx.set_var(name, value.clone());
+                Ok(value)
+            }))),
+        );
+        let composed = ComposedMacro {
+            inputs,
+            preparation: preparation.map(code),
+            template: template(template_source),
+        };
+        ctx.register_macro("m".to_owned(), Macro::Composed(Rc::new(composed)));
+        let value = run_in(&mut ctx, source)?;
+        Ok(value.expect("the source has a value"))
+    }
+
+    fn show(value: Result<Value, LyssRuntimeError>) -> String {
+        format!("{:?}", value.unwrap())
+    }
+
+    fn ident(name: &str) -> MacroInput {
+        MacroInput::Ident(name.to_owned())
+    }
+
+    fn swap() -> Vec<MacroInput> {
+        vec![
+            ident("a"),
+            MacroInput::Literal("with".to_owned()),
+            ident("b"),
+        ]
+    }
+
+    #[test]
+    fn idents_and_literals_match_in_order() {
+        let value = expand(swap(), None, "`( (list _.b _.a ) )`", "!(m x with y )");
+        assert_eq!(show(value), r#"List([Str("y"), Str("x")])"#);
+    }
+
+    #[test]
+    fn repetitions_are_spliced() {
+        let inputs = vec![MacroInput::Many("items".to_owned(), vec![ident("item")])];
+        let value = expand(inputs, None, "`( (list $items ) )`", "!(m a b c )");
+        assert_eq!(
+            show(value),
+            r#"List([List([Str("a")]), List([Str("b")]), List([Str("c")])])"#
+        );
+    }
+
+    #[test]
+    fn repetitions_keep_the_names_inside() {
+        let pairs = || {
+            vec![MacroInput::Many(
+                "pairs".to_owned(),
+                vec![ident("key"), ident("value")],
+            )]
+        };
+        let value = expand(
+            pairs(),
+            None,
+            "`( (list _.key _.value ) )`",
+            "!(m a x b y )",
+        );
+        assert_eq!(
+            show(value),
+            r#"List([List([Str("a"), Str("b")]), List([Str("x"), Str("y")])])"#
+        );
+        let value = expand(pairs(), None, "`( (list _.key _.pairs ) )`", "!(m )");
+        assert_eq!(show(value), "List([List([]), List([])])");
+    }
+
+    #[test]
+    fn optional_inputs_capture_zero_or_one_items() {
+        let inputs = vec![ident("a"), MacroInput::Maybe(vec![ident("b")])];
+        let value = expand(
+            inputs,
+            None,
+            "`( (list _.a _.b ) )`",
+            "(list !(m x ) !(m x y ) )",
+        );
+        assert_eq!(
+            show(value),
+            r#"List([List([Str("x"), List([])]), List([Str("x"), List([Str("y")])])])"#
+        );
+    }
+
+    #[test]
+    fn the_preparation_block_binds_what_the_template_uses() {
+        let value = expand(
+            vec![ident("who")],
+            Some(r#"(local $.greeting (list "hi" $.who ) )"#),
+            "`( (list _.greeting ) )`",
+            "!(m bob )",
+        );
+        assert_eq!(show(value), r#"List([List([Str("hi"), Str("bob")])])"#);
+    }
+
+    #[test]
+    fn atoms_are_captured_as_code() {
+        let inputs = vec![
+            MacroInput::TypeAtom("atom".to_owned()),
+            MacroInput::MacroAtom("atoms".to_owned()),
+        ];
+        let value = expand(
+            inputs,
+            None,
+            "`( (list _.atom _.atoms ) )`",
+            "!(m (list 1 ) ((list 2 ) ) )",
+        );
+        let Ok(Value::List(items)) = value else {
+            panic!("expected a list, got {value:?}");
+        };
+        assert!(matches!(
+            items.as_slice(),
+            [Value::Code(atom), Value::Code(atoms)] if atom.exprs.len() == 1 && atoms.exprs.len() == 1
+        ));
+    }
+
+    #[test]
+    fn mismatches_name_what_was_expected() {
+        let error = expand(swap(), None, "`( (list _.b _.a ) )`", "!(m x y )").unwrap_err();
+        assert!(matches!(
+            error,
+            LyssRuntimeError::MacroMismatch { name, expected, .. }
+                if name == "m" && expected == "`with`"
+        ));
+    }
+}
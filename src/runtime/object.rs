@@ -54,7 +54,9 @@ impl<V: Clone> Object<V> {
             },
         })?;
         match obj_entry {
-            ObjectSearch::Leaf(_) => todo!(),
+            ObjectSearch::Leaf(_) => Err(LyssRuntimeError::EntryWasLeaf {
+                path: path.to_vec(),
+            }),
             ObjectSearch::Branch(b) => Ok(b),
         }
     }
@@ -84,7 +86,50 @@ impl<V: Clone> Object<V> {
                 None => self.find_next(path).ok_or(ObjectFindResult::NotFound),
             }?);
         }
-        Ok(obj.unwrap())
+        obj.ok_or(ObjectFindResult::NotFound)
+    }
+    /// Inserts `entry` at `path`, creating the branches in between, branches shared with other
+    /// objects are copied before being changed
+    pub fn insert_path(
+        &mut self,
+        path: &[String],
+        entry: ObjectEntry<V>,
+    ) -> Result<(), LyssRuntimeError> {
+        let Some((last, branches)) = path.split_last() else {
+            return Err(LyssRuntimeError::EntryNotFound { path: vec![] });
+        };
+        let mut obj = self;
+        for (depth, name) in branches.iter().enumerate() {
+            let next = obj
+                .0
+                .entry(name.clone())
+                .or_insert_with(|| ObjectEntry::Branch(Rc::new(Object::default())));
+            obj = match next {
+                ObjectEntry::Branch(branch) => Rc::make_mut(branch),
+                ObjectEntry::Leaf(_) => {
+                    return Err(LyssRuntimeError::EntryWasLeaf {
+                        path: path[..=depth].to_vec(),
+                    });
+                }
+            };
+        }
+        obj.0.insert(last.clone(), entry);
+        Ok(())
+    }
+    /// The path of every entry, branches included, in no particular order
+    #[must_use]
+    pub fn paths(&self) -> Vec<Vec<String>> {
+        let mut paths = Vec::new();
+        for (name, entry) in &self.0 {
+            paths.push(vec![name.clone()]);
+            if let ObjectEntry::Branch(branch) = entry {
+                for mut inner in branch.paths() {
+                    inner.insert(0, name.clone());
+                    paths.push(inner);
+                }
+            }
+        }
+        paths
     }
     fn find_next(&self, path: &str) -> Option<ObjectSearch<'_, V>> {
         Some(match self.0.get(path)? {
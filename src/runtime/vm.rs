@@ -0,0 +1,426 @@
+//! The bytecode engine: code blocks are compiled into a [`Chunk`] once, every call remembers the
+//! function it resolved to until the functions it can see change, and every macro use its
+//! compiled expansion until the macros it can see change
+//!
+//! It has to give the same values and errors as the tree walking interpreter, which stays the
+//! reference, `ci.sh` runs the example scripts with both engines and compares their output.
+//! Variables are compiled to their [`Slot`]. `local` and `extern` bind them while the code runs,
+//! so the context holding one is still found by walking up from the running one.
+
+pub mod compile;
+
+use std::cell::RefCell;
+use std::rc::Weak;
+
+use crate::parser::MacroUse;
+use crate::span::Span;
+
+use super::*;
+use api::Api;
+use function::ScriptFunc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    /// Pushes `constants[n]`
+    Const(usize),
+    /// Pushes the variable in the slot
+    Var(Slot),
+    /// Pushes the function, object or stored value at `paths[n]`
+    Path(usize),
+    /// Pops `n` values and pushes them as a list
+    List(usize),
+    /// Resolves the function of `calls[site]`. Functions taking their arguments as written are
+    /// called right away, skipping the next `skip` ops which evaluate the arguments
+    Call { site: usize, skip: usize },
+    /// Pops the values of the arguments and calls the function resolved by the matching `Call`
+    Invoke { site: usize },
+    /// Expands and runs `macros[n]`, or runs its cached expansion
+    Macro(usize),
+    /// Fails on the unfilled hole `names[n]`
+    Hole(usize),
+    /// Fails on a part of the code that didn't parse
+    SyntaxError,
+    /// Drops the value of the previous expression
+    Pop,
+}
+
+/// Compiled code, every op has the span errors raised by it are located at
+#[derive(Debug, Default)]
+pub struct Chunk {
+    ops: Vec<Op>,
+    spans: Vec<Span>,
+    constants: Vec<Value>,
+    names: Vec<String>,
+    paths: Vec<Vec<String>>,
+    calls: Vec<CallSite>,
+    macros: Vec<MacroSite>,
+}
+
+#[derive(Debug)]
+struct CallSite {
+    path: Vec<String>,
+    /// As written, for host functions taking them that way and for errors
+    arguments: Vec<Argument>,
+    cache: RefCell<Option<Cached>>,
+}
+
+#[derive(Debug)]
+struct MacroSite {
+    macro_use: MacroUse,
+    cache: RefCell<Option<Rc<CachedExpansion>>>,
+}
+
+/// What a macro use expanded to, valid as long as the generation of the context doesn't change
+#[derive(Debug)]
+struct CachedExpansion {
+    generation: u64,
+    /// Kept for `--dump-expanded`
+    exprs: Vec<Expr>,
+    chunk: Chunk,
+}
+
+/// What a call resolved to, valid as long as the generation of the context doesn't change
+#[derive(Debug)]
+struct Cached {
+    generation: u64,
+    func: CachedFunction,
+}
+
+#[derive(Debug)]
+enum CachedFunction {
+    Host(HostFunc),
+    /// Weak so a recursive function doesn't keep itself alive through the calls in its body
+    Script(Weak<ScriptFunc>),
+}
+
+impl CachedFunction {
+    fn get(&self) -> Option<Function> {
+        match self {
+            CachedFunction::Host(host_fn) => Some(Function::Host(host_fn.clone())),
+            CachedFunction::Script(script_fn) => script_fn.upgrade().map(Function::Script),
+        }
+    }
+}
+
+/// What a `Call` op resolved to
+enum Callee {
+    /// A host function taking its arguments as written
+    Raw(HostFunc),
+    /// A function the values of the arguments are evaluated for, with its depth
+    Strict(Function, usize),
+}
+
+impl CallSite {
+    /// Resolves the function, checking the argument count of the ones taking values
+    fn callee(&self, ctx: &Context) -> Result<Callee, LyssRuntimeError> {
+        let (func, depth) = self.resolve(ctx)?;
+        let arity = match &func {
+            Function::Host(host_fn) => match &host_fn.strict {
+                Some((arity, _)) => *arity,
+                None => return Ok(Callee::Raw(host_fn.clone())),
+            },
+            Function::Script(script_fn) => Some(script_fn.params.len()),
+        };
+        if let Some(arity) = arity {
+            Api::assert_args_count(&self.arguments, arity)?;
+        }
+        Ok(Callee::Strict(func, depth))
+    }
+    /// Like [`Context::resolve_callee`], only cached when every context between `ctx` and the
+    /// root has no functions of its own, then the result doesn't depend on where the code runs
+    fn resolve(&self, ctx: &Context) -> Result<(Function, usize), LyssRuntimeError> {
+        let Some(depth) = self.root_depth(ctx) else {
+            return ctx.resolve_callee(&self.path);
+        };
+        let generation = ctx.generation.get();
+        if let Some(cached) = &*self.cache.borrow()
+            && cached.generation == generation
+            && let Some(func) = cached.func.get()
+        {
+            return Ok((func, depth));
+        }
+        let (func, found) = ctx.resolve_callee(&self.path)?;
+        if found == depth {
+            let cached = match &func {
+                Function::Host(host_fn) => CachedFunction::Host(host_fn.clone()),
+                Function::Script(script_fn) => CachedFunction::Script(Rc::downgrade(script_fn)),
+            };
+            *self.cache.borrow_mut() = Some(Cached {
+                generation,
+                func: cached,
+            });
+        }
+        Ok((func, found))
+    }
+    /// How far the root is above `ctx`, `None` if the lookup can't be cached
+    fn root_depth(&self, ctx: &Context) -> Option<usize> {
+        if self.path.first().is_some_and(|head| head == "$") {
+            return None;
+        }
+        let mut ctx = ctx;
+        let mut depth = 0;
+        while let Some(paret) = ctx.paret {
+            if !ctx.functions.0.is_empty() || !ctx.scopes.is_empty() {
+                return None;
+            }
+            ctx = paret;
+            depth += 1;
+        }
+        Some(depth)
+    }
+}
+
+impl MacroSite {
+    /// Like [`Context::execute_macro`], without expanding again while the expansion is cached
+    fn execute(&self, ctx: &mut Context) -> Result<Value, LyssRuntimeError> {
+        ctx.in_macro(&self.macro_use, |ctx| {
+            let expansion = self.expansion(ctx)?;
+            ctx.record_expansion(&self.macro_use, &expansion.exprs);
+            run(ctx, &expansion.chunk)
+        })
+    }
+    /// Only cached when every context between `ctx` and the root has no macros of its own,
+    /// like [`CallSite::resolve`]
+    fn expansion(&self, ctx: &mut Context) -> Result<Rc<CachedExpansion>, LyssRuntimeError> {
+        let generation = ctx.generation.get();
+        if let Some(cached) = &*self.cache.borrow()
+            && cached.generation == generation
+        {
+            return Ok(Rc::clone(cached));
+        }
+        let exprs = ctx.expand_macro(&self.macro_use)?;
+        let expansion = Rc::new(CachedExpansion {
+            generation,
+            chunk: Chunk::compile(&exprs),
+            exprs,
+        });
+        let mut current: &Context = ctx;
+        while let Some(paret) = current.paret {
+            if !current.macros.is_empty() {
+                return Ok(expansion);
+            }
+            current = paret;
+        }
+        *self.cache.borrow_mut() = Some(Rc::clone(&expansion));
+        Ok(expansion)
+    }
+}
+
+/// Runs `chunk` in `ctx`, giving the value of the last expression
+pub(crate) fn run(ctx: &mut Context, chunk: &Chunk) -> Result<Option<Value>, LyssRuntimeError> {
+    let mut stack: Vec<Value> = Vec::new();
+    // The functions of the calls whose arguments are being evaluated
+    let mut pending: Vec<(Function, usize)> = Vec::new();
+    let mut ip = 0;
+    while let Some(&op) = chunk.ops.get(ip) {
+        let span = chunk.spans[ip];
+        ip += 1;
+        let value = match op {
+            Op::Const(index) => Ok(chunk.constants[index].clone()),
+            Op::Var(slot) => ctx
+                .get_slot(slot)
+                .ok_or_else(|| LyssRuntimeError::VarNotFound {
+                    name: slot.name().to_string(),
+                }),
+            Op::Path(index) => ctx.resolve_path(&chunk.paths[index]),
+            Op::List(len) => Ok(Value::List(stack.split_off(stack.len() - len))),
+            Op::Call { site, skip } => {
+                let site = &chunk.calls[site];
+                match site.callee(ctx) {
+                    Ok(Callee::Strict(func, depth)) => {
+                        pending.push((func, depth));
+                        continue;
+                    }
+                    Ok(Callee::Raw(host_fn)) => {
+                        ip += skip;
+                        host_fn.call(ctx, &site.arguments)
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+            Op::Invoke { site } => {
+                let arguments = &chunk.calls[site].arguments;
+                let values = stack.split_off(stack.len() - arguments.len());
+                match pending.pop().expect("every Invoke follows its Call") {
+                    (Function::Host(host_fn), _) => {
+                        let (_, func) = host_fn.strict.expect("only strict calls are invoked");
+                        func(ctx, values, arguments)
+                    }
+                    (Function::Script(script_fn), depth) => {
+                        script_fn.call(ctx.ancestor(depth), values)
+                    }
+                }
+            }
+            Op::Macro(index) => chunk.macros[index].execute(ctx),
+            Op::Hole(index) => Err(LyssRuntimeError::UnfilledHole {
+                name: chunk.names[index].clone(),
+            }),
+            Op::SyntaxError => Err(LyssRuntimeError::SyntaxError),
+            Op::Pop => {
+                stack.pop();
+                continue;
+            }
+        };
+        stack.push(value.map_err(|e| e.located(span))?);
+    }
+    Ok(stack.pop())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Value;
+    use crate::parser::Code;
+    use crate::runtime::object::ObjectEntry;
+    use std::cell::Cell;
+
+    use crate::runtime::macros::HostMacro;
+    use crate::runtime::{Context, HostContext, HostFunc};
+    use crate::span::Span;
+    use crate::testing::{both, parse};
+
+    #[test]
+    fn macros() {
+        let outcome = both(
+            r#"
+            (Macro.def "twice" (Macro.Composers.Default.make
+                (Macro.Composers.Default.Inputs.macro_atom "code" )
+                `( ($code ) ($code ) )`
+            ) )
+            !(twice ((print "a" ) ) )
+            (print (list !(twice ((+ 1 2 ) ) ) ) )
+            (defn "f" (list ) Types.int '( !(twice ((print "b" ) ) ) (return 4 ) )' )
+            (list (f ) (f ) )
+        "#,
+        );
+        assert_eq!(outcome.output, "aa[3]bbbb");
+        assert_eq!(outcome.result, Ok(Some("[4 4]".to_owned())));
+    }
+
+    #[test]
+    fn returns() {
+        let outcome = both(
+            r#"
+            (defn "sign" (list (Types.int "n" ) ) Types.str '(
+                (if '( (< $.n 0 ) )' '( (return "-" ) )' else '( (list ) )' )
+                (if '( (= $.n 0 ) )' '( (return "0" ) )' else '( (return "+" ) )' )
+                (print "not here" )
+            )' )
+            (list (sign -3 ) (sign 0 ) (sign 5 ) )
+        "#,
+        );
+        assert_eq!(outcome.output, "");
+        assert_eq!(outcome.result, Ok(Some(r#"["-" "0" "+"]"#.to_owned())));
+        assert!(both("(return 1 )").error().starts_with("error[E0116]"));
+    }
+
+    #[test]
+    fn errors_are_located_the_same() {
+        for source in [
+            "(print (+ 1 $.missing ) )",
+            "(defn \"f\" (list (Types.int \"n\" ) ) Types.int '(\n\t(+ $.n \"x\" )\n)' )\n(f 1 )",
+            "(defn \"f\" (list (Types.int \"n\" ) ) Types.int '( (return $.n ) )' )\n(f \"1\" )",
+            "(defn \"f\" (list (Types.int \"n\" ) ) Types.int '( (return $.n ) )' )\n(f 1 2 )",
+            "(if '( (missing ) )' '( (list ) )' else '( (list ) )' )",
+            "(List.map prnt (list 1 ) )",
+            "!(nowhere a )",
+        ] {
+            let outcome = both(source);
+            assert!(outcome.error().contains("test.ls:"), "{source:?}");
+        }
+    }
+
+    #[test]
+    fn scopes_and_variables() {
+        let outcome = both(
+            r#"
+            (local $.n 1 )
+            (defn "shadow" (list (Types.int "n" ) ) Types.int '( (return (* $.n 10 ) ) )' )
+            (if '( (= $.n 1 ) )' '(
+                (local $.n 2 )
+                (extern $.outer 3 )
+                (Builtin.alias List L )
+                (print (L.map shadow (list $.n ) ) )
+            )' else '( (list ) )' )
+            (list $.n $.outer (shadow 4 ) )
+        "#,
+        );
+        assert_eq!(outcome.output, "[20]");
+        assert_eq!(outcome.result, Ok(Some("[1 3 40]".to_owned())));
+        assert!(
+            both("(L.map print (list ) )")
+                .error()
+                .starts_with("error[E0100]")
+        );
+    }
+
+    #[test]
+    fn redefined_functions_replace_cached_ones() {
+        let outcome = both(
+            r#"
+            (defn "f" (list ) Types.int '( (return 1 ) )' )
+            (defn "g" (list ) Types.int '( (return (f ) ) )' )
+            (print (g ) )
+            (defn "f" (list ) Types.int '( (return 2 ) )' )
+            (print (g ) )
+            (if '( (= 1 1 ) )' '(
+                (defn "f" (list ) Types.int '( (return 3 ) )' )
+                (print (f ) (g ) )
+            )' else '( (list ) )' )
+            (g )
+        "#,
+        );
+        assert_eq!(outcome.output, "1232");
+        assert_eq!(outcome.result, Ok(Some("2".to_owned())));
+    }
+
+    #[test]
+    fn host_functions_registered_between_runs() {
+        let code = Code::new(Span::default(), parse("(answer )"));
+        let mut ctx = HostContext::new();
+        for answer in [1, 2] {
+            ctx.register_fn("answer", move |_, _| Ok(Value::Int(answer)))
+                .unwrap();
+            let value = ctx.run_code(&code).unwrap();
+            assert_eq!(value, Some(Value::Int(answer)));
+        }
+    }
+
+    #[test]
+    fn functions_edited_directly_replace_cached_ones() {
+        let code = Code::new(Span::default(), parse("(answer )"));
+        let mut ctx = HostContext::new();
+        for answer in [1, 2] {
+            let func = HostFunc::new(move |_, _| Ok(Value::Int(answer)));
+            ctx.functions
+                .0
+                .insert("answer".to_owned(), ObjectEntry::Leaf(func.into()));
+            let value = ctx.run_code(&code).unwrap();
+            assert_eq!(value, Some(Value::Int(answer)));
+        }
+    }
+
+    #[test]
+    fn macro_expansions_are_cached_until_the_macros_change() {
+        thread_local! {
+            static EXPANSIONS: Cell<usize> = const { Cell::new(0) };
+        }
+        let code = Code::new(Span::default(), parse("(answer !(answer ) )"));
+        let mut ctx = HostContext::new();
+        ctx.register_variadic("answer", |_, values, _| {
+            Ok(values.into_iter().next().unwrap_or(Value::Int(1)))
+        })
+        .unwrap();
+        let answer = |_: &mut Context, _| {
+            EXPANSIONS.set(EXPANSIONS.get() + 1);
+            Ok(parse("(answer )"))
+        };
+        ctx.register_macro("answer".to_owned(), HostMacro(answer).into());
+        for _ in 0..3 {
+            assert_eq!(ctx.run_code(&code).unwrap(), Some(Value::Int(1)));
+        }
+        assert_eq!(EXPANSIONS.get(), 1);
+        ctx.register_macro("answer".to_owned(), HostMacro(answer).into());
+        ctx.run_code(&code).unwrap();
+        assert_eq!(EXPANSIONS.get(), 2);
+    }
+}
@@ -0,0 +1,101 @@
+//! Compiles expressions into a [`Chunk`]. Arguments are compiled in the order the tree walker
+//! evaluates them, and every op keeps the span the tree walker locates its errors at, so both
+//! engines fail at the same place.
+
+use crate::parser::{ArgumentCont, ExprCont};
+
+use super::*;
+
+impl Chunk {
+    /// Compiles `exprs`, running the chunk gives the value of the last one
+    #[must_use]
+    pub fn compile(exprs: &[Expr]) -> Chunk {
+        let mut chunk = Chunk::default();
+        for (i, expr) in exprs.iter().enumerate() {
+            if i > 0 {
+                chunk.emit(Op::Pop, expr.span);
+            }
+            chunk.expr(expr);
+        }
+        chunk
+    }
+    fn emit(&mut self, op: Op, span: Span) -> usize {
+        self.ops.push(op);
+        self.spans.push(span);
+        self.ops.len() - 1
+    }
+    fn expr(&mut self, expr: &Expr) {
+        match &expr.cont {
+            ExprCont::Atom(atom) => self.atom(atom),
+            ExprCont::Macro(macro_use) => {
+                self.macros.push(MacroSite {
+                    macro_use: macro_use.clone(),
+                    cache: RefCell::default(),
+                });
+                self.emit(Op::Macro(self.macros.len() - 1), expr.span);
+            }
+            ExprCont::Hole(hole) => {
+                self.names.push(hole.name.clone());
+                self.emit(Op::Hole(self.names.len() - 1), expr.span);
+            }
+            ExprCont::Error => {
+                self.emit(Op::SyntaxError, expr.span);
+            }
+        }
+    }
+    /// `Call`, the ops evaluating the arguments, then `Invoke`
+    fn atom(&mut self, atom: &Atom) {
+        let site = self.calls.len();
+        self.calls.push(CallSite {
+            path: atom.fn_name.0.clone(),
+            arguments: atom.arguments.clone(),
+            cache: RefCell::default(),
+        });
+        let call = self.emit(Op::Call { site, skip: 0 }, atom.span);
+        for argument in &atom.arguments {
+            self.argument(argument);
+        }
+        let invoke = self.emit(Op::Invoke { site }, atom.span);
+        self.ops[call] = Op::Call {
+            site,
+            skip: invoke - call,
+        };
+    }
+    fn argument(&mut self, argument: &Argument) {
+        let span = argument.span;
+        match &argument.cont {
+            ArgumentCont::Var(name) => {
+                self.emit(Op::Var(Slot::of(name)), span);
+            }
+            ArgumentCont::Ident(path) => {
+                self.paths.push(path.0.clone());
+                self.emit(Op::Path(self.paths.len() - 1), span);
+            }
+            ArgumentCont::Atom(atom) => self.atom(atom),
+            ArgumentCont::Value(value) => {
+                self.constants.push(value.clone());
+                self.emit(Op::Const(self.constants.len() - 1), span);
+            }
+            ArgumentCont::Macro(macro_use) => {
+                self.macros.push(MacroSite {
+                    macro_use: macro_use.clone(),
+                    cache: RefCell::default(),
+                });
+                self.emit(Op::Macro(self.macros.len() - 1), span);
+            }
+            ArgumentCont::Hole(hole) => {
+                self.names.push(hole.name.clone());
+                self.emit(Op::Hole(self.names.len() - 1), span);
+            }
+            ArgumentCont::List(items) => {
+                for item in items {
+                    self.argument(item);
+                }
+                self.emit(Op::List(items.len()), span);
+            }
+            ArgumentCont::Error => {
+                self.emit(Op::SyntaxError, span);
+            }
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use std::path::{Path, PathBuf};
+
+/// Identifies a source file, given out by [`SourceMap::add`]
+pub type FileId = usize;
+
+/// A location in a source file, `line` and `column` are where it starts and both start at 1,
+/// the bytes are a half open range into the file's content. The default span is no location at
+/// all, for nodes built by the host instead of read from a file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Span {
+    pub file_id: FileId,
+    pub start_byte: usize,
+    pub end_byte: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    /// The empty span at the start of a file
+    #[must_use]
+    pub fn file_start(file_id: FileId) -> Span {
+        Span {
+            file_id,
+            start_byte: 0,
+            end_byte: 0,
+            line: 1,
+            column: 1,
+        }
+    }
+    /// From the start of `self` to the end of `end`
+    #[must_use]
+    pub fn to(self, end: Span) -> Span {
+        Span {
+            end_byte: end.end_byte,
+            ..self
+        }
+    }
+    /// The empty span right after `self`'s start, moved by `c`
+    #[must_use]
+    pub fn advance(self, c: char) -> Span {
+        let start_byte = self.start_byte + c.len_utf8();
+        let (line, column) = if c == '\n' {
+            (self.line + 1, 1)
+        } else {
+            (self.line, self.column + 1)
+        };
+        Span {
+            file_id: self.file_id,
+            start_byte,
+            end_byte: start_byte,
+            line,
+            column,
+        }
+    }
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.end_byte - self.start_byte
+    }
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.start_byte == self.end_byte
+    }
+}
+
+#[derive(Debug)]
+pub struct SourceFile {
+    pub path: PathBuf,
+    pub content: String,
+}
+
+/// Every file read so far, so errors can show the source they point at
+#[derive(Debug, Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+impl SourceMap {
+    #[must_use]
+    pub fn new() -> SourceMap {
+        SourceMap::default()
+    }
+    pub fn add(&mut self, path: &Path, content: String) -> FileId {
+        self.files.push(SourceFile {
+            path: path.to_path_buf(),
+            content,
+        });
+        self.files.len() - 1
+    }
+    #[must_use]
+    pub fn get(&self, file_id: FileId) -> Option<&SourceFile> {
+        self.files.get(file_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{ArgumentCont, ExprCont};
+    use crate::testing::{parse, run};
+    use crate::tokenizer;
+
+    /// `(line, column, start_byte, end_byte)` of every token of `source`
+    fn token_spans(source: &str) -> Vec<(usize, usize, usize, usize)> {
+        tokenizer::tokenize(source, Path::new("test.ls"), 0)
+            .0
+            .iter()
+            .map(|token| {
+                let span = token.span;
+                (span.line, span.column, span.start_byte, span.end_byte)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn columns_count_chars_and_bytes_count_bytes() {
+        assert_eq!(
+            token_spans("(a\n\tb \"ü\" )"),
+            [
+                (1, 1, 0, 1),
+                (1, 2, 1, 2),
+                (2, 2, 4, 5),
+                (2, 4, 6, 10),
+                (2, 8, 11, 12)
+            ]
+        );
+    }
+
+    #[test]
+    fn nodes_cover_their_source() {
+        let exprs = parse("(f (g 1 ) )");
+        assert_eq!((exprs[0].span.start_byte, exprs[0].span.end_byte), (0, 11));
+        let ExprCont::Atom(atom) = &exprs[0].cont else {
+            panic!("{:?}", exprs[0]);
+        };
+        let inner = &atom.arguments[0];
+        assert!(matches!(inner.cont, ArgumentCont::Atom(_)));
+        assert_eq!((inner.span.start_byte, inner.span.end_byte), (3, 9));
+    }
+
+    #[test]
+    fn runtime_errors_point_at_the_argument() {
+        let outcome = run("(Builtin.list\n  $.missing )");
+        assert!(
+            outcome.error().contains(" --> test.ls:2:3\n"),
+            "{}",
+            outcome.error()
+        );
+    }
+}
@@ -0,0 +1,95 @@
+//! The `Builtin` library, every namespace has its own installer so embedders can pick which
+//! ones scripts get access to.
+
+pub mod base;
+pub mod io;
+pub mod list;
+pub mod macros;
+pub mod math;
+pub mod types;
+
+use crate::LyssRuntimeError;
+use crate::runtime::Context;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Namespace {
+    /// Variables, functions and control flow, most scripts can't do anything without it
+    Base,
+    /// `Builtin.print` and `Builtin.exit`
+    Io,
+    List,
+    Macro,
+    Math,
+    Types,
+}
+
+impl Namespace {
+    pub const ALL: [Namespace; 6] = [
+        Namespace::Base,
+        Namespace::Io,
+        Namespace::List,
+        Namespace::Macro,
+        Namespace::Math,
+        Namespace::Types,
+    ];
+
+    pub fn install(self, ctx: &mut Context) -> Result<(), LyssRuntimeError> {
+        match self {
+            Namespace::Base => base::install(ctx),
+            Namespace::Io => io::install(ctx),
+            Namespace::List => list::install(ctx),
+            Namespace::Macro => macros::install(ctx),
+            Namespace::Math => math::install(ctx),
+            Namespace::Types => types::install(ctx),
+        }
+    }
+}
+
+/// Installs every namespace, the same `Builtin` tree the `lyss` binary runs scripts with
+pub fn install(ctx: &mut Context) -> Result<(), LyssRuntimeError> {
+    install_only(ctx, &Namespace::ALL)
+}
+
+/// Installs only the given namespaces
+pub fn install_only(ctx: &mut Context, namespaces: &[Namespace]) -> Result<(), LyssRuntimeError> {
+    namespaces
+        .iter()
+        .try_for_each(|namespace| namespace.install(ctx))
+}
+
+/// Installs every namespace except the given ones, e.g. `&[Namespace::Io]` for sandboxed scripts
+pub fn install_except(ctx: &mut Context, skip: &[Namespace]) -> Result<(), LyssRuntimeError> {
+    Namespace::ALL
+        .into_iter()
+        .filter(|namespace| !skip.contains(namespace))
+        .try_for_each(|namespace| namespace.install(ctx))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::HostContext;
+
+    fn has(ctx: &HostContext, path: &str) -> bool {
+        let path: Vec<String> = path.split('.').map(str::to_owned).collect();
+        ctx.resolve_path(&path).is_ok()
+    }
+
+    #[test]
+    fn install_only_installs_the_given_namespaces() {
+        let mut ctx = HostContext::new();
+        install_only(&mut ctx, &[Namespace::Math]).unwrap();
+        assert!(has(&ctx, "Builtin.Math.="));
+        assert!(!has(&ctx, "Builtin.print"));
+        assert!(!has(&ctx, "Builtin.local"));
+    }
+
+    #[test]
+    fn install_except_skips_the_given_namespaces() {
+        let mut ctx = HostContext::new();
+        install_except(&mut ctx, &[Namespace::Io]).unwrap();
+        assert!(!has(&ctx, "Builtin.print"));
+        assert!(has(&ctx, "Builtin.local"));
+        assert!(has(&ctx, "Builtin.Macro.def"));
+    }
+}
@@ -0,0 +1,158 @@
+//! Variables, functions, name resolution and control flow: `Builtin.local`, `Builtin.extern`,
+//! `Builtin.alias`, `Builtin.scope`, `Builtin.list`, `Builtin.defn`, `Builtin.call`,
+//! `Builtin.return` and `if`.
+
+use std::rc::Rc;
+
+use crate::parser::{Argument, Code};
+use crate::runtime::Context;
+use crate::runtime::api::Api;
+use crate::runtime::function::{Function, Param, ScriptFunc, Type};
+use crate::runtime::object::ObjectEntry;
+use crate::{LyssRuntimeError, Value};
+
+pub fn install(ctx: &mut Context) -> Result<(), LyssRuntimeError> {
+    ctx.register_fn("Builtin.local", |ctx, args| {
+        Api::assert_args_count(args, 2)?;
+        let var_name = Api::needs_nth_arg(args, 0)?;
+        let name = Api::expect_var(var_name).ok_or(LyssRuntimeError::UnexpectedArg {
+            arg: Box::new(var_name.clone()),
+            expected: "Identifier",
+        })?;
+        let value = Api::needs_nth_arg(args, 1)?;
+        let value = ctx.eval_argument(value)?;
+        ctx.set_var(name, value.clone());
+        Ok(value)
+    })?;
+
+    ctx.register_fn("Builtin.extern", |ctx, args| {
+        Api::assert_args_count(args, 2)?;
+        let var_name = Api::needs_nth_arg(args, 0)?;
+        let name = match Api::expect_var(var_name) {
+            Some(name) => name.to_owned(),
+            None => Api::eval_as::<String>(ctx, var_name)?,
+        };
+        let value = ctx.eval_argument(Api::needs_nth_arg(args, 1)?)?;
+        ctx.set_extern_var(&name, value.clone());
+        Ok(value)
+    })?;
+
+    ctx.register_fn("Builtin.alias", |ctx, args| {
+        let to_arg = Api::needs_nth_arg(args, 0)?;
+        let from = Api::needs_nth_arg(args, 1)?;
+        let to = Api::expect_ident(to_arg).ok_or(LyssRuntimeError::UnexpectedArg {
+            arg: Box::new(to_arg.clone()),
+            expected: "Identifier Path",
+        })?;
+        let from_name = Api::expect_ident(from)
+            .filter(|from| from.0.len() == 1)
+            .ok_or(LyssRuntimeError::UnexpectedArg {
+                arg: Box::new(from.clone()),
+                expected: "Single Identifier",
+            })?;
+        let from = from_name.0[0].clone();
+        let to = match ctx.resolve_path(&to.0)? {
+            Value::Func(func) => ObjectEntry::Leaf(func),
+            Value::Object(object) => ObjectEntry::Branch(object),
+            _ => {
+                return Err(LyssRuntimeError::UnexpectedArg {
+                    arg: Box::new(to_arg.clone()),
+                    expected: "function or object",
+                });
+            }
+        };
+
+        ctx.register(from.clone(), to);
+        Ok(Value::Str(from))
+    })?;
+
+    ctx.register_fn("Builtin.scope", |ctx, args| {
+        let to = Api::needs_nth_arg(args, 0)?;
+        let to_name = Api::expect_ident(to).ok_or(LyssRuntimeError::UnexpectedArg {
+            arg: Box::new(to.clone()),
+            expected: "Identifier Path",
+        })?;
+        let Value::Object(object) = ctx.resolve_path(&to_name.0)? else {
+            return Err(LyssRuntimeError::EntryWasLeaf {
+                path: to_name.0.clone(),
+            });
+        };
+        ctx.add_scope(object);
+
+        Ok(Value::List(
+            to_name
+                .0
+                .iter()
+                .map(String::to_owned)
+                .map(Value::Str)
+                .collect(),
+        ))
+    })?;
+
+    ctx.register_variadic("Builtin.list", |_, values, _| Ok(Value::List(values)))?;
+
+    ctx.register_fn("Builtin.defn", |ctx, args| {
+        Api::assert_args_valid_counts(args, [3, 4])?;
+        let name = Api::eval_as::<String>(ctx, Api::needs_nth_arg(args, 0)?)?;
+        let params = Api::eval_as::<Vec<Param>>(ctx, Api::needs_nth_arg(args, 1)?)?;
+        let output = if args.len() == 4 {
+            super::types::eval_type(ctx, Api::needs_nth_arg(args, 2)?)?
+        } else {
+            Type::Any
+        };
+        let body = Api::eval_as::<Code>(ctx, Api::needs_nth_arg(args, args.len() - 1)?)?;
+        ctx.register(
+            name.clone(),
+            ObjectEntry::Leaf(Function::Script(Rc::new(ScriptFunc {
+                params,
+                output,
+                body,
+            }))),
+        );
+        Ok(Value::Str(name))
+    })?;
+
+    ctx.register_variadic("Builtin.call", |ctx, mut values, args| {
+        if values.is_empty() {
+            return Err(LyssRuntimeError::NeedsArg);
+        }
+        let func = Api::convert(values.remove(0), &args[0])?;
+        Function::call_values(func, ctx, values)
+    })?;
+
+    ctx.register_typed("Builtin.return", |value: Value| {
+        Err::<Value, _>(LyssRuntimeError::Return { value })
+    })?;
+
+    ctx.register_fn("if", |ctx, args| {
+        Api::assert_args_count(args, 4)?;
+        let if_code = Api::needs_nth_arg(args, 0)?;
+        Api::expect_this_text(Api::needs_nth_arg(args, 2)?, "else")?;
+
+        let Some(Value::Code(if_code)) = Api::expect_literal(if_code) else {
+            return Err(LyssRuntimeError::UnexpectedArg {
+                arg: Box::new(if_code.clone()),
+                expected: "code",
+            });
+        };
+        let Some(if_res) = ctx.run_block(if_code)? else {
+            return Err(LyssRuntimeError::NeedsArg);
+        };
+        let Value::Bool(if_res) = if_res else {
+            return Err(LyssRuntimeError::UnexpectedArg {
+                arg: Box::new(Argument::value(if_res)),
+                expected: "boolean",
+            });
+        };
+
+        let branch = Api::needs_nth_arg(args, if if_res { 1 } else { 3 })?;
+        let Some(Value::Code(branch_code)) = Api::expect_literal(branch) else {
+            return Err(LyssRuntimeError::UnexpectedArg {
+                arg: Box::new(branch.clone()),
+                expected: "code",
+            });
+        };
+        ctx.run_block(branch_code)?
+            .ok_or(LyssRuntimeError::NeedsArg)
+    })
+}
@@ -0,0 +1,71 @@
+//! Interaction with the outside world: `Builtin.print` and `Builtin.exit`.
+
+use crate::display::DisplayValue;
+use crate::runtime::Context;
+use crate::runtime::api::Api;
+use crate::{LyssRuntimeError, Value};
+
+pub fn install(ctx: &mut Context) -> Result<(), LyssRuntimeError> {
+    ctx.register_variadic("Builtin.print", |_, values, _| {
+        let mut out = String::new();
+        for value in values {
+            let cnt = DisplayValue(value).to_string();
+            out.push_str(&cnt);
+        }
+        print!("{out}");
+        #[allow(clippy::cast_possible_wrap)]
+        Ok(Value::Int(out.len() as i64))
+    })?;
+
+    // Unwinds instead of ending the process, so embedders decide what exiting means
+    ctx.register_fn("Builtin.exit", |ctx, args| {
+        Api::assert_args_valid_counts(args, [0, 1])?;
+        let code = match args.first() {
+            Some(arg) => i32::try_from(Api::eval_as::<i64>(ctx, arg)?).map_err(|_| {
+                LyssRuntimeError::UnexpectedArg {
+                    arg: Box::new(arg.clone()),
+                    expected: "exit code",
+                }
+            })?,
+            None => 0,
+        };
+        Err(LyssRuntimeError::Exit { code })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::run;
+
+    #[test]
+    fn exit_unwinds_out_of_the_script() {
+        let outcome = run(r#"
+            (Builtin.scope Builtin )
+            (defn "leave" (list ) Types.int '( (exit 3 ) (print "not here" ) )' )
+            (print "before " )
+            (leave )
+            (print "after" )
+        "#);
+        assert_eq!(outcome.output, "before ");
+        assert!(
+            outcome
+                .error()
+                .starts_with("error[E0119]: the script exited with code 3")
+        );
+    }
+
+    #[test]
+    fn exit_codes_are_checked() {
+        assert!(run("(Builtin.exit )").error().contains("code 0"));
+        assert!(
+            run("(Builtin.exit 4294967296 )")
+                .error()
+                .starts_with("error[E0105]")
+        );
+        assert!(
+            run("(Builtin.exit 1 2 )")
+                .error()
+                .starts_with("error[E0109]")
+        );
+    }
+}
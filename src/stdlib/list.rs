@@ -0,0 +1,40 @@
+//! Higher order list functions: `Builtin.List.map` and `Builtin.List.filter`.
+
+use crate::parser::Argument;
+use crate::runtime::Context;
+use crate::runtime::api::Api;
+use crate::runtime::function::Function;
+use crate::{LyssRuntimeError, Value};
+
+pub fn install(ctx: &mut Context) -> Result<(), LyssRuntimeError> {
+    ctx.register_fn("Builtin.List.map", |ctx, args| {
+        Api::assert_args_count(args, 2)?;
+        let func = Api::eval_as::<Function>(ctx, Api::needs_nth_arg(args, 0)?)?;
+        let items = Api::eval_as::<Vec<Value>>(ctx, Api::needs_nth_arg(args, 1)?)?;
+        let mapped = items
+            .into_iter()
+            .map(|item| func.clone().call_values(ctx, vec![item]))
+            .collect::<Result<_, _>>()?;
+        Ok(Value::List(mapped))
+    })?;
+
+    ctx.register_fn("Builtin.List.filter", |ctx, args| {
+        Api::assert_args_count(args, 2)?;
+        let func = Api::eval_as::<Function>(ctx, Api::needs_nth_arg(args, 0)?)?;
+        let items = Api::eval_as::<Vec<Value>>(ctx, Api::needs_nth_arg(args, 1)?)?;
+        let mut kept = Vec::new();
+        for item in items {
+            match func.clone().call_values(ctx, vec![item.clone()])? {
+                Value::Bool(true) => kept.push(item),
+                Value::Bool(false) => {}
+                other => {
+                    return Err(LyssRuntimeError::UnexpectedArg {
+                        arg: Box::new(Argument::value(other)),
+                        expected: "boolean",
+                    });
+                }
+            }
+        }
+        Ok(Value::List(kept))
+    })
+}
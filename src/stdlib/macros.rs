@@ -0,0 +1,84 @@
+//! Macros written in lyss: `Builtin.Macro.def` and the default composer,
+//! `Builtin.Macro.Composers.Default`.
+
+use std::rc::Rc;
+
+use crate::parser::{Argument, Code, Template};
+use crate::runtime::Context;
+use crate::runtime::api::Api;
+use crate::runtime::convert::FromLyss;
+use crate::runtime::macros::Macro;
+use crate::runtime::macros::composer::{ComposedMacro, MacroInput};
+use crate::{LyssRuntimeError, Value};
+
+const INPUTS: &str = "Builtin.Macro.Composers.Default.Inputs";
+
+/// Accepts either a single macro input or a list of them
+fn eval_macro_inputs(
+    ctx: &mut Context,
+    arg: &Argument,
+) -> Result<Vec<MacroInput>, LyssRuntimeError> {
+    match ctx.eval_argument(arg)? {
+        Value::MacroInput(input) => Ok(vec![input]),
+        value => FromLyss::from_lyss(value).ok_or_else(|| LyssRuntimeError::UnexpectedArg {
+            arg: Box::new(arg.clone()),
+            expected: "macro input or list of macro inputs",
+        }),
+    }
+}
+
+pub fn install(ctx: &mut Context) -> Result<(), LyssRuntimeError> {
+    ctx.register_fn("Builtin.Macro.def", |ctx, args| {
+        Api::assert_args_count(args, 2)?;
+        let name = Api::eval_as::<String>(ctx, Api::needs_nth_arg(args, 0)?)?;
+        let macro_arg = Api::needs_nth_arg(args, 1)?;
+        let Value::Macro(entry) = ctx.eval_argument(macro_arg)? else {
+            return Err(LyssRuntimeError::UnexpectedArg {
+                arg: Box::new(macro_arg.clone()),
+                expected: "macro",
+            });
+        };
+        ctx.register_macro(name.clone(), entry);
+        Ok(Value::Str(name))
+    })?;
+
+    ctx.register_fn("Builtin.Macro.Composers.Default.make", |ctx, args| {
+        Api::assert_args_valid_counts(args, [2, 3])?;
+        let inputs = eval_macro_inputs(ctx, Api::needs_nth_arg(args, 0)?)?;
+        let preparation = if args.len() == 3 {
+            Some(Api::eval_as::<Code>(ctx, Api::needs_nth_arg(args, 1)?)?)
+        } else {
+            None
+        };
+        let template = Api::eval_as::<Template>(ctx, Api::needs_nth_arg(args, args.len() - 1)?)?;
+        Ok(Value::Macro(Macro::Composed(Rc::new(ComposedMacro {
+            inputs,
+            preparation,
+            template,
+        }))))
+    })?;
+
+    for (name, make) in [
+        ("ident", MacroInput::Ident as fn(String) -> MacroInput),
+        ("literal", MacroInput::Literal),
+        ("type_atom", MacroInput::TypeAtom),
+        ("macro_atom", MacroInput::MacroAtom),
+    ] {
+        ctx.register_typed(&format!("{INPUTS}.{name}"), move |name: String| {
+            Value::MacroInput(make(name))
+        })?;
+    }
+
+    ctx.register_fn(&format!("{INPUTS}.many"), |ctx, args| {
+        Api::assert_args_count(args, 2)?;
+        let name = Api::eval_as::<String>(ctx, Api::needs_nth_arg(args, 0)?)?;
+        let inner = eval_macro_inputs(ctx, Api::needs_nth_arg(args, 1)?)?;
+        Ok(Value::MacroInput(MacroInput::Many(name, inner)))
+    })?;
+
+    ctx.register_fn(&format!("{INPUTS}.maybe"), |ctx, args| {
+        Api::assert_args_count(args, 1)?;
+        let inner = eval_macro_inputs(ctx, Api::needs_nth_arg(args, 0)?)?;
+        Ok(Value::MacroInput(MacroInput::Maybe(inner)))
+    })
+}
@@ -0,0 +1,346 @@
+//! Numbers and comparisons: `Builtin.Math`.
+//!
+//! Integers stay exact while only integers are involved, mixing in a float makes the result a
+//! float. An integer operation without an integer result, like one that overflows or divides by
+//! zero, is done on floats instead. `/` always gives a float, `floor`, `ceil` and `round` give
+//! integers when the result fits in one.
+
+use std::cmp::Ordering;
+
+use crate::parser::Argument;
+use crate::runtime::Context;
+use crate::runtime::api::Api;
+use crate::runtime::convert::{FromLyss, IntoLyss};
+use crate::{LyssRuntimeError, Value};
+
+#[derive(Debug, Clone, Copy)]
+enum Num {
+    Int(i64),
+    Float(f64),
+}
+
+impl FromLyss for Num {
+    const EXPECTED: &'static str = "number";
+    fn from_lyss(value: Value) -> Option<Self> {
+        match value {
+            Value::Int(n) => Some(Num::Int(n)),
+            Value::Num(n) => Some(Num::Float(n)),
+            _ => None,
+        }
+    }
+}
+
+impl IntoLyss for Num {
+    fn into_lyss(self) -> Value {
+        match self {
+            Num::Int(n) => Value::Int(n),
+            Num::Float(n) => Value::Num(n),
+        }
+    }
+}
+
+impl Num {
+    #[allow(clippy::cast_precision_loss)]
+    fn float(self) -> f64 {
+        match self {
+            Num::Int(n) => n as f64,
+            Num::Float(n) => n,
+        }
+    }
+    /// `n` as an integer if it's whole and fits in one
+    #[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+    fn whole(n: f64) -> Num {
+        // i64::MAX as f64 rounds up to 2^63, which doesn't fit
+        if n.fract() == 0.0 && n >= i64::MIN as f64 && n < i64::MAX as f64 {
+            Num::Int(n as i64)
+        } else {
+            Num::Float(n)
+        }
+    }
+    /// Applies `int` when both are integers and it has a result, `float` otherwise
+    fn apply(self, rhs: Num, int: IntOp, float: FloatOp) -> Num {
+        if let (Num::Int(lhs), Num::Int(rhs)) = (self, rhs)
+            && let Some(result) = int(lhs, rhs)
+        {
+            return Num::Int(result);
+        }
+        Num::Float(float(self.float(), rhs.float()))
+    }
+    fn compare(self, rhs: Num) -> Option<Ordering> {
+        match (self, rhs) {
+            (Num::Int(lhs), Num::Int(rhs)) => Some(lhs.cmp(&rhs)),
+            (lhs, rhs) => lhs.float().partial_cmp(&rhs.float()),
+        }
+    }
+}
+
+type IntOp = fn(i64, i64) -> Option<i64>;
+type FloatOp = fn(f64, f64) -> f64;
+
+fn nums(values: Vec<Value>, args: &[Argument]) -> Result<Vec<Num>, LyssRuntimeError> {
+    values
+        .into_iter()
+        .zip(args)
+        .map(|(value, arg)| Api::convert(value, arg))
+        .collect()
+}
+
+/// Folds every argument with the ops, a single argument is applied to `identity` instead so
+/// `(- 2)` is `-2` and `(/ 2)` is `0.5`
+fn fold(
+    values: Vec<Value>,
+    args: &[Argument],
+    identity: Num,
+    int: IntOp,
+    float: FloatOp,
+) -> Result<Value, LyssRuntimeError> {
+    let nums = nums(values, args)?;
+    let result = match nums.as_slice() {
+        [] => return Err(LyssRuntimeError::NeedsArg),
+        [only] => identity.apply(*only, int, float),
+        [first, rest @ ..] => rest
+            .iter()
+            .fold(*first, |acc, num| acc.apply(*num, int, float)),
+    };
+    Ok(result.into_lyss())
+}
+
+/// Like [`fold`], but no arguments give `empty`
+fn sum(
+    values: Vec<Value>,
+    args: &[Argument],
+    empty: Num,
+    int: IntOp,
+    float: FloatOp,
+) -> Result<Value, LyssRuntimeError> {
+    let result = nums(values, args)?
+        .into_iter()
+        .reduce(|acc, num| acc.apply(num, int, float))
+        .unwrap_or(empty);
+    Ok(result.into_lyss())
+}
+
+/// True when every pair of neighbouring arguments is ordered as `cmp` wants, `(< 1 2 3)`
+fn chain(
+    values: Vec<Value>,
+    args: &[Argument],
+    cmp: fn(Ordering) -> bool,
+) -> Result<Value, LyssRuntimeError> {
+    if args.len() < 2 {
+        return Err(LyssRuntimeError::NeedsArg);
+    }
+    let nums = nums(values, args)?;
+    Ok(Value::Bool(
+        nums.windows(2)
+            .all(|pair| pair[0].compare(pair[1]).is_some_and(cmp)),
+    ))
+}
+
+/// The argument that is ordered before every other one as `keep` wants
+fn pick(values: Vec<Value>, args: &[Argument], keep: Ordering) -> Result<Value, LyssRuntimeError> {
+    nums(values, args)?
+        .into_iter()
+        .reduce(|acc, num| {
+            if num.compare(acc) == Some(keep) {
+                num
+            } else {
+                acc
+            }
+        })
+        .map(IntoLyss::into_lyss)
+        .ok_or(LyssRuntimeError::NeedsArg)
+}
+
+pub fn install(ctx: &mut Context) -> Result<(), LyssRuntimeError> {
+    ctx.register_typed("Builtin.Math.=", |lhs: Value, rhs: Value| lhs == rhs)?;
+    ctx.register_typed("Builtin.Math.!=", |lhs: Value, rhs: Value| lhs != rhs)?;
+
+    ctx.register_variadic("Builtin.Math.+", |_, values, args| {
+        sum(values, args, Num::Int(0), i64::checked_add, |lhs, rhs| {
+            lhs + rhs
+        })
+    })?;
+    ctx.register_variadic("Builtin.Math.*", |_, values, args| {
+        sum(values, args, Num::Int(1), i64::checked_mul, |lhs, rhs| {
+            lhs * rhs
+        })
+    })?;
+    ctx.register_variadic("Builtin.Math.-", |_, values, args| {
+        fold(values, args, Num::Int(0), i64::checked_sub, |lhs, rhs| {
+            lhs - rhs
+        })
+    })?;
+    ctx.register_variadic("Builtin.Math./", |_, values, args| {
+        fold(values, args, Num::Int(1), |_, _| None, |lhs, rhs| lhs / rhs)
+    })?;
+    ctx.register_typed("Builtin.Math.%", |lhs: Num, rhs: Num| {
+        lhs.apply(rhs, i64::checked_rem, |lhs, rhs| lhs % rhs)
+    })?;
+
+    ctx.register_variadic("Builtin.Math.<", |_, values, args| {
+        chain(values, args, Ordering::is_lt)
+    })?;
+    ctx.register_variadic("Builtin.Math.<=", |_, values, args| {
+        chain(values, args, Ordering::is_le)
+    })?;
+    ctx.register_variadic("Builtin.Math.>", |_, values, args| {
+        chain(values, args, Ordering::is_gt)
+    })?;
+    ctx.register_variadic("Builtin.Math.>=", |_, values, args| {
+        chain(values, args, Ordering::is_ge)
+    })?;
+
+    ctx.register_variadic("Builtin.Math.min", |_, values, args| {
+        pick(values, args, Ordering::Less)
+    })?;
+    ctx.register_variadic("Builtin.Math.max", |_, values, args| {
+        pick(values, args, Ordering::Greater)
+    })?;
+
+    ctx.register_typed("Builtin.Math.abs", |num: Num| match num {
+        Num::Int(n) => n
+            .checked_abs()
+            .map_or(Num::Float(num.float().abs()), Num::Int),
+        Num::Float(n) => Num::Float(n.abs()),
+    })?;
+    for (name, op) in [
+        ("floor", f64::floor as fn(f64) -> f64),
+        ("ceil", f64::ceil),
+        ("round", f64::round),
+    ] {
+        ctx.register_typed(&format!("Builtin.Math.{name}"), move |num: Num| match num {
+            Num::Int(_) => num,
+            Num::Float(n) => Num::whole(op(n)),
+        })?;
+    }
+    for (name, op) in [
+        ("sqrt", f64::sqrt as fn(f64) -> f64),
+        ("sin", f64::sin),
+        ("cos", f64::cos),
+        ("tan", f64::tan),
+        ("asin", f64::asin),
+        ("acos", f64::acos),
+        ("atan", f64::atan),
+    ] {
+        ctx.register_typed(&format!("Builtin.Math.{name}"), move |num: f64| op(num))?;
+    }
+    ctx.register_typed("Builtin.Math.pow", |base: Num, exp: Num| {
+        base.apply(
+            exp,
+            |base, exp| base.checked_pow(u32::try_from(exp).ok()?),
+            f64::powf,
+        )
+    })?;
+    ctx.register_typed("Builtin.Math.atan2", f64::atan2)?;
+
+    ctx.register_value("Builtin.Math.pi", Value::Num(std::f64::consts::PI))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::runtime::HostContext;
+    use crate::stdlib::{Namespace, install_only};
+    use crate::testing::run_in;
+    use crate::{LyssRuntimeError, Value};
+
+    fn eval(expr: &str) -> Result<Value, LyssRuntimeError> {
+        let mut ctx = HostContext::new();
+        install_only(&mut ctx, &[Namespace::Base, Namespace::Math]).unwrap();
+        let source = format!("(Builtin.scope Builtin.Math ) {expr}");
+        let value = run_in(&mut ctx, &source)?;
+        Ok(value.expect("the source has a value"))
+    }
+
+    /// The value of `expr`, `Debug` formatted so ints and floats stay apart
+    #[track_caller]
+    fn value(expr: &str) -> String {
+        match eval(expr) {
+            Ok(value) => format!("{value:?}"),
+            Err(e) => panic!("{expr} gave {e:?}"),
+        }
+    }
+
+    #[test]
+    fn integers_stay_exact() {
+        assert_eq!(value("(+ 1 2 3 )"), "Int(6)");
+        assert_eq!(value("(* 9007199254740993 1 )"), "Int(9007199254740993)");
+        assert_eq!(value("(- 10 1 2 )"), "Int(7)");
+        assert_eq!(value("(% 7 3 )"), "Int(1)");
+        assert_eq!(value("(pow 2 10 )"), "Int(1024)");
+    }
+
+    #[test]
+    fn floats_spread_and_overflow_falls_back_to_them() {
+        assert_eq!(value("(+ 1 0.5 )"), "Num(1.5)");
+        assert_eq!(value("(/ 1 2 )"), "Num(0.5)");
+        assert_eq!(
+            value("(+ 9223372036854775807 1 )"),
+            "Num(9.223372036854776e18)"
+        );
+        assert_eq!(
+            value("(abs -9223372036854775808 )"),
+            "Num(9.223372036854776e18)"
+        );
+    }
+
+    #[test]
+    fn single_arguments_and_no_arguments() {
+        assert_eq!(value("(- 2 )"), "Int(-2)");
+        assert_eq!(value("(/ 2 )"), "Num(0.5)");
+        assert_eq!(value("(+ )"), "Int(0)");
+        assert_eq!(value("(* )"), "Int(1)");
+    }
+
+    #[test]
+    fn rounding_gives_integers_when_they_fit() {
+        assert_eq!(value("(floor 2.7 )"), "Int(2)");
+        assert_eq!(value("(round -2.5 )"), "Int(-3)");
+        assert_eq!(value("(ceil 1e300 )"), "Num(1e300)");
+    }
+
+    #[test]
+    fn comparisons_chain() {
+        assert_eq!(value("(< 1 2 2.5 )"), "Bool(true)");
+        assert_eq!(value("(<= 1 3 2 )"), "Bool(false)");
+        assert_eq!(value("(min 3 1.5 2 )"), "Num(1.5)");
+        assert_eq!(value("(max 3 1.5 2 )"), "Int(3)");
+    }
+
+    #[test]
+    fn ints_equal_floats_exactly() {
+        assert_eq!(value("(= 3 3.0 )"), "Bool(true)");
+        assert_eq!(value("(= 3 3.5 )"), "Bool(false)");
+        assert_eq!(
+            value("(Builtin.Math.= 9007199254740993 9007199254740992.0 )"),
+            "Bool(false)"
+        );
+        assert_eq!(
+            value("(= 9007199254740992 9007199254740992.0 )"),
+            "Bool(true)"
+        );
+        assert_eq!(
+            value("(= 9223372036854775807 9223372036854775808.0 )"),
+            "Bool(false)"
+        );
+        assert_eq!(
+            value("(= -9223372036854775808 -9223372036854775808.0 )"),
+            "Bool(true)"
+        );
+    }
+
+    #[test]
+    fn pi_is_a_value() {
+        let pi = format!("Num({:?})", std::f64::consts::PI);
+        assert_eq!(value("(max Builtin.Math.pi )"), pi);
+        assert_eq!(value("(max Builtin.Math.pi 3 )"), pi);
+    }
+
+    #[test]
+    fn non_numbers_are_rejected() {
+        let result = eval(r#"(+ 1 "2" )"#);
+        assert!(
+            matches!(result, Err(LyssRuntimeError::UnexpectedArg { .. })),
+            "{result:?}"
+        );
+    }
+}
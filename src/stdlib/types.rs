@@ -0,0 +1,86 @@
+//! Types for script function signatures: `Builtin.Types`, every type is also a parameter of
+//! that type when given a name, `(Types.list Types.num "items")`.
+
+use crate::parser::{Argument, ArgumentCont, Atom};
+use crate::runtime::Context;
+use crate::runtime::api::Api;
+use crate::runtime::function::{Param, Type};
+use crate::{LyssRuntimeError, Value};
+
+/// Evaluates a type, a bare path like `Types.any` is called without arguments
+pub fn eval_type(ctx: &mut Context, arg: &Argument) -> Result<Type, LyssRuntimeError> {
+    match &arg.cont {
+        ArgumentCont::Ident(path) => {
+            let value = ctx.execute_atom(&Atom {
+                span: arg.span,
+                fn_name: path.clone(),
+                arguments: vec![],
+            })?;
+            match value {
+                Value::Type(kind) => Ok(kind),
+                _ => Err(LyssRuntimeError::UnexpectedArg {
+                    arg: Box::new(arg.clone()),
+                    expected: "type",
+                }),
+            }
+        }
+        _ => Api::eval_as(ctx, arg),
+    }
+}
+
+/// A type, or a parameter of that type when given a name
+fn type_or_param(
+    ctx: &mut Context,
+    name: Option<&Argument>,
+    kind: Type,
+) -> Result<Value, LyssRuntimeError> {
+    Ok(match name {
+        Some(name) => Value::Param(Param {
+            name: Api::eval_as(ctx, name)?,
+            kind,
+        }),
+        None => Value::Type(kind),
+    })
+}
+
+fn simple_type(
+    ctx: &mut Context,
+    args: &[Argument],
+    kind: Type,
+) -> Result<Value, LyssRuntimeError> {
+    Api::assert_args_valid_counts(args, [0, 1])?;
+    type_or_param(ctx, args.first(), kind)
+}
+
+fn wrapper_type(
+    ctx: &mut Context,
+    args: &[Argument],
+    wrap: fn(Box<Type>) -> Type,
+) -> Result<Value, LyssRuntimeError> {
+    Api::assert_args_valid_counts(args, [1, 2])?;
+    let item = eval_type(ctx, Api::needs_nth_arg(args, 0)?)?;
+    type_or_param(ctx, args.get(1), wrap(Box::new(item)))
+}
+
+pub fn install(ctx: &mut Context) -> Result<(), LyssRuntimeError> {
+    let simple = [
+        ("any", Type::Any),
+        ("bool", Type::Bool),
+        ("str", Type::Str),
+        ("num", Type::Num),
+        ("int", Type::Int),
+        ("func", Type::Func),
+        ("code", Type::Code),
+    ];
+    for (name, kind) in simple {
+        ctx.register_fn(&format!("Builtin.Types.{name}"), move |ctx, args| {
+            simple_type(ctx, args, kind.clone())
+        })?;
+    }
+    ctx.register_fn("Builtin.Types.list", |ctx, args| {
+        wrapper_type(ctx, args, Type::List)
+    })?;
+    ctx.register_fn("Builtin.Types.maybe", |ctx, args| {
+        wrapper_type(ctx, args, Type::Maybe)
+    })
+}
@@ -0,0 +1,150 @@
+//! Helpers for the tests: running a source with the whole `Builtin` library while keeping what
+//! it printed
+
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::diagnostic::Diagnostic;
+use crate::display::DisplayValue;
+use crate::parser::{self, Expr};
+use crate::runtime::{Engine, HostContext};
+use crate::span::SourceMap;
+use crate::{LyssCompError, LyssRuntimeError, Value, stdlib, tokenizer};
+
+/// What running a source did
+#[derive(Debug, PartialEq, Eq)]
+pub struct Outcome {
+    /// Everything `Builtin.print` printed
+    pub output: String,
+    /// The repr of the value of the last expression, or the rendered diagnostic of the error
+    pub result: Result<Option<String>, String>,
+}
+
+impl Outcome {
+    /// The rendered diagnostic, panicking if the source ran fine
+    #[track_caller]
+    pub fn error(&self) -> &str {
+        match &self.result {
+            Err(error) => error,
+            Ok(value) => panic!("expected an error, got {value:?}"),
+        }
+    }
+}
+
+/// Runs `source` with the default engine
+pub fn run(source: &str) -> Outcome {
+    run_with(Engine::default(), source)
+}
+
+/// Runs `source` as the file `test.ls`, with `Builtin.print` writing to the outcome
+pub fn run_with(engine: Engine, source: &str) -> Outcome {
+    let mut sources = SourceMap::new();
+    let file_id = sources.add(Path::new("test.ls"), source.to_owned());
+    let exprs = parse_file(source, file_id);
+    let mut ctx = library();
+    ctx.engine = engine;
+    let output = Rc::new(RefCell::new(String::new()));
+    let printed = Rc::clone(&output);
+    ctx.register_variadic("Builtin.print", move |_, values, _| {
+        let mut out = String::new();
+        for value in values {
+            out.push_str(&DisplayValue(value).to_string());
+        }
+        printed.borrow_mut().push_str(&out);
+        Ok(Value::Int(i64::try_from(out.len()).unwrap_or(i64::MAX)))
+    })
+    .expect("print registers");
+    let result = match ctx.run(&exprs) {
+        Ok(value) => Ok(value.map(|value| value.to_string())),
+        Err(error) => Err(Diagnostic::from_runtime(&error, &ctx).render(&sources)),
+    };
+    Outcome {
+        output: output.take(),
+        result,
+    }
+}
+
+/// A context with the whole `Builtin` library
+pub fn library() -> HostContext {
+    let mut ctx = HostContext::new();
+    stdlib::install(&mut ctx).expect("the library installs");
+    ctx
+}
+
+/// Puts the `Builtin` and `Builtin.Math` functions in scope, so sources can call them bare
+pub const PRELUDE: &str = "(Builtin.scope Builtin ) (Builtin.scope Builtin.Math )\n";
+
+/// Runs `source` after the [`PRELUDE`] with both engines, they have to agree on everything
+#[track_caller]
+pub fn both(source: &str) -> Outcome {
+    let source = format!("{PRELUDE}{source}");
+    let tree = run_with(Engine::Tree, &source);
+    assert_eq!(
+        run_with(Engine::Bytecode, &source),
+        tree,
+        "the engines disagree"
+    );
+    tree
+}
+
+/// `error` without the locations wrapped around it, for matching on what went wrong
+pub fn unlocated(error: LyssRuntimeError) -> LyssRuntimeError {
+    match error {
+        LyssRuntimeError::Located { error, .. } => unlocated(*error),
+        error => error,
+    }
+}
+
+/// Runs `source` in `ctx`, with the locations taken off the error
+#[track_caller]
+pub fn run_in(ctx: &mut HostContext, source: &str) -> Result<Option<Value>, LyssRuntimeError> {
+    ctx.run(&parse(source)).map_err(unlocated)
+}
+
+/// A file in the temp dir, removed when it's dropped
+pub struct TempFile {
+    pub path: PathBuf,
+}
+
+impl TempFile {
+    /// Writes `contents` to a file named after `name` and the test process
+    pub fn new(name: &str, contents: &str) -> TempFile {
+        let path = std::env::temp_dir().join(format!("lyss-{}-{name}.ls", std::process::id()));
+        std::fs::write(&path, contents).expect("the temp dir is writable");
+        TempFile { path }
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Tokenizes and parses `source`, panicking if it doesn't compile
+#[track_caller]
+pub fn parse(source: &str) -> Vec<Expr> {
+    parse_file(source, 0)
+}
+
+/// The errors tokenizing and parsing `source` gives
+pub fn compile_errors(source: &str) -> Vec<LyssCompError> {
+    compile(source, 0).1
+}
+
+fn compile(source: &str, file_id: usize) -> (Vec<Expr>, Vec<LyssCompError>) {
+    let (tokens, mut errors) = tokenizer::tokenize(source, Path::new("test.ls"), file_id);
+    let (exprs, parse_errors) = parser::parse(&mut tokens.into_iter());
+    errors.extend(parse_errors);
+    (exprs, errors)
+}
+
+#[track_caller]
+fn parse_file(source: &str, file_id: usize) -> Vec<Expr> {
+    let (exprs, errors) = compile(source, file_id);
+    if let Some(error) = errors.first() {
+        panic!("{source:?} doesn't compile: {error}");
+    }
+    exprs
+}
@@ -1,11 +1,22 @@
+//! Splits source into [`Token`]s, see [`syntax`] for what identifiers are made of
+
+pub mod string;
+pub mod syntax;
+
 use std::path::Path;
 
 use crate::LyssCompError;
+use crate::span::{FileId, Span};
+use string::StrLit;
+use syntax::Syntax;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum TokenCont {
     OParam,
     CParam,
+    /// `[` opening a list literal
+    OBracket,
+    CBracket,
     SingleQuote,
     MacroQuote,
     Ident(String),
@@ -15,19 +26,22 @@ pub enum TokenCont {
     Macro {
         name: String,
         content: String,
-        line_span: std::ops::Range<usize>,
+        /// Where `content` is in the file, so its tokens can point back at it
+        content_span: Span,
     },
+    /// Source that couldn't be tokenized, its error was already reported
+    Error,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Token {
-    pub line: usize,
+    pub span: Span,
     pub content: TokenCont,
 }
 
 macro_rules! char_group {
     (space) => {
-        ' ' | '\n' | '\t'
+        ' ' | '\n' | '\t' | '\r'
     };
     (alphabet) => {
         'A'..='Z' | 'a'..='z'
@@ -35,77 +49,182 @@ macro_rules! char_group {
     (digit) => {
         '0'..='9'
     };
-    (ident_start) => {
-        '$' | '=' | '<' | '>' | '_' | '-' | '+' | '/' | '*' | char_group!(alphabet)
-    };
-    (ident) => {
-        char_group!(ident_start) | '!'
+    (number) => {
+        char_group!(digit) | char_group!(alphabet) | '_'
     };
 }
 
+/// Whether a sign after the number `digits` is the one of an exponent, like in `1e-9`
+fn takes_exponent_sign(digits: &str) -> bool {
+    digits.ends_with(['e', 'E']) && !digits.trim_start_matches('-').starts_with("0x")
+}
+
 #[derive(Debug)]
 pub enum State {
     Nothing,
     Comment,
-    String(String),
-    StringSlash(String),
+    String(StrLit),
+    /// After the `\` starting an escape at the span
+    StringSlash(StrLit, Span),
+    /// Reading the digits of a `\x..` escape
+    StringHex(StrLit, Span, String),
+    /// Reading the digits of a `\u{..}` escape, `None` until the `{`
+    StringUnicode(StrLit, Span, Option<String>),
+    /// Skipping the whitespace after a `\` ending a line
+    StringContinue(StrLit),
+    /// `r` followed by this many `#`, waiting for the `"`
+    RawStringOpen(usize),
+    /// A raw string closed by `"` and this many `#`
+    RawString(StrLit, usize),
+    /// Read a `"` and this many `#` of the closing of a raw string
+    RawStringQuote(StrLit, usize, usize),
     Ident(String),
     PathDot(Vec<String>),
     Path(Vec<String>, String),
+    /// A number literal, checked when it's parsed
     Digit(String),
-    DigitDot(String),
     MacroWaitAtom,
     MacroWaitContent(String),
     Macro {
         name: String,
         content: String,
         parem_depth: usize,
-        start_line: usize,
+        content_start: Span,
     },
 }
 
 impl State {
-    fn into_token(self, line: usize, file: &Path) -> Result<Option<TokenCont>, LyssCompError> {
+    fn into_token(self, span: Span, file: &Path) -> Result<Option<TokenCont>, LyssCompError> {
         Ok(Some(match self {
             State::Nothing => return Ok(None),
-            State::String(cnt) => TokenCont::String(cnt),
             State::Ident(cnt) => TokenCont::Ident(cnt),
             State::Digit(cnt) => TokenCont::Digit(cnt),
-            State::DigitDot(cnt) => TokenCont::Digit(cnt),
             State::Path(mut secs, end) => {
                 secs.push(end);
                 TokenCont::Path(secs)
             }
             State::Comment
             | State::PathDot(..)
+            | State::String(..)
             | State::StringSlash(..)
+            | State::StringHex(..)
+            | State::StringUnicode(..)
+            | State::StringContinue(..)
+            | State::RawStringOpen(..)
+            | State::RawString(..)
+            | State::RawStringQuote(..)
             | State::Macro { .. }
             | State::MacroWaitContent(..)
             | State::MacroWaitAtom => {
                 return Err(LyssCompError::CantStopToken {
-                    line,
+                    span,
                     file: file.to_path_buf(),
-                    tokenizer_state: self,
+                    tokenizer_state: Box::new(self),
                 });
             }
         }))
     }
 }
 
-pub fn tokenize(content: &str, file_name: &Path) -> Result<Vec<Token>, LyssCompError> {
+/// Tokenizes a whole file. Source that can't be tokenized is reported and left as a
+/// [`TokenCont::Error`] token, so every error of the file is found in one pass
+#[must_use]
+pub fn tokenize(
+    content: &str,
+    file_name: &Path,
+    file_id: FileId,
+) -> (Vec<Token>, Vec<LyssCompError>) {
+    tokenize_from(content, file_name, Span::file_start(file_id))
+}
+
+/// Tokenizes `content` as if it was found at `start`, used for source nested inside a file
+#[must_use]
+pub fn tokenize_from(
+    content: &str,
+    file_name: &Path,
+    start: Span,
+) -> (Vec<Token>, Vec<LyssCompError>) {
+    tokenize_with(content, file_name, start, &Syntax::default())
+}
+
+/// Like [`tokenize_from`], with the identifiers of `syntax`
+#[must_use]
+pub fn tokenize_with(
+    content: &str,
+    file_name: &Path,
+    start: Span,
+    syntax: &Syntax,
+) -> (Vec<Token>, Vec<LyssCompError>) {
     let mut state = State::Nothing;
     let mut tokens = Vec::new();
-    let mut line = 1;
+    let mut errors = Vec::new();
+    // The char being read, and the start of the token being built
+    let mut pos = start;
+    let mut start = start;
+    // A token that ended right before the current char
     macro_rules! token {
         ($tkn:expr) => {
             Token {
-                line,
+                span: Span {
+                    end_byte: pos.start_byte,
+                    ..start
+                },
                 content: $tkn,
             }
         };
     }
 
     for c in content.chars() {
+        // A token that ends with the current char
+        macro_rules! token_incl {
+            ($tkn:expr) => {
+                Token {
+                    span: Span {
+                        end_byte: pos.start_byte + c.len_utf8(),
+                        ..start
+                    },
+                    content: $tkn,
+                }
+            };
+        }
+        // A token made of only the current char
+        macro_rules! token_here {
+            ($tkn:expr) => {
+                Token {
+                    span: Span {
+                        end_byte: pos.start_byte + c.len_utf8(),
+                        ..pos
+                    },
+                    content: $tkn,
+                }
+            };
+        }
+
+        // An invalid escape, from its `\` up to the current char. The rest of the string is
+        // still read, the whole literal becomes an error token
+        macro_rules! escape_error {
+            ($lit:expr, $slash:expr, $reason:expr) => {{
+                errors.push(LyssCompError::InvalidEscape {
+                    span: Span {
+                        end_byte: pos.start_byte + c.len_utf8(),
+                        ..$slash
+                    },
+                    reason: $reason,
+                });
+                let mut lit: StrLit = $lit;
+                lit.fail();
+                if c == '"' {
+                    tokens.push(token_incl!(TokenCont::Error));
+                    State::Nothing
+                } else {
+                    State::String(lit)
+                }
+            }};
+        }
+
+        if let State::Nothing = state {
+            start = pos;
+        }
         state = match (state, c) {
             // Comment
             (State::Comment, '\n') => State::Nothing,
@@ -113,43 +232,127 @@ pub fn tokenize(content: &str, file_name: &Path) -> Result<Vec<Token>, LyssCompE
             (State::Nothing, '#') => State::Comment,
 
             // String
-            (State::Nothing, '"') => State::String(String::new()),
-            (State::String(cnt), '\\') => State::StringSlash(cnt),
-            (State::StringSlash(mut cnt), 'n') => {
-                cnt.push('\n');
-                State::String(cnt)
-            }
-            (State::StringSlash(mut cnt), 't') => {
-                cnt.push('\t');
-                State::String(cnt)
-            }
-            (State::String(cnt), '"') => {
-                tokens.push(token!(TokenCont::String(cnt)));
+            (State::Nothing, '"') => State::String(StrLit::default()),
+            (State::String(lit) | State::StringContinue(lit), '"') => {
+                tokens.push(token_incl!(lit.into_token()));
                 State::Nothing
             }
-            (State::String(mut cnt), c) => {
-                cnt.push(c);
-                State::String(cnt)
+            (State::String(lit) | State::StringContinue(lit), '\\') => State::StringSlash(lit, pos),
+            (State::StringContinue(lit), char_group!(space)) => State::StringContinue(lit),
+            (State::String(mut lit) | State::StringContinue(mut lit), c) => {
+                lit.push(c);
+                State::String(lit)
+            }
+            (State::StringSlash(lit, _), '\n') => State::StringContinue(lit),
+            (State::StringSlash(lit, slash), 'x') => State::StringHex(lit, slash, String::new()),
+            (State::StringSlash(lit, slash), 'u') => State::StringUnicode(lit, slash, None),
+            (State::StringSlash(mut lit, slash), c) => {
+                if let Some(escaped) = string::simple_escape(c) {
+                    lit.push_escaped(escaped);
+                    State::String(lit)
+                } else {
+                    escape_error!(lit, slash, "unknown escape")
+                }
+            }
+            (State::StringHex(lit, slash, _), c) if !c.is_ascii_hexdigit() => {
+                escape_error!(lit, slash, "`\\x` must be followed by two hex digits")
+            }
+            (State::StringHex(mut lit, slash, mut digits), c) => {
+                digits.push(c);
+                if digits.len() < 2 {
+                    State::StringHex(lit, slash, digits)
+                } else {
+                    match string::hex_escape(&digits) {
+                        Ok(escaped) => {
+                            lit.push_escaped(escaped);
+                            State::String(lit)
+                        }
+                        Err(reason) => escape_error!(lit, slash, reason),
+                    }
+                }
+            }
+            (State::StringUnicode(lit, slash, None), '{') => {
+                State::StringUnicode(lit, slash, Some(String::new()))
+            }
+            (State::StringUnicode(lit, slash, None), _) => {
+                escape_error!(lit, slash, "`\\u` must be followed by `{`")
+            }
+            (State::StringUnicode(mut lit, slash, Some(digits)), '}') => {
+                match string::unicode_escape(&digits) {
+                    Ok(escaped) => {
+                        lit.push_escaped(escaped);
+                        State::String(lit)
+                    }
+                    Err(reason) => escape_error!(lit, slash, reason),
+                }
+            }
+            (State::StringUnicode(lit, slash, Some(digits)), c)
+                if !c.is_ascii_hexdigit() || digits.len() == 6 =>
+            {
+                escape_error!(lit, slash, "`\\u{..}` must hold 1 to 6 hex digits")
+            }
+            (State::StringUnicode(lit, slash, Some(mut digits)), c) => {
+                digits.push(c);
+                State::StringUnicode(lit, slash, Some(digits))
+            }
+
+            // Raw string
+            (State::Ident(cnt), '"') if cnt == "r" => State::RawString(StrLit::default(), 0),
+            (State::Ident(cnt), '#') if cnt == "r" => State::RawStringOpen(1),
+            (State::RawStringOpen(hashes), '#') => State::RawStringOpen(hashes + 1),
+            (State::RawStringOpen(hashes), '"') => State::RawString(StrLit::default(), hashes),
+            (State::RawString(lit, 0), '"') => {
+                tokens.push(token_incl!(lit.into_token()));
+                State::Nothing
+            }
+            (State::RawString(lit, hashes), '"') => State::RawStringQuote(lit, hashes, 0),
+            (State::RawString(mut lit, hashes), c) => {
+                lit.push(c);
+                State::RawString(lit, hashes)
+            }
+            (State::RawStringQuote(lit, hashes, seen), '#') if seen + 1 == hashes => {
+                tokens.push(token_incl!(lit.into_token()));
+                State::Nothing
+            }
+            (State::RawStringQuote(lit, hashes, seen), '#') => {
+                State::RawStringQuote(lit, hashes, seen + 1)
+            }
+            (State::RawStringQuote(mut lit, hashes, seen), c) => {
+                // Not the end after all, what was taken for it is content
+                lit.push('"');
+                for _ in 0..seen {
+                    lit.push('#');
+                }
+                if c == '"' {
+                    State::RawStringQuote(lit, hashes, 0)
+                } else {
+                    lit.push(c);
+                    State::RawString(lit, hashes)
+                }
             }
 
             // Digit
             (State::Nothing, d @ char_group!(digit)) => State::Digit(String::from(d)),
-            (State::Digit(mut cnt) | State::DigitDot(mut cnt), d @ char_group!(digit)) => {
+            (State::Ident(mut cnt), d @ char_group!(digit)) if cnt == "-" => {
                 cnt.push(d);
                 State::Digit(cnt)
             }
-            (State::Digit(mut cnt), '.') => {
-                cnt.push('.');
-                State::DigitDot(cnt)
+            (State::Digit(mut cnt), c @ (char_group!(number) | '.')) => {
+                cnt.push(c);
+                State::Digit(cnt)
+            }
+            (State::Digit(mut cnt), c @ ('+' | '-')) if takes_exponent_sign(&cnt) => {
+                cnt.push(c);
+                State::Digit(cnt)
             }
-            (State::Digit(cnt) | State::DigitDot(cnt), char_group!(space)) => {
+            (State::Digit(cnt), char_group!(space)) => {
                 tokens.push(token!(TokenCont::Digit(cnt)));
                 State::Nothing
             }
 
             // Ident
-            (State::Nothing, c @ char_group!(ident_start)) => State::Ident(String::from(c)),
-            (State::Ident(mut cnt), c @ char_group!(ident)) => {
+            (State::Nothing, c) if syntax.is_ident_start(c) => State::Ident(String::from(c)),
+            (State::Ident(mut cnt), c) if syntax.is_ident_continue(c) => {
                 cnt.push(c);
                 State::Ident(cnt)
             }
@@ -158,12 +361,14 @@ pub fn tokenize(content: &str, file_name: &Path) -> Result<Vec<Token>, LyssCompE
                 State::Nothing
             }
             (State::Ident(cnt), '.') => State::PathDot(vec![cnt]),
-            (State::PathDot(secs), c @ char_group!(ident)) => State::Path(secs, String::from(c)),
+            (State::PathDot(secs), c) if syntax.is_ident_continue(c) => {
+                State::Path(secs, String::from(c))
+            }
             (State::Path(mut secs, end), '.') => {
                 secs.push(end);
                 State::PathDot(secs)
             }
-            (State::Path(secs, mut end), c @ char_group!(ident)) => {
+            (State::Path(secs, mut end), c) if syntax.is_ident_continue(c) => {
                 end.push(c);
                 State::Path(secs, end)
             }
@@ -175,23 +380,32 @@ pub fn tokenize(content: &str, file_name: &Path) -> Result<Vec<Token>, LyssCompE
 
             // Macro content
             (State::Nothing, '!') => State::MacroWaitAtom,
+            (State::MacroWaitAtom, '=') => State::Ident(String::from("!=")),
             (State::MacroWaitAtom, '(') => State::MacroWaitContent(String::new()),
-            (State::MacroWaitContent(mut name), char_group!(ident)) => {
+            (State::MacroWaitContent(mut name), c) if syntax.is_ident_continue(c) => {
                 name.push(c);
                 State::MacroWaitContent(name)
             }
-            (State::MacroWaitContent(name), ' ') => State::Macro {
+            (State::MacroWaitContent(name), ')') => {
+                tokens.push(token_incl!(TokenCont::Macro {
+                    name,
+                    content: String::new(),
+                    content_span: pos,
+                }));
+                State::Nothing
+            }
+            (State::MacroWaitContent(name), char_group!(space)) => State::Macro {
                 name,
                 content: String::new(),
                 parem_depth: 0,
-                start_line: line,
+                content_start: pos.advance(c),
             },
             (
                 State::Macro {
                     name,
                     mut content,
                     parem_depth,
-                    start_line,
+                    content_start,
                 },
                 '(',
             ) => {
@@ -199,7 +413,7 @@ pub fn tokenize(content: &str, file_name: &Path) -> Result<Vec<Token>, LyssCompE
                 State::Macro {
                     name,
                     content,
-                    start_line,
+                    content_start,
                     parem_depth: parem_depth + 1,
                 }
             }
@@ -208,14 +422,17 @@ pub fn tokenize(content: &str, file_name: &Path) -> Result<Vec<Token>, LyssCompE
                     name,
                     content,
                     parem_depth: 0,
-                    start_line,
+                    content_start,
                 },
                 ')',
             ) => {
-                tokens.push(token!(TokenCont::Macro {
+                tokens.push(token_incl!(TokenCont::Macro {
                     name,
                     content,
-                    line_span: start_line..line
+                    content_span: Span {
+                        end_byte: pos.start_byte,
+                        ..content_start
+                    },
                 }));
                 State::Nothing
             }
@@ -223,7 +440,7 @@ pub fn tokenize(content: &str, file_name: &Path) -> Result<Vec<Token>, LyssCompE
                 State::Macro {
                     name,
                     mut content,
-                    start_line,
+                    content_start,
                     parem_depth,
                 },
                 ')',
@@ -232,7 +449,7 @@ pub fn tokenize(content: &str, file_name: &Path) -> Result<Vec<Token>, LyssCompE
                 State::Macro {
                     name,
                     content,
-                    start_line,
+                    content_start,
                     parem_depth: parem_depth - 1,
                 }
             }
@@ -241,7 +458,7 @@ pub fn tokenize(content: &str, file_name: &Path) -> Result<Vec<Token>, LyssCompE
                     name,
                     mut content,
                     parem_depth,
-                    start_line,
+                    content_start,
                 },
                 c,
             ) => {
@@ -249,49 +466,169 @@ pub fn tokenize(content: &str, file_name: &Path) -> Result<Vec<Token>, LyssCompE
                 State::Macro {
                     name,
                     content,
-                    start_line,
+                    content_start,
                     parem_depth,
                 }
             }
 
-            // Params
-            (s, '(') => {
-                if let Some(tkn) = s.into_token(line, file_name)? {
-                    tokens.push(token!(tkn));
+            // Params and list brackets
+            (s, '(' | ')' | '[' | ']') => {
+                let before = Span {
+                    end_byte: pos.start_byte,
+                    ..start
+                };
+                match s.into_token(before, file_name) {
+                    Ok(Some(tkn)) => tokens.push(token!(tkn)),
+                    Ok(None) => {}
+                    Err(error) => {
+                        errors.push(error);
+                        tokens.push(token!(TokenCont::Error));
+                    }
                 }
-                tokens.push(token!(TokenCont::OParam));
-                State::Nothing
-            }
-            (s, ')') => {
-                if let Some(tkn) = s.into_token(line, file_name)? {
-                    tokens.push(token!(tkn));
-                }
-                tokens.push(token!(TokenCont::CParam));
+                tokens.push(token_here!(match c {
+                    '(' => TokenCont::OParam,
+                    ')' => TokenCont::CParam,
+                    '[' => TokenCont::OBracket,
+                    _ => TokenCont::CBracket,
+                }));
                 State::Nothing
             }
 
             // Single/Macro quotes
             (State::Nothing, '\'') => {
-                tokens.push(token!(TokenCont::SingleQuote));
+                tokens.push(token_here!(TokenCont::SingleQuote));
                 State::Nothing
             }
             (State::Nothing, '`') => {
-                tokens.push(token!(TokenCont::MacroQuote));
+                tokens.push(token_here!(TokenCont::MacroQuote));
                 State::Nothing
             }
 
             (s, char_group!(space)) => s,
 
-            (s, c) => todo!("{file_name:?}#{line}: {s:?} -> '{c}'"),
+            // The char and the token it interrupted become an error token
+            (s, c) => {
+                errors.push(LyssCompError::UnexpectedChar {
+                    span: Span {
+                        end_byte: pos.start_byte + c.len_utf8(),
+                        ..pos
+                    },
+                    found: c,
+                    tokenizer_state: Box::new(s),
+                });
+                tokens.push(token_incl!(TokenCont::Error));
+                State::Nothing
+            }
         };
-        if c == '\n' {
-            line += 1;
+        pos = pos.advance(c);
+    }
+
+    let rest = Span {
+        end_byte: pos.start_byte,
+        ..start
+    };
+    let error = match state {
+        State::Nothing | State::Comment => None,
+        State::String(_)
+        | State::StringSlash(..)
+        | State::StringHex(..)
+        | State::StringUnicode(..)
+        | State::StringContinue(_)
+        | State::RawStringOpen(_)
+        | State::RawString(..)
+        | State::RawStringQuote(..) => Some(LyssCompError::UnterminatedString { span: rest }),
+        State::Macro { .. } | State::MacroWaitContent(_) | State::MacroWaitAtom => {
+            Some(LyssCompError::UnexpectedEof {
+                span: rest,
+                expected: "`)` closing the macro invocation",
+            })
         }
+        state => match state.into_token(rest, file_name) {
+            Ok(tkn) => {
+                tokens.extend(tkn.map(|content| Token {
+                    span: rest,
+                    content,
+                }));
+                None
+            }
+            Err(error) => Some(error),
+        },
+    };
+    if let Some(error) = error {
+        errors.push(error);
+        tokens.push(Token {
+            span: rest,
+            content: TokenCont::Error,
+        });
+    }
+    (tokens, errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::testing::compile_errors;
+
+    #[test]
+    fn malformed_sources_are_errors() {
+        for (source, code) in [
+            ("(", "E0007"),
+            (")", "E0006"),
+            ("\"abc", "E0005"),
+            ("'(", "E0006"),
+            ("(f '( (g ) ", "E0007"),
+            ("(f \"\\q\" )", "E0008"),
+            ("(f # )", "E0007"),
+            ("(f @ )", "E0004"),
+            ("!(m (", "E0007"),
+            ("(f 1e )", "E0002"),
+            ("(f 0x )", "E0009"),
+            ("(f 1.2.3 )", "E0002"),
+        ] {
+            let codes: Vec<_> = compile_errors(source)
+                .iter()
+                .map(LyssCompError::code)
+                .collect();
+            assert_eq!(codes.first(), Some(&code), "{source:?} gave {codes:?}");
+        }
+    }
+
+    #[test]
+    fn every_error_is_reported_in_one_pass() {
+        let source = "(f @ \"\\q\" 1 )\n(g a;b \"\\x4\" )\n(h \"open";
+        let (tokens, errors) = tokenize(source, Path::new("test.ls"), 0);
+        let found: Vec<_> = errors
+            .iter()
+            .map(|error| (error.code(), error.span().map(|span| span.line)))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("E0004", Some(1)),
+                ("E0008", Some(1)),
+                ("E0004", Some(2)),
+                ("E0008", Some(2)),
+                ("E0005", Some(3)),
+            ]
+        );
+        let errors = tokens
+            .iter()
+            .filter(|token| matches!(token.content, TokenCont::Error))
+            .count();
+        assert_eq!(errors, 5);
+        assert!(matches!(tokens[4].content, TokenCont::Digit(_)));
+    }
+
+    #[test]
+    fn carriage_returns_are_whitespace() {
+        assert!(compile_errors("(f a\r\n  1 )\r\n(g )\r\n").is_empty());
     }
 
-    if let State::Nothing = state {
-        Ok(tokens)
-    } else {
-        panic!("Unfinished state")
+    #[test]
+    fn unexpected_characters_are_escaped() {
+        let errors = compile_errors("(f \u{7} )");
+        assert_eq!(errors[0].to_string(), "unexpected character `\\u{7}`");
     }
 }
@@ -0,0 +1,202 @@
+//! String literals, their escapes and the indentation stripping of multi-line strings
+//!
+//! A string whose opening quote ends its line is multi-line:
+//!
+//! ```text
+//! (Db.query "
+//!     SELECT *
+//!     FROM users
+//!     ")
+//! ```
+//!
+//! The newline after the opening quote and the line holding only the closing quote are dropped,
+//! and the indentation shared by every line that isn't blank is stripped, so the string above is
+//! `"SELECT *\nFROM users"`. Indentation is counted in chars, a tab counts as much as a space.
+
+use super::TokenCont;
+
+/// A line of a multi-line string
+#[derive(Debug, Clone, Copy)]
+struct Line {
+    /// Where the line starts in the content
+    start: usize,
+    /// How many bytes of indentation the line starts with
+    indent: usize,
+    /// Whether only indentation was read so far
+    blank: bool,
+}
+
+impl Line {
+    fn new(start: usize) -> Line {
+        Line {
+            start,
+            indent: 0,
+            blank: true,
+        }
+    }
+}
+
+/// The content of a string literal being read
+#[derive(Debug, Default)]
+pub struct StrLit {
+    content: String,
+    /// The lines of a multi-line string, `None` for strings on a single line
+    lines: Option<Vec<Line>>,
+    /// Set when an escape in it was invalid
+    failed: bool,
+}
+
+impl StrLit {
+    /// Adds a char as written in the source
+    pub fn push(&mut self, c: char) {
+        let Some(lines) = &mut self.lines else {
+            if c == '\n' && self.content.is_empty() {
+                self.lines = Some(vec![Line::new(0)]);
+            } else {
+                self.content.push(c);
+            }
+            return;
+        };
+        self.content.push(c);
+        if c == '\n' {
+            lines.push(Line::new(self.content.len()));
+        } else if let Some(line) = lines.last_mut() {
+            match c {
+                ' ' | '\t' if line.blank => line.indent += 1,
+                _ => line.blank = false,
+            }
+        }
+    }
+    /// Adds a char made by an escape, it's never indentation
+    pub fn push_escaped(&mut self, c: char) {
+        if let Some(line) = self.lines.as_mut().and_then(|lines| lines.last_mut()) {
+            line.blank = false;
+        }
+        self.content.push(c);
+    }
+    /// Marks the literal as invalid, it's still read up to the closing quote
+    pub fn fail(&mut self) {
+        self.failed = true;
+    }
+    /// The string token, or an error token if an escape in it was invalid
+    #[must_use]
+    pub fn into_token(self) -> TokenCont {
+        if self.failed {
+            TokenCont::Error
+        } else {
+            TokenCont::String(self.finish())
+        }
+    }
+    /// The value of the string, with the indentation of multi-line strings stripped
+    #[must_use]
+    pub fn finish(self) -> String {
+        let Some(mut lines) = self.lines else {
+            return self.content;
+        };
+        // The closing quote's line, the newline before it is dropped with it
+        let end = if lines.len() > 1 && lines.last().is_some_and(|line| line.blank) {
+            lines
+                .pop()
+                .map_or(self.content.len(), |closing| closing.start - 1)
+        } else {
+            self.content.len()
+        };
+        let shared = lines
+            .iter()
+            .filter(|line| !line.blank)
+            .map(|line| line.indent)
+            .min()
+            .unwrap_or(0);
+        let mut out = String::with_capacity(self.content.len());
+        for (i, line) in lines.iter().enumerate() {
+            let end = lines.get(i + 1).map_or(end, |next| next.start - 1);
+            if i > 0 {
+                out.push('\n');
+            }
+            if !line.blank {
+                out.push_str(&self.content[line.start + shared..end]);
+            }
+        }
+        out
+    }
+}
+
+/// The char of a single char escape like `\n`
+#[must_use]
+pub fn simple_escape(c: char) -> Option<char> {
+    Some(match c {
+        'n' => '\n',
+        't' => '\t',
+        'r' => '\r',
+        '0' => '\0',
+        '\\' => '\\',
+        '"' => '"',
+        '\'' => '\'',
+        _ => return None,
+    })
+}
+
+/// The char of a `\x..` escape from its two hex digits
+pub fn hex_escape(digits: &str) -> Result<char, &'static str> {
+    match u8::from_str_radix(digits, 16) {
+        Ok(byte) if byte.is_ascii() => Ok(char::from(byte)),
+        Ok(_) => Err("`\\x` escapes only go up to `\\x7f`, use `\\u{..}` for other chars"),
+        Err(_) => Err("`\\x` must be followed by two hex digits"),
+    }
+}
+
+/// The char of a `\u{..}` escape from the digits between the braces
+pub fn unicode_escape(digits: &str) -> Result<char, &'static str> {
+    if digits.is_empty() || digits.len() > 6 {
+        return Err("`\\u{..}` must hold 1 to 6 hex digits");
+    }
+    u32::from_str_radix(digits, 16)
+        .ok()
+        .and_then(char::from_u32)
+        .ok_or("it's not a valid unicode char")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Value;
+    use crate::parser::{ArgumentCont, ExprCont};
+    use crate::testing::parse;
+
+    /// The value of the string literal `literal`
+    #[track_caller]
+    fn string(literal: &str) -> String {
+        let exprs = parse(&format!("(f {literal} )"));
+        if let ExprCont::Atom(atom) = &exprs[0].cont
+            && let ArgumentCont::Value(Value::Str(string)) = &atom.arguments[0].cont
+        {
+            return string.clone();
+        }
+        panic!("{literal} isn't a string");
+    }
+
+    #[test]
+    fn the_documented_example_strips_it_all() {
+        let literal = "\"\n    SELECT *\n    FROM users\n    \"";
+        assert_eq!(string(literal), "SELECT *\nFROM users");
+    }
+
+    #[test]
+    fn multi_line_strings_keep_inner_blank_lines_and_deeper_indents() {
+        let literal = "\"\n\tif\n\n\t\tthen\n\t\"";
+        assert_eq!(string(literal), "if\n\n\tthen");
+    }
+
+    #[test]
+    fn a_closing_quote_after_text_keeps_the_last_line() {
+        let literal = "\"\n  one\n  two\"";
+        assert_eq!(string(literal), "one\ntwo");
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(string(r#""a\tb\n\\\"\'\0""#), "a\tb\n\\\"'\0");
+        assert_eq!(string(r#""\x41\u{e9}\u{1F600}""#), "Aé😀");
+        assert_eq!(string("\"one \\\n     two\""), "one two");
+        assert_eq!(string("r#\"raw \\n \"quoted\"\"#"), "raw \\n \"quoted\"");
+    }
+}
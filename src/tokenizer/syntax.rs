@@ -0,0 +1,130 @@
+//! Which chars identifiers are made of
+//!
+//! ```text
+//! ident    = start continue*
+//! path     = ident ("." continue+)+
+//! start    = XID_Start | "$" | "_" | operator
+//! continue = XID_Continue | "$" | "_" | "!" | operator
+//! operator = any of Syntax::operator_chars, by default = < > - + / * % ? & | ^ ~ :
+//! ```
+//!
+//! `XID_Start` and `XID_Continue` are the Unicode classes rust identifiers are made of, so
+//! `is-empty?`, `%`, `größe` and `名前` are all identifiers. Keywords like `else` are plain
+//! identifiers that functions look for, only `true` and `false` arguments are read as booleans.
+//! A `-` followed by a digit starts a number instead, and `!=` is an identifier even though `!`
+//! starts macro invocations.
+
+/// The operator chars allowed in identifiers by default
+pub const OPERATOR_CHARS: &str = "=<>-+/*%?&|^~:";
+
+/// Chars that already mean something else and can't be operators
+const RESERVED_CHARS: &str = "()[]'`\"#.!";
+
+#[derive(Debug, Clone)]
+pub struct Syntax {
+    operator_chars: Vec<char>,
+}
+
+impl Default for Syntax {
+    fn default() -> Self {
+        Syntax {
+            operator_chars: OPERATOR_CHARS.chars().collect(),
+        }
+    }
+}
+
+impl Syntax {
+    /// A syntax whose identifiers may use `operator_chars`, fails with the first char that can't
+    /// be an operator, like whitespace, digits or `(`
+    pub fn with_operator_chars(operator_chars: &str) -> Result<Syntax, char> {
+        if let Some(reserved) = operator_chars
+            .chars()
+            .find(|c| c.is_whitespace() || c.is_ascii_digit() || RESERVED_CHARS.contains(*c))
+        {
+            return Err(reserved);
+        }
+        Ok(Syntax {
+            operator_chars: operator_chars.chars().collect(),
+        })
+    }
+    #[must_use]
+    pub fn operator_chars(&self) -> &[char] {
+        &self.operator_chars
+    }
+    /// Chars allowed besides the Unicode ones, `$` and `_` are used by variables and holes
+    fn is_extra(&self, c: char) -> bool {
+        matches!(c, '$' | '_') || self.operator_chars.contains(&c)
+    }
+    #[must_use]
+    pub fn is_ident_start(&self, c: char) -> bool {
+        unicode_ident::is_xid_start(c) || self.is_extra(c)
+    }
+    #[must_use]
+    pub fn is_ident_continue(&self, c: char) -> bool {
+        unicode_ident::is_xid_continue(c) || c == '!' || self.is_extra(c)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::span::Span;
+    use crate::tokenizer::{TokenCont, tokenize_with};
+
+    /// The tokens of `source` as they display
+    fn tokens(source: &str, syntax: &Syntax) -> Vec<String> {
+        let (tokens, errors) = tokenize_with(source, Path::new("test.ls"), Span::default(), syntax);
+        assert!(errors.is_empty(), "{errors:?}");
+        tokens
+            .iter()
+            .map(|token| match &token.content {
+                TokenCont::Ident(name) => format!("ident {name}"),
+                TokenCont::Path(path) => format!("path {}", path.join(".")),
+                TokenCont::Digit(digits) => format!("digit {digits}"),
+                content => content.to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn unicode_and_operator_identifiers() {
+        assert_eq!(
+            tokens(
+                "is-empty? größe 名前 != <= a.b-c $.x -1 - ",
+                &Syntax::default()
+            ),
+            [
+                "ident is-empty?",
+                "ident größe",
+                "ident 名前",
+                "ident !=",
+                "ident <=",
+                "path a.b-c",
+                "path $.x",
+                "digit -1",
+                "ident -",
+            ]
+        );
+    }
+
+    #[test]
+    fn custom_operator_chars() {
+        let syntax = Syntax::with_operator_chars("@+").unwrap();
+        assert_eq!(tokens("@ a@b ", &syntax), ["ident @", "ident a@b"]);
+        let (_, errors) = tokenize_with("<", Path::new("test.ls"), Span::default(), &syntax);
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn reserved_chars_cant_be_operators() {
+        for reserved in ["(", "#", ".", "!", "1", " ", "\""] {
+            let chars = format!("+{reserved}");
+            assert_eq!(
+                Syntax::with_operator_chars(&chars).unwrap_err().to_string(),
+                reserved
+            );
+        }
+    }
+}